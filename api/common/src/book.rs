@@ -1,28 +1,97 @@
 use core::{marker::PhantomData, str::FromStr};
-use std::sync::Arc;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use ipiis_common::{
+    balance::Replica,
+    error::{ErrorKind, ServerError},
+    record::{self, AddressRecord},
+};
+use ipis::{
+    bytecheck::CheckBytes,
+    core::{
+        account::{Account, AccountRef, Verifier},
+        anyhow::{anyhow, bail, Result},
+        value::hash::Hash,
+    },
+    env::infer,
+    rkyv::{
+        de::deserializers::SharedDeserializeMap, ser::serializers::AllocSerializer,
+        validation::validators::DefaultValidator, AlignedVec, Archive, Deserialize, Serialize,
+    },
+    tokio::sync::broadcast,
+};
 
-use ipis::core::{
-    account::{Account, AccountRef},
-    anyhow::{bail, Result},
-    value::hash::Hash,
+use crate::{
+    primary::PrimaryHealth,
+    replication::{self, DirectoryChange, SignedChange},
 };
 
+/// The number of the changes which are kept for the slow watchers.
+pub const WATCH_CAPACITY: usize = 1024;
+
 #[derive(Clone, Debug)]
 pub struct AddressBook<Address> {
     pub account_me: Arc<Account>,
+    pub primary_health: Arc<PrimaryHealth>,
     table: sled::Db,
+    /// The latest change of each entry, which is used to replicate the directory.
+    ///
+    /// Each change is stored along with its sequence number, which counts the changes
+    /// in the order of being applied to this book.
+    changes: sled::Tree,
+    /// The sequence number of the last applied change.
+    seq: Arc<Mutex<u64>>,
+    /// The latest address record of each entry, which is replicated along with the changes.
+    ///
+    /// Each record is stored along with its sequence number, sharing the one of the changes.
+    records: sled::Tree,
+    /// The accounts which are detected to be dead, e.g. by the membership protocol.
+    dead: Arc<Mutex<HashSet<AccountRef>>>,
+    /// Notifies the watchers of each applied change.
+    watchers: broadcast::Sender<DirectoryChange>,
     _address: PhantomData<Address>,
 }
 
 impl<Address> AddressBook<Address> {
+    /// Open the book in the data directory of `ipiis_data_dir`, so that it persists across restarts.
+    ///
+    /// If the directory is not configured, the book is a temporary one which is removed on drop.
     pub fn new<P>(account_me: Account, book_path: P) -> Result<Self>
     where
         P: AsRef<::std::path::Path>,
     {
+        let data_dir: Option<PathBuf> = infer("ipiis_data_dir").ok();
+        let table = match data_dir {
+            Some(data_dir) => sled::open(data_dir.join(book_path))?,
+            None => sled::Config::new().temporary(true).open()?,
+        };
+
+        // resume the change log
+        let changes = table.open_tree("changes")?;
+        let records = table.open_tree("records")?;
+        let seq = changes
+            .iter()
+            .chain(records.iter())
+            .values()
+            .map(|value| Ok(decode_seq(&value?)?))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .max()
+            .unwrap_or_default();
+
         Ok(Self {
             account_me: account_me.into(),
-            // TODO: allow to store in specific directory
-            table: sled::open(::tempfile::tempdir()?.path().join(book_path))?,
+            primary_health: Default::default(),
+            table,
+            changes,
+            seq: Arc::new(Mutex::new(seq)),
+            records,
+            dead: Default::default(),
+            watchers: broadcast::channel(WATCH_CAPACITY).0,
             _address: Default::default(),
         })
     }
@@ -46,12 +115,38 @@ impl<Address> AddressBook<Address> {
         }
     }
 
+    /// Get the most preferred primary, which is reachable if possible.
     pub fn get_primary(&self, kind: Option<&Hash>) -> Result<Option<AccountRef>> {
+        self.get_primaries(kind)
+            .map(|primaries| primaries.into_iter().next())
+    }
+
+    /// Get the primaries in order of preference, with the unreachable ones at the end.
+    pub fn get_primaries(&self, kind: Option<&Hash>) -> Result<Vec<AccountRef>> {
+        let mut primaries: Vec<_> = self
+            .get_replicas(kind)?
+            .into_iter()
+            .map(|(account, _)| account)
+            .collect();
+        self.primary_health.sort(&mut primaries);
+        Ok(primaries)
+    }
+
+    /// Get the accounts serving the kind with their weights, in order of registration.
+    pub fn get_replicas(&self, kind: Option<&Hash>) -> Result<Vec<Replica>> {
         let key = self.to_key_canonical(kind, None);
 
         match self.table.get(key)? {
-            Some(address) => Ok(Some(String::from_utf8(address.to_vec())?.parse()?)),
-            None => Ok(None),
+            Some(accounts) => String::from_utf8(accounts.to_vec())?
+                .split(',')
+                .map(|replica| -> Result<Replica> {
+                    match replica.split_once(':') {
+                        Some((account, weight)) => Ok((account.parse()?, weight.parse()?)),
+                        None => Ok((replica.parse()?, 1)),
+                    }
+                })
+                .collect(),
+            None => Ok(vec![]),
         }
     }
 
@@ -59,21 +154,168 @@ impl<Address> AddressBook<Address> {
     where
         Address: ToString,
     {
-        let key = self.to_key_canonical(kind, Some(target));
+        let change = (
+            kind.copied(),
+            Some(*target),
+            address.to_string(),
+            replication::now(),
+        );
+        self.record(self.sign_change(change)?, false).map(|_| ())
+    }
+
+    pub fn is_dead(&self, account: &AccountRef) -> bool {
+        self.dead.lock().unwrap().contains(account)
+    }
+
+    /// Mark the account as dead, so that it is not contacted until it is alive again.
+    pub fn mark_dead(&self, account: &AccountRef) {
+        self.dead.lock().unwrap().insert(*account);
+    }
 
-        self.table
-            .insert(key, address.to_string().into_bytes())
-            .map(|_| ())
-            .map_err(Into::into)
+    pub fn mark_alive(&self, account: &AccountRef) {
+        self.dead.lock().unwrap().remove(account);
     }
 
     pub fn set_primary(&self, kind: Option<&Hash>, account: &AccountRef) -> Result<()> {
-        let key = self.to_key_canonical(kind, None);
+        self.set_primaries(kind, ::core::slice::from_ref(account))
+    }
+
+    /// Replace the primaries with the ordered ones.
+    pub fn set_primaries(&self, kind: Option<&Hash>, accounts: &[AccountRef]) -> Result<()> {
+        let replicas: Vec<_> = accounts.iter().map(|account| (*account, 1)).collect();
+        self.set_replicas(kind, &replicas)
+    }
+
+    /// Add, reweigh or withdraw (with a zero weight) one of the accounts serving the kind.
+    pub fn set_replica(
+        &self,
+        kind: Option<&Hash>,
+        account: &AccountRef,
+        weight: u32,
+    ) -> Result<()> {
+        let mut replicas = self.get_replicas(kind)?;
+        match replicas.iter_mut().find(|(known, _)| known == account) {
+            Some(replica) => replica.1 = weight,
+            None => replicas.push((*account, weight)),
+        }
+        replicas.retain(|(_, weight)| *weight > 0);
+
+        self.set_replicas(kind, &replicas)
+    }
+
+    /// Replace the accounts serving the kind, which are the primaries of it.
+    pub fn set_replicas(&self, kind: Option<&Hash>, replicas: &[Replica]) -> Result<()> {
+        let replicas = replicas
+            .iter()
+            .map(|(account, weight)| match weight {
+                1 => account.to_string(),
+                weight => format!("{account}:{weight}"),
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let change = (kind.copied(), None, replicas, replication::now());
+        self.record(self.sign_change(change)?, false).map(|_| ())
+    }
+
+    /// Get the latest change of the entry, which is `None` for the primaries.
+    pub fn get_change(
+        &self,
+        kind: Option<&Hash>,
+        account: Option<&AccountRef>,
+    ) -> Result<Option<SignedChange>> {
+        let key = self.to_key_canonical(kind, account);
+
+        match self.changes.get(key)? {
+            Some(value) => Ok(Some(decode_entry(&value)?.1)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the changes applied after the sequence number, from the oldest one,
+    /// along with the sequence number of the last applied change.
+    pub fn changes_since(&self, since: u64) -> Result<(Vec<SignedChange>, u64)> {
+        // read the sequence number first, so that no change is missed on the next call
+        let seq = *self.seq.lock().unwrap();
+
+        let mut changes = self
+            .changes
+            .iter()
+            .values()
+            .map(|value| decode_entry(&value?))
+            .filter(|change| match change {
+                Ok((change_seq, _)) => *change_seq > since,
+                Err(_) => true,
+            })
+            .collect::<Result<Vec<_>>>()?;
+        changes.sort_by_key(|(change_seq, _)| *change_seq);
+
+        Ok((changes.into_iter().map(|(_, change)| change).collect(), seq))
+    }
+
+    /// Merge the changes of the other primaries, returning the number of the applied ones.
+    ///
+    /// Each change should be signed by one of the primaries,
+    /// and is applied only if it is newer than the current one (last-writer-wins).
+    pub fn merge(&self, changes: Vec<SignedChange>) -> Result<usize> {
+        let mut applied = 0;
+        for change in changes {
+            replication::verify_change(self, &change)?;
+            if self.record(change, true)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Apply the change which is pushed by the primary in a signed session,
+    /// if it is newer than the current one.
+    pub fn apply(&self, change: DirectoryChange) -> Result<bool> {
+        self.record(self.sign_change(change)?, true)
+    }
+
+    /// Subscribe the changes which are applied after now.
+    pub fn subscribe(&self) -> broadcast::Receiver<DirectoryChange> {
+        self.watchers.subscribe()
+    }
+
+    /// Persist all pending changes of the book.
+    pub fn flush(&self) -> Result<()> {
+        self.table.flush().map(|_| ()).map_err(Into::into)
+    }
+
+    fn sign_change(&self, change: DirectoryChange) -> Result<SignedChange> {
+        replication::sign_change(&self.account_me, change)
+    }
+
+    fn record(&self, change: SignedChange, only_newer: bool) -> Result<bool> {
+        let data = &change.data.data;
+        let key = self.to_key_canonical(data.0.as_ref(), data.1.as_ref());
+
+        let mut seq = self.seq.lock().unwrap();
+        if only_newer {
+            if let Some(current) = self.changes.get(&key)? {
+                let (_, current) = decode_entry::<SignedChange>(&current)?;
+                if !replication::is_newer(data, &current.data.data) {
+                    return Ok(false);
+                }
+            }
+        }
+
+        // store data
+        if data.2.is_empty() {
+            self.table.remove(&key)?;
+        } else {
+            self.table.insert(&key, data.2.as_bytes())?;
+        }
+
+        // store the change with the next sequence number
+        *seq += 1;
+        self.changes.insert(&key, encode_entry(*seq, &change)?)?;
 
-        self.table
-            .insert(key, account.to_string().into_bytes())
-            .map(|_| ())
-            .map_err(Into::into)
+        // notify the watchers, if any
+        let _ = self.watchers.send(data.clone());
+        Ok(true)
     }
 
     fn to_key_canonical(&self, kind: Option<&Hash>, account: Option<&AccountRef>) -> Vec<u8> {
@@ -88,3 +330,149 @@ impl<Address> AddressBook<Address> {
         [&[flag], kind, account].concat()
     }
 }
+
+impl<Address> AddressBook<Address>
+where
+    Address: ToString,
+    AddressRecord<Address>: Archive + Serialize<AllocSerializer<256>> + Verifier,
+    <AddressRecord<Address> as Archive>::Archived: for<'a> CheckBytes<DefaultValidator<'a>>
+        + Deserialize<AddressRecord<Address>, SharedDeserializeMap>,
+{
+    /// Get the address record of the target, which is signed by the target itself.
+    ///
+    /// Note that it may be expired, so it should be verified before use.
+    pub fn get_record(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<Option<AddressRecord<Address>>> {
+        let key = self.to_key_canonical(kind, Some(target));
+
+        match self.records.get(key)? {
+            Some(value) => Ok(Some(decode_entry(&value)?.1)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the address record, which should be verified before.
+    ///
+    /// The record which is older than the stored one is rejected, so that it cannot be replayed.
+    pub fn set_record(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        record: AddressRecord<Address>,
+    ) -> Result<()> {
+        self.record_address(kind, target, record, false).map(|_| ())
+    }
+
+    /// Get the live address records stored after the sequence number, from the oldest one,
+    /// along with the sequence number of the last applied change.
+    pub fn records_since(&self, since: u64) -> Result<(Vec<AddressRecord<Address>>, u64)> {
+        // read the sequence number first, so that no record is missed on the next call
+        let seq = *self.seq.lock().unwrap();
+
+        let now = record::now();
+        let mut records = self
+            .records
+            .iter()
+            .values()
+            .map(|value| decode_entry::<AddressRecord<Address>>(&value?))
+            .filter(|record| match record {
+                Ok((record_seq, record)) => *record_seq > since && record.data.data.3 > now,
+                Err(_) => true,
+            })
+            .collect::<Result<Vec<_>>>()?;
+        records.sort_by_key(|(record_seq, _)| *record_seq);
+
+        Ok((records.into_iter().map(|(_, record)| record).collect(), seq))
+    }
+
+    /// Merge the address records of the other primaries, returning the number of the applied ones.
+    ///
+    /// Each record should be signed by the account it describes,
+    /// and is applied only if it is newer than the current one.
+    pub fn merge_records(&self, records: Vec<AddressRecord<Address>>) -> Result<usize> {
+        let mut applied = 0;
+        for record in records {
+            let kind = record.data.data.0;
+            let target = record.guarantee.account;
+
+            record::verify_record(&record, kind.as_ref(), &target)?;
+            if self.record_address(kind.as_ref(), &target, record, true)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    fn record_address(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        record: AddressRecord<Address>,
+        only_newer: bool,
+    ) -> Result<bool> {
+        let key = self.to_key_canonical(kind, Some(target));
+        let timestamp = record.data.data.2;
+
+        {
+            let mut seq = self.seq.lock().unwrap();
+            if let Some(current) = self.records.get(&key)? {
+                let (_, current) = decode_entry::<AddressRecord<Address>>(&current)?;
+                let current = current.data.data.2;
+                if timestamp < current {
+                    if only_newer {
+                        return Ok(false);
+                    }
+                    bail!(ServerError::new(
+                        ErrorKind::Unauthorized,
+                        format!("the address record of {target} is older than the stored one"),
+                    ));
+                }
+                if timestamp == current && only_newer {
+                    return Ok(false);
+                }
+            }
+
+            // store the record with the next sequence number
+            *seq += 1;
+            self.records.insert(&key, encode_entry(*seq, &record)?)?;
+        }
+
+        // store data
+        self.set(kind, target, &record.data.data.1)?;
+        Ok(true)
+    }
+}
+
+fn encode_entry<T>(seq: u64, value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize<AllocSerializer<256>>,
+{
+    let bytes = ::ipis::rkyv::to_bytes::<_, 256>(value).map_err(|_| anyhow!("malformed entry"))?;
+    Ok([&seq.to_be_bytes()[..], &bytes].concat())
+}
+
+fn decode_seq(value: &[u8]) -> Result<u64> {
+    match value.get(..8) {
+        Some(seq) => Ok(u64::from_be_bytes(seq.try_into()?)),
+        None => bail!("malformed entry"),
+    }
+}
+
+fn decode_entry<T>(value: &[u8]) -> Result<(u64, T)>
+where
+    T: Archive,
+    <T as Archive>::Archived:
+        for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, SharedDeserializeMap>,
+{
+    let seq = decode_seq(value)?;
+
+    // align the archived entry
+    let mut bytes = AlignedVec::new();
+    bytes.extend_from_slice(&value[8..]);
+
+    let entry = ::ipis::rkyv::from_bytes(&bytes).map_err(|_| anyhow!("malformed entry"))?;
+    Ok((seq, entry))
+}
@@ -0,0 +1,188 @@
+use core::{str::FromStr, time::Duration};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use ipiis_common::record::now;
+use ipis::{
+    core::{
+        account::{Account, AccountRef, GuaranteeSigned, Verifier},
+        anyhow::{bail, Result},
+        metadata::Metadata,
+        value::hash::Hash,
+    },
+    log::{debug, warn},
+    stream::DynStream,
+    tokio::{self, net::UdpSocket},
+};
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{book::AddressBook, shutdown::Shutdown};
+
+/// The default multicast group of the beacons.
+pub const DEFAULT_MULTICAST_GROUP: SocketAddrV4 =
+    SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 98), 9802);
+
+/// The default interval to announce the beacon.
+pub const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The maximum size of a beacon, which fits in a UDP datagram.
+pub const MAX_BEACON_SIZE: usize = 1 << 14;
+
+/// The address, the kinds and the timestamp of an account, which is signed by itself.
+pub type Beacon = GuaranteeSigned<(String, Vec<Hash>, u64)>;
+
+/// Announces this node and populates the address book with the beacons of the others,
+/// which are exchanged on a multicast group in the local network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Discovery {
+    group: SocketAddrV4,
+    interface: Ipv4Addr,
+    interval: Duration,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new(DEFAULT_MULTICAST_GROUP)
+    }
+}
+
+impl Discovery {
+    pub fn new(group: SocketAddrV4) -> Self {
+        Self {
+            group,
+            interface: Ipv4Addr::UNSPECIFIED,
+            interval: DEFAULT_ANNOUNCE_INTERVAL,
+        }
+    }
+
+    /// Select the network interface by its address, e.g. `127.0.0.1` for the loopback.
+    pub fn with_interface(mut self, interface: Ipv4Addr) -> Self {
+        self.interface = interface;
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Announce the address of this node (if any) and accept the beacons, until the shutdown.
+    pub async fn run<Address>(
+        &self,
+        book: &AddressBook<Address>,
+        address: Option<&Address>,
+        kinds: &[Hash],
+        shutdown: &Shutdown,
+    ) -> Result<()>
+    where
+        Address: FromStr + ToString,
+        <Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+    {
+        let socket = self.bind()?;
+        let target = SocketAddr::V4(self.group);
+
+        let mut buf = vec![0; MAX_BEACON_SIZE];
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if let Some(address) = address {
+                        let beacon = encode_beacon(&book.account_me, address, kinds).await?;
+                        if let Err(e) = socket.send_to(&beacon, target).await {
+                            debug!("failed to announce the beacon: {e}");
+                        }
+                    }
+                }
+                received = socket.recv_from(&mut buf) => {
+                    // a failed datagram should not stop the discovery, e.g. ICMP unreachable
+                    let len = match received {
+                        Ok((len, _)) => len,
+                        Err(e) => {
+                            warn!("failed to receive a beacon: {e}");
+                            continue;
+                        }
+                    };
+                    if let Err(e) = accept_beacon(book, &buf[..len], self.interval * 3).await {
+                        debug!("ignored a beacon: {e}");
+                    }
+                }
+                _ = shutdown.wait() => break Ok(()),
+            }
+        }
+    }
+
+    fn bind(&self) -> Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+
+        // share the port with the other nodes in the same host
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+
+        let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, self.group.port());
+        socket.bind(&SocketAddr::V4(addr).into())?;
+
+        // join the group
+        socket.set_multicast_if_v4(&self.interface)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.join_multicast_v4(self.group.ip(), &self.interface)?;
+
+        socket.set_nonblocking(true)?;
+        UdpSocket::from_std(socket.into()).map_err(Into::into)
+    }
+}
+
+/// Sign and encode the beacon of the account.
+pub async fn encode_beacon<Address>(
+    account: &Account,
+    address: &Address,
+    kinds: &[Hash],
+) -> Result<Vec<u8>>
+where
+    Address: ToString,
+{
+    let data = (address.to_string(), kinds.to_vec(), now());
+    let beacon: Beacon = Metadata::builder().build(account, account.account_ref(), data)?;
+
+    let mut buf = Vec::new();
+    DynStream::Owned(beacon).copy_to(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Verify the beacon and store its address in the book, returning the announced account.
+///
+/// The beacons of this node are ignored, returning `None`.
+pub async fn accept_beacon<Address>(
+    book: &AddressBook<Address>,
+    data: &[u8],
+    max_age: Duration,
+) -> Result<Option<AccountRef>>
+where
+    Address: FromStr + ToString,
+    <Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+{
+    // decode data
+    let beacon: Beacon = DynStream::recv(data).await?.into_owned().await?;
+
+    // verify data
+    beacon.verify(None)?;
+    beacon.ensure_self_signed()?;
+
+    let account = beacon.guarantee.account;
+    if account == book.account_me.account_ref() {
+        return Ok(None);
+    }
+
+    let (address, kinds, timestamp) = &beacon.data.data;
+    let max_age = max_age.as_millis() as u64;
+    if now().abs_diff(*timestamp) > max_age {
+        bail!("stale beacon of {account}");
+    }
+
+    // store data
+    let address: Address = address.parse()?;
+    book.set(None, &account, &address)?;
+    for kind in kinds {
+        book.set(Some(kind), &account, &address)?;
+    }
+    Ok(Some(account))
+}
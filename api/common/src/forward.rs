@@ -0,0 +1,57 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ipis::core::account::AccountRef;
+
+/// The usage of the forwarded streams of a requester, which is accounted at each hop.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ForwardUsage {
+    pub streams: u64,
+    /// The bytes sent from the requester to the target.
+    pub sent: u64,
+    /// The bytes received from the target to the requester.
+    pub received: u64,
+}
+
+/// Accounts the forwarded streams per requester, i.e. the previous hop.
+#[derive(Debug, Default)]
+pub struct ForwardAccounting {
+    usages: Mutex<HashMap<AccountRef, ForwardUsage>>,
+}
+
+impl ForwardAccounting {
+    /// Count a new stream of the requester.
+    pub fn begin(&self, requester: &AccountRef) {
+        self.usages
+            .lock()
+            .unwrap()
+            .entry(*requester)
+            .or_default()
+            .streams += 1;
+    }
+
+    /// Add the bytes of a finished stream of the requester.
+    pub fn record(&self, requester: &AccountRef, sent: u64, received: u64) {
+        let mut usages = self.usages.lock().unwrap();
+        let usage = usages.entry(*requester).or_default();
+        usage.sent += sent;
+        usage.received += received;
+    }
+
+    pub fn usage(&self, requester: &AccountRef) -> ForwardUsage {
+        self.usages
+            .lock()
+            .unwrap()
+            .get(requester)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn usages(&self) -> Vec<(AccountRef, ForwardUsage)> {
+        self.usages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(requester, usage)| (*requester, *usage))
+            .collect()
+    }
+}
@@ -1,3 +1,14 @@
 pub mod book;
+pub mod discovery;
 pub mod flag;
+pub mod forward;
+pub mod limits;
+pub mod policy;
+pub mod primary;
+pub mod rate_limit;
+pub mod register;
+pub mod relay;
+pub mod rendezvous;
+pub mod replication;
 pub mod server;
+pub mod shutdown;
@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::{Context, Interceptor, Next},
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    tokio::sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+/// The admission limits of a server, where `None` means unlimited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of concurrent connections.
+    pub max_connections: Option<usize>,
+    /// The maximum number of concurrent streams in a single connection.
+    pub max_streams_per_connection: Option<usize>,
+    /// The maximum number of concurrent requests signed by a single account.
+    pub max_streams_per_account: Option<usize>,
+    /// The maximum number of concurrent duplex sessions of a single account,
+    /// which are long-lived and so are not counted as requests.
+    pub max_sessions_per_account: Option<usize>,
+    /// The maximum number of concurrent requests in the whole server.
+    pub max_in_flight: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_connections: Some(1_024),
+            max_streams_per_connection: Some(128),
+            max_streams_per_account: Some(64),
+            max_sessions_per_account: Some(16),
+            max_in_flight: Some(4_096),
+        }
+    }
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self {
+            max_connections: None,
+            max_streams_per_connection: None,
+            max_streams_per_account: None,
+            max_sessions_per_account: None,
+            max_in_flight: None,
+        }
+    }
+}
+
+/// A counting limiter which rejects the excess work instead of queueing it.
+#[derive(Clone, Debug, Default)]
+pub struct Limiter {
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl Limiter {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            semaphore: limit.map(Semaphore::new).map(Arc::new),
+        }
+    }
+
+    /// Acquire a permit, or fail with an "overloaded" error naming the exhausted resource.
+    pub fn try_acquire(&self, resource: &str) -> Result<Option<OwnedSemaphorePermit>> {
+        match &self.semaphore {
+            Some(semaphore) => match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => Ok(Some(permit)),
+                Err(_) => bail!(ServerError::new(
+                    ErrorKind::Overloaded,
+                    format!("too many {resource}"),
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// Limits the concurrent requests signed by each account.
+///
+/// The duplex sessions are counted apart from the requests, so that the long-lived sessions
+/// cannot starve the requests of the same account.
+/// Unsigned (raw) requests are not counted, as their requester is unknown.
+#[derive(Debug, Default)]
+pub struct AccountLimiter {
+    max_requests: Option<usize>,
+    max_sessions: Option<usize>,
+    requests: Arc<Mutex<HashMap<AccountRef, usize>>>,
+    sessions: Arc<Mutex<HashMap<AccountRef, usize>>>,
+}
+
+impl AccountLimiter {
+    pub fn new(max_requests: Option<usize>, max_sessions: Option<usize>) -> Self {
+        Self {
+            max_requests,
+            max_sessions,
+            ..Default::default()
+        }
+    }
+
+    fn try_acquire(&self, account: AccountRef, session: bool) -> Result<Option<AccountPermit>> {
+        let (limit, counts, resource) = if session {
+            (self.max_sessions, &self.sessions, "sessions")
+        } else {
+            (self.max_requests, &self.requests, "requests")
+        };
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return Ok(None),
+        };
+
+        let mut guard = counts.lock().unwrap();
+        let count = guard.entry(account).or_default();
+        if *count >= limit {
+            bail!(ServerError::new(
+                ErrorKind::Overloaded,
+                format!("too many {resource} of the account: {account}"),
+            ));
+        }
+        *count += 1;
+
+        Ok(Some(AccountPermit {
+            account,
+            counts: counts.clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Interceptor for AccountLimiter {
+    async fn intercept(&self, ctx: &Context, next: Next<'_>) -> Result<()> {
+        match ctx.peer {
+            Some(account) => {
+                let _permit = self.try_acquire(account, ctx.session)?;
+                next.run(ctx).await
+            }
+            None => next.run(ctx).await,
+        }
+    }
+}
+
+struct AccountPermit {
+    account: AccountRef,
+    counts: Arc<Mutex<HashMap<AccountRef, usize>>>,
+}
+
+impl Drop for AccountPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.account) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.account);
+            }
+        }
+    }
+}
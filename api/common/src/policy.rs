@@ -0,0 +1,92 @@
+use std::collections::{HashMap, HashSet};
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::core::{
+    account::AccountRef,
+    anyhow::{bail, Result},
+    value::hash::Hash,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DirectoryOp {
+    SetAccountPrimary,
+    SetAddress,
+    RegisterReplica,
+    /// Forward a stream to the account, which writes nothing but costs the bandwidth.
+    Forward,
+}
+
+/// A write request to the address book, which should be authorized by the server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DirectoryWrite<'a> {
+    pub op: DirectoryOp,
+    /// The verified guarantee account of the request sign.
+    pub requester: AccountRef,
+    /// Whether the requester has signed the request for itself, e.g. the server itself.
+    pub self_signed: bool,
+    pub kind: Option<&'a Hash>,
+    /// The account whose primary, address or replica is being written.
+    pub account: &'a AccountRef,
+}
+
+/// Decides who may write to the address book of a server.
+pub trait AuthorizationPolicy: Send + Sync {
+    fn authorize(&self, write: &DirectoryWrite<'_>) -> Result<()>;
+}
+
+/// A policy with admins, per-`kind` owners and self-publishing accounts.
+///
+/// The self-signed writes are always allowed, so the default policy
+/// accepts the writes from the server itself only.
+#[derive(Clone, Debug, Default)]
+pub struct DirectoryPolicy {
+    admins: HashSet<AccountRef>,
+    owners: HashMap<Option<Hash>, HashSet<AccountRef>>,
+    allow_own_address: bool,
+}
+
+impl DirectoryPolicy {
+    /// Allow the account to write anything.
+    pub fn admin(mut self, account: AccountRef) -> Self {
+        self.admins.insert(account);
+        self
+    }
+
+    /// Allow the account to write anything of the `kind`.
+    pub fn owner(mut self, kind: Option<Hash>, account: AccountRef) -> Self {
+        self.owners.entry(kind).or_default().insert(account);
+        self
+    }
+
+    /// Allow each account to set its own address.
+    pub fn allow_own_address(mut self, allow: bool) -> Self {
+        self.allow_own_address = allow;
+        self
+    }
+}
+
+impl AuthorizationPolicy for DirectoryPolicy {
+    fn authorize(&self, write: &DirectoryWrite<'_>) -> Result<()> {
+        let requester = &write.requester;
+
+        let is_authorized = write.self_signed
+            || self.admins.contains(requester)
+            || self
+                .owners
+                .get(&write.kind.copied())
+                .map(|owners| owners.contains(requester))
+                .unwrap_or_default()
+            || (self.allow_own_address
+                && write.op == DirectoryOp::SetAddress
+                && write.account == requester);
+
+        if is_authorized {
+            Ok(())
+        } else {
+            bail!(ServerError::new(
+                ErrorKind::Unauthorized,
+                format!("{requester} cannot {:?} of {}", write.op, write.account),
+            ))
+        }
+    }
+}
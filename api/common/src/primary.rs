@@ -0,0 +1,105 @@
+use core::time::Duration;
+use std::{sync::Mutex, time::Instant};
+
+use ipiis_common::error::ServerError;
+use ipis::{
+    core::{
+        account::AccountRef,
+        anyhow::{anyhow, Error, Result},
+    },
+    env::infer,
+    futures::Future,
+    log::warn,
+};
+
+/// The default time to wait before retrying an unreachable primary first.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Infer an ordered set of the primaries, which are separated by commas.
+pub fn infer_primaries(key: &str) -> Result<Vec<AccountRef>> {
+    let value: String = infer(key)?;
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|account| !account.is_empty())
+        .map(|account| account.parse().map_err(Into::into))
+        .collect()
+}
+
+/// Tracks the unreachable primaries, so that they are tried last until the retry interval.
+#[derive(Debug)]
+pub struct PrimaryHealth {
+    failures: Mutex<Vec<(AccountRef, Instant)>>,
+    retry_interval: Duration,
+}
+
+impl Default for PrimaryHealth {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRY_INTERVAL)
+    }
+}
+
+impl PrimaryHealth {
+    pub fn new(retry_interval: Duration) -> Self {
+        Self {
+            failures: Default::default(),
+            retry_interval,
+        }
+    }
+
+    pub fn is_healthy(&self, account: &AccountRef) -> bool {
+        let failures = self.failures.lock().unwrap();
+        match failures.iter().find(|(failed, _)| failed == account) {
+            Some((_, since)) => since.elapsed() >= self.retry_interval,
+            None => true,
+        }
+    }
+
+    pub fn report_success(&self, account: &AccountRef) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|(failed, _)| failed != account);
+    }
+
+    pub fn report_failure(&self, account: &AccountRef) {
+        let mut failures = self.failures.lock().unwrap();
+        match failures.iter_mut().find(|(failed, _)| failed == account) {
+            Some((_, since)) => *since = Instant::now(),
+            None => failures.push((*account, Instant::now())),
+        }
+    }
+
+    /// Move the unhealthy primaries to the end, keeping the configured order otherwise.
+    pub fn sort(&self, primaries: &mut [AccountRef]) {
+        primaries.sort_by_key(|account| !self.is_healthy(account));
+    }
+
+    /// Run the request on each primary in order, until one of them succeeds.
+    ///
+    /// Only the primaries which could not be reached are demoted;
+    /// an error response of a server is still a proof of its liveness.
+    pub async fn failover<F, Fut, T>(&self, primaries: Vec<AccountRef>, mut f: F) -> Result<T>
+    where
+        F: FnMut(AccountRef) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut last_error: Option<Error> = None;
+        for primary in primaries {
+            match f(primary).await {
+                Ok(value) => {
+                    self.report_success(&primary);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    if e.downcast_ref::<ServerError>().is_some() {
+                        self.report_success(&primary);
+                    } else {
+                        warn!("primary {primary} is unreachable: {e}");
+                        self.report_failure(&primary);
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("failed to get primary address")))
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::{Context, Interceptor, Next},
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    env::infer,
+};
+
+/// The number of buckets which triggers pruning the idle ones.
+const PRUNE_THRESHOLD: usize = 4_096;
+
+/// The rate of a token bucket.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quota {
+    /// The number of requests refilled per second.
+    pub rate: f64,
+    /// The maximum number of requests in a burst.
+    pub burst: u32,
+}
+
+impl Quota {
+    pub fn per_second(rate: u32) -> Self {
+        Self {
+            rate: rate as f64,
+            burst: rate.max(1),
+        }
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+/// Infer a quota from the requests per second, and the burst in `{key}_burst` if any.
+pub fn infer_quota(key: &str) -> Result<Quota> {
+    let quota = Quota::per_second(infer(key)?);
+    match infer(&format!("{key}_burst")) {
+        Ok(burst) => Ok(quota.with_burst(burst)),
+        Err(_) => Ok(quota),
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn new(quota: &Quota, now: Instant) -> Self {
+        Self {
+            tokens: quota.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * quota.rate).min(quota.burst as f64);
+        self.updated_at = now;
+    }
+
+    /// Take a token, or return the time to wait for the next one.
+    fn try_take(&mut self, quota: &Quota, now: Instant) -> Result<(), Duration> {
+        self.refill(quota, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if quota.rate > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / quota.rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    fn is_full(&self, quota: &Quota, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens + elapsed.as_secs_f64() * quota.rate >= quota.burst as f64
+    }
+}
+
+type BucketKey = (AccountRef, Option<(&'static str, &'static str)>);
+
+/// Limits the request rate of each account with token buckets.
+///
+/// The account is the verified guarantee of the request sign,
+/// so unsigned (raw) requests are not limited by this interceptor.
+#[derive(Debug)]
+pub struct RateLimiter {
+    quota: Quota,
+    overrides: HashMap<(&'static str, &'static str), Quota>,
+    allowlist: HashSet<AccountRef>,
+    buckets: Mutex<HashMap<BucketKey, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            overrides: Default::default(),
+            allowlist: Default::default(),
+            buckets: Default::default(),
+        }
+    }
+
+    /// Use a separate bucket with its own quota for the opcode of the service.
+    pub fn with_opcode(
+        mut self,
+        service: &'static str,
+        opcode: &'static str,
+        quota: Quota,
+    ) -> Self {
+        self.overrides.insert((service, opcode), quota);
+        self
+    }
+
+    /// Never limit the trusted account.
+    pub fn allow(mut self, account: AccountRef) -> Self {
+        self.allowlist.insert(account);
+        self
+    }
+
+    pub fn check(&self, ctx: &Context) -> Result<()> {
+        let account = match ctx.peer {
+            Some(account) if !self.allowlist.contains(&account) => account,
+            _ => return Ok(()),
+        };
+
+        // select the bucket
+        let (key, quota) = match self.overrides.get(&(ctx.service, ctx.opcode)) {
+            Some(quota) => ((account, Some((ctx.service, ctx.opcode))), quota),
+            None => ((account, None), &self.quota),
+        };
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // forget the accounts which are idle enough
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|(_, opcode), bucket| {
+                let quota = opcode
+                    .and_then(|opcode| self.overrides.get(&opcode))
+                    .unwrap_or(&self.quota);
+                !bucket.is_full(quota, now)
+            });
+        }
+
+        let bucket = buckets
+            .entry(key)
+            .or_insert_with(|| Bucket::new(quota, now));
+        match bucket.try_take(quota, now) {
+            Ok(()) => Ok(()),
+            Err(retry_after) => bail!(ServerError::new(
+                ErrorKind::RateLimited,
+                format!(
+                    "{account} on {}/{} (retry after {retry_after:?})",
+                    ctx.service, ctx.opcode,
+                ),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Interceptor for RateLimiter {
+    async fn intercept(&self, ctx: &Context, next: Next<'_>) -> Result<()> {
+        self.check(ctx)?;
+        next.run(ctx).await
+    }
+}
@@ -0,0 +1,39 @@
+use core::time::Duration;
+
+use ipiis_common::Ipiis;
+use ipis::{
+    core::{anyhow::Result, value::hash::Hash},
+    log::warn,
+    tokio,
+};
+
+use crate::shutdown::Shutdown;
+
+/// The default interval to refresh the registered address.
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Register the address to the primary periodically, until the shutdown.
+///
+/// A failure is only logged, so that the node can recover once the primary is back.
+pub async fn heartbeat<IpiisClient>(
+    client: &IpiisClient,
+    kind: Option<&Hash>,
+    address: &<IpiisClient as Ipiis>::Address,
+    interval: Duration,
+    shutdown: &Shutdown,
+) -> Result<()>
+where
+    IpiisClient: Ipiis + Send + Sync,
+    <IpiisClient as Ipiis>::Address: Send + Sync,
+{
+    loop {
+        if let Err(e) = client.register(kind, address).await {
+            warn!("failed to register the address: {e}");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => continue,
+            _ = shutdown.wait() => break Ok(()),
+        }
+    }
+}
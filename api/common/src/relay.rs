@@ -0,0 +1,208 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{collections::HashMap, net::SocketAddr, sync::Mutex, time::Instant};
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::{
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    futures::future,
+    tokio::{
+        io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+        sync::mpsc,
+    },
+};
+
+use crate::shutdown::Shutdown;
+
+/// The time for a relayed account to accept an incoming stream, before it is dropped.
+pub const RELAY_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default interval to reopen the relay session after it is closed.
+pub const DEFAULT_RELAY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Holds the incoming streams of the relayed accounts, until they dial back to accept them.
+///
+/// A relayed account keeps a session to the relay, on which a token is sent for each
+/// incoming stream. Then the account opens a new stream to the relay with the token,
+/// and the relay splices both streams. The payloads are signed end-to-end, so the relay
+/// cannot tamper them.
+pub struct Relay<W, R> {
+    next_token: AtomicU64,
+    sessions: Mutex<HashMap<AccountRef, mpsc::UnboundedSender<u64>>>,
+    pending: Mutex<HashMap<u64, PendingStream<W, R>>>,
+}
+
+struct PendingStream<W, R> {
+    account: AccountRef,
+    since: Instant,
+    stream: (W, R),
+}
+
+impl<W, R> Default for Relay<W, R> {
+    fn default() -> Self {
+        Self {
+            next_token: Default::default(),
+            sessions: Default::default(),
+            pending: Default::default(),
+        }
+    }
+}
+
+impl<W, R> Relay<W, R> {
+    /// Begin a session of the account, replacing the former one if any.
+    ///
+    /// The session is closed when the returned receiver of the tokens is dropped.
+    pub fn listen(&self, account: AccountRef) -> mpsc::UnboundedReceiver<u64> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sessions.lock().unwrap().insert(account, tx);
+        rx
+    }
+
+    pub fn is_listening(&self, account: &AccountRef) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(account)
+            .map(|tx| !tx.is_closed())
+            .unwrap_or_default()
+    }
+
+    /// Hold the incoming stream for the account, and notify the account of its token.
+    pub fn enqueue(&self, account: &AccountRef, send: W, recv: R) -> Result<u64> {
+        // drop the streams which are not accepted in time
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|_, pending| pending.since.elapsed() < RELAY_ACCEPT_TIMEOUT);
+
+        let tx = match self.sessions.lock().unwrap().get(account) {
+            Some(tx) if !tx.is_closed() => tx.clone(),
+            _ => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("the account is not relayed: {account}"),
+            )),
+        };
+
+        let token = self.next_token.fetch_add(1, Ordering::SeqCst);
+        self.pending.lock().unwrap().insert(
+            token,
+            PendingStream {
+                account: *account,
+                since: Instant::now(),
+                stream: (send, recv),
+            },
+        );
+
+        // notify the account
+        if tx.send(token).is_err() {
+            self.pending.lock().unwrap().remove(&token);
+            bail!("the relay session is closed: {account}");
+        }
+        Ok(token)
+    }
+
+    /// Take the incoming stream of the token, which is held for the account.
+    pub fn take(&self, account: &AccountRef, token: u64) -> Result<(W, R)> {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get(&token) {
+            Some(stream) if &stream.account == account => {
+                Ok(pending.remove(&token).unwrap().stream)
+            }
+            Some(_) => bail!(ServerError::new(
+                ErrorKind::Unauthorized,
+                format!("the stream is not relayed to {account}"),
+            )),
+            None => bail!("no such relayed stream: {token}"),
+        }
+    }
+}
+
+/// A port allocated for a relayed account, which is released when dropped.
+#[derive(Debug)]
+pub struct Allocation {
+    port: u16,
+    stop: Shutdown,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.stop.shutdown()
+    }
+}
+
+impl Allocation {
+    /// The allocated port should stop accepting when `stop` is triggered.
+    pub fn new(port: u16, stop: Shutdown) -> Self {
+        Self { port, stop }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+/// The address of the relayed account, which is the allocated port on the relay.
+pub fn relayed_address(relay: &SocketAddr, port: u16) -> SocketAddr {
+    SocketAddr::new(relay.ip(), port)
+}
+
+/// Copy the bytes in both directions, until both sides are closed.
+///
+/// Returns the number of bytes sent from `a` to `b`, and the ones from `b` to `a`.
+pub async fn splice<WA, RA, WB, RB>(a: (WA, RA), b: (WB, RB)) -> Result<(u64, u64)>
+where
+    WA: AsyncWrite + Unpin,
+    RA: AsyncRead + Unpin,
+    WB: AsyncWrite + Unpin,
+    RB: AsyncRead + Unpin,
+{
+    let (len, result) = splice_counted(a, b).await;
+    result.map(|()| len)
+}
+
+/// Copy the bytes in both directions like [`splice`], counting them even if either side fails.
+///
+/// Returns the number of bytes which have been copied in each direction, with the error if any.
+pub async fn splice_counted<WA, RA, WB, RB>(
+    (mut send_a, mut recv_a): (WA, RA),
+    (mut send_b, mut recv_b): (WB, RB),
+) -> ((u64, u64), Result<()>)
+where
+    WA: AsyncWrite + Unpin,
+    RA: AsyncRead + Unpin,
+    WB: AsyncWrite + Unpin,
+    RB: AsyncRead + Unpin,
+{
+    let mut a_to_b = 0;
+    let mut b_to_a = 0;
+
+    let result = future::try_join(
+        copy_counted(&mut recv_a, &mut send_b, &mut a_to_b),
+        copy_counted(&mut recv_b, &mut send_a, &mut b_to_a),
+    )
+    .await;
+
+    ((a_to_b, b_to_a), result.map(|_| ()).map_err(Into::into))
+}
+
+async fn copy_counted<R, W>(recv: &mut R, send: &mut W, len: &mut u64) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; 8 * 1024];
+    loop {
+        let read = recv.read(&mut buf).await?;
+        if read == 0 {
+            break send.shutdown().await;
+        }
+
+        send.write_all(&buf[..read]).await?;
+        *len += read as u64;
+    }
+}
@@ -0,0 +1,75 @@
+use core::time::Duration;
+use std::{collections::HashMap, sync::Mutex};
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::{
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    tokio::sync::mpsc,
+};
+
+/// The time to wait for a hole to be punched, before falling back to the registered address.
+pub const PUNCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The default interval to reopen the rendezvous session after it is closed.
+pub const DEFAULT_RENDEZVOUS_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An account which is going to dial, with its address observed by the rendezvous node.
+pub type Introduction = (AccountRef, String);
+
+/// Exchanges the observed addresses of the accounts, so that they can dial each other
+/// at the same time to punch holes through their NATs.
+///
+/// An account which wants to be dialed keeps a session to the rendezvous node,
+/// on which the introductions of the dialing accounts are sent.
+#[derive(Debug, Default)]
+pub struct Rendezvous {
+    sessions: Mutex<HashMap<AccountRef, (String, mpsc::UnboundedSender<Introduction>)>>,
+}
+
+impl Rendezvous {
+    /// Begin a session of the account with its observed address,
+    /// replacing the former one if any.
+    ///
+    /// The session is closed when the returned receiver of the introductions is dropped.
+    pub fn listen(
+        &self,
+        account: AccountRef,
+        address: String,
+    ) -> mpsc::UnboundedReceiver<Introduction> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.sessions.lock().unwrap().insert(account, (address, tx));
+        rx
+    }
+
+    /// Introduce the account to the target, returning the observed address of the target.
+    pub fn introduce(
+        &self,
+        account: &AccountRef,
+        address: &str,
+        target: &AccountRef,
+    ) -> Result<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        match sessions.get(target) {
+            Some((target_address, tx)) => {
+                if tx.send((*account, address.to_string())).is_ok() {
+                    return Ok(target_address.clone());
+                }
+            }
+            None => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("the account is not waiting for the rendezvous: {target}"),
+            )),
+        }
+
+        // clean up the closed session
+        sessions.remove(target);
+        bail!(ServerError::new(
+            ErrorKind::Unresolvable,
+            format!("the rendezvous session is closed: {target}"),
+        ))
+    }
+}
@@ -0,0 +1,128 @@
+use core::{future::Future, time::Duration};
+use std::collections::HashMap;
+
+use ipiis_common::error::{ErrorKind, ServerError};
+pub use ipiis_common::record::now;
+use ipis::{
+    core::{
+        account::{Account, AccountRef, GuaranteeSigned, Verifier},
+        anyhow::{bail, Result},
+        metadata::Metadata,
+        value::hash::Hash,
+    },
+    log::warn,
+    tokio,
+};
+
+use crate::{book::AddressBook, shutdown::Shutdown};
+
+/// The default interval of the anti-entropy rounds between the primaries.
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A change of an entry in the directory.
+///
+/// It consists of the kind, the account (or `None` for the primaries),
+/// the value (or an empty one for the removal) and the timestamp in milliseconds.
+pub type DirectoryChange = (Option<Hash>, Option<AccountRef>, String, u64);
+
+/// A change which is signed by the node making it, so that any primary can relay it
+/// without being able to forge it.
+pub type SignedChange = GuaranteeSigned<DirectoryChange>;
+
+/// Sign the change as the node making it.
+pub fn sign_change(account: &Account, change: DirectoryChange) -> Result<SignedChange> {
+    Metadata::builder().build(account, account.account_ref(), change)
+}
+
+/// Ensure that the change is signed by one of the primaries.
+pub fn verify_change<Address>(book: &AddressBook<Address>, change: &SignedChange) -> Result<()> {
+    // verify the signature
+    change.verify(None)?;
+
+    // verify the signer
+    let signer = &change.guarantee.account;
+    if !book.get_primaries(None)?.contains(signer) {
+        bail!(ServerError::new(
+            ErrorKind::Unauthorized,
+            format!("the change is not signed by a primary: {signer}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the change wins over the current one, comparing the timestamps and then the values.
+pub fn is_newer(change: &DirectoryChange, current: &DirectoryChange) -> bool {
+    (change.3, &change.2) > (current.3, &current.2)
+}
+
+/// The other primaries of this node, which replicate the directory to each other.
+pub fn peers<Address>(book: &AddressBook<Address>) -> Result<Vec<AccountRef>> {
+    let account_me = book.account_me.account_ref();
+
+    Ok(book
+        .get_primaries(None)?
+        .into_iter()
+        .filter(|primary| primary != &account_me)
+        .collect())
+}
+
+/// Ensure that the requester is one of the peer primaries.
+pub fn ensure_peer<Address>(book: &AddressBook<Address>, requester: &AccountRef) -> Result<()> {
+    if peers(book)?.contains(requester) {
+        Ok(())
+    } else {
+        bail!(ServerError::new(
+            ErrorKind::Unauthorized,
+            format!("{requester} is not a peer primary"),
+        ))
+    }
+}
+
+/// The sequence numbers of the entries which are exchanged with a peer primary.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cursor {
+    /// The last change pulled from the peer, which is numbered by the peer.
+    pub pulled: u64,
+    /// The last change pushed to the peer, which is numbered by this node.
+    pub pushed: u64,
+    /// The last address record pulled from the peer, which is numbered by the peer.
+    pub pulled_records: u64,
+    /// The last address record pushed to the peer, which is numbered by this node.
+    pub pushed_records: u64,
+}
+
+/// Synchronize the directory with the peer primaries periodically, until the shutdown.
+///
+/// Each round exchanges the entries since the last successful round with the peer,
+/// so that a peer which has been unreachable catches up once it is reconnected.
+/// The entries are tracked by the sequence numbers of each side rather than the timestamps,
+/// so that the ones relayed late from the other primaries are not missed.
+pub async fn anti_entropy<Address, F, Fut>(
+    book: &AddressBook<Address>,
+    interval: Duration,
+    shutdown: &Shutdown,
+    sync: F,
+) -> Result<()>
+where
+    F: Fn(AccountRef, Cursor) -> Fut,
+    Fut: Future<Output = Result<Cursor>>,
+{
+    let mut cursors: HashMap<AccountRef, Cursor> = HashMap::default();
+    loop {
+        for peer in peers(book)? {
+            let cursor = cursors.get(&peer).copied().unwrap_or_default();
+
+            match sync(peer, cursor).await {
+                Ok(cursor) => {
+                    cursors.insert(peer, cursor);
+                }
+                Err(e) => warn!("failed to synchronize the directory with {peer}: {e}"),
+            }
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => continue,
+            _ = shutdown.wait() => break Ok(()),
+        }
+    }
+}
@@ -7,7 +7,7 @@ macro_rules! impl_ipiis_server {
         const _: () = {
             use std::sync::Arc;
 
-            use ipiis_common::{handle_external_call, Ipiis, ServerResult};
+            use ipiis_common::{external_call, handle_external_call, Ipiis, ServerResult};
             use ipis::core::anyhow::Result;
 
             impl AsRef<Self> for $client {
@@ -35,14 +35,370 @@ macro_rules! impl_ipiis_server {
                     SetAccountPrimary => handle_set_account_primary,
                     GetAddress => handle_get_address,
                     SetAddress => handle_set_address,
+                    Register => handle_register,
+                    Resolve => handle_resolve,
+                    Replicate => handle_replicate,
+                    SyncDirectory => handle_sync_directory,
+                    PublishRecord => handle_publish_record,
+                    GetAddressRecord => handle_get_address_record,
+                    RegisterReplica => handle_register_replica,
+                    GetAccountReplicas => handle_get_account_replicas,
+                    ResolveAccountPrimary => handle_resolve_account_primary,
+                },
+                request_duplex: ::ipiis_common::io => {
+                    Watch => handle_watch,
+                    Relay => handle_relay,
+                    RelayAccept => handle_relay_accept,
+                    Rendezvous => handle_rendezvous,
+                    Forward => handle_forward,
                 },
             );
 
             impl $server {
-                pub async fn run_ipiis(self: Arc<Self>) {
+                pub async fn run_ipiis(self: Arc<Self>) -> Result<()> {
                     let client = self.clone();
 
-                    self.run(client, Self::__handle::<$client>).await
+                    // register services
+                    let mut router = self.router();
+                    Self::register_service::<$client>(client, &mut router)?;
+
+                    self.run(Arc::new(router)).await
+                }
+
+                /// Register the address of this node to the primary periodically, until the shutdown.
+                pub async fn run_heartbeat(
+                    self: Arc<Self>,
+                    kind: Option<::ipis::core::value::hash::Hash>,
+                    address: <$client as Ipiis>::Address,
+                ) -> Result<()> {
+                    ::ipiis_api_common::register::heartbeat(
+                        &self.client,
+                        kind.as_ref(),
+                        &address,
+                        ::ipiis_api_common::register::DEFAULT_HEARTBEAT_INTERVAL,
+                        &self.shutdown,
+                    )
+                    .await
+                }
+
+                /// Announce this node and discover the others in the local network, until the shutdown.
+                pub async fn run_discovery(
+                    self: Arc<Self>,
+                    address: Option<<$client as Ipiis>::Address>,
+                    kinds: Vec<::ipis::core::value::hash::Hash>,
+                ) -> Result<()> {
+                    ::ipiis_api_common::discovery::Discovery::default()
+                        .run(&self.client.book, address.as_ref(), &kinds, &self.shutdown)
+                        .await
+                }
+
+                /// Keep a relayed address at the relay and serve the incoming streams through it,
+                /// until the shutdown.
+                ///
+                /// It lets the node be dialed even if it has no public address, e.g. behind NAT.
+                pub async fn run_relayed(
+                    self: Arc<Self>,
+                    router: Arc<::ipiis_common::router::Router<$client>>,
+                    kind: Option<::ipis::core::value::hash::Hash>,
+                    relay: ::ipis::core::account::AccountRef,
+                ) -> Result<()> {
+                    loop {
+                        if let Err(e) = self.serve_relayed(&router, kind.as_ref(), &relay).await {
+                            ::ipis::log::warn!("the relay session is closed: {e}");
+                        }
+
+                        ::ipis::tokio::select! {
+                            _ = ::ipis::tokio::time::sleep(
+                                ::ipiis_api_common::relay::DEFAULT_RELAY_RETRY_INTERVAL,
+                            ) => continue,
+                            _ = self.shutdown.wait() => break Ok(()),
+                        }
+                    }
+                }
+
+                async fn serve_relayed(
+                    self: &Arc<Self>,
+                    router: &Arc<::ipiis_common::router::Router<$client>>,
+                    kind: Option<&::ipis::core::value::hash::Hash>,
+                    relay: &::ipis::core::account::AccountRef,
+                ) -> Result<()> {
+                    let account_me = self.account_me().account_ref();
+
+                    // open a session
+                    let (mut res, session) = external_call!(
+                        client: &self.client,
+                        target: None => relay,
+                        request: ::ipiis_common::io => Relay,
+                        sign: self.sign(*relay, account_me)?,
+                        inputs: { },
+                        outputs: open,
+                    );
+                    let port = res.port.to_owned().await?;
+                    let (_, mut tokens) = session.require_signed(true).split();
+
+                    // register the relayed address
+                    let relay_address = self.client.get_address(None, relay).await?;
+                    let address = ::ipiis_api_common::relay::relayed_address(&relay_address, port);
+                    self.client.set_address(kind, &account_me, &address).await?;
+                    ::ipis::log::info!("relayed: {account_me} ({address})");
+
+                    // accept the incoming streams until the relay leaves
+                    loop {
+                        let token = ::ipis::tokio::select! {
+                            token = tokens.recv() => match token? {
+                                Some(token) => token,
+                                None => ::ipis::core::anyhow::bail!("closed by the relay"),
+                            },
+                            _ = self.shutdown.wait() => break Ok(()),
+                        };
+
+                        let server = self.clone();
+                        let router = router.clone();
+                        let relay = *relay;
+                        ::ipis::tokio::spawn(async move {
+                            if let Err(e) = server
+                                .accept_relayed(&router, &relay, relay_address, token)
+                                .await
+                            {
+                                ::ipis::log::warn!("failed to accept the relayed stream: {e}");
+                            }
+                        });
+                    }
+                }
+
+                /// Dial back to the relay with the token, and serve the spliced stream.
+                async fn accept_relayed(
+                    &self,
+                    router: &::ipiis_common::router::Router<$client>,
+                    relay: &::ipis::core::account::AccountRef,
+                    relay_address: <$client as Ipiis>::Address,
+                    token: u64,
+                ) -> Result<()> {
+                    // open a session
+                    let (_, session) = external_call!(
+                        client: &self.client,
+                        target: None => relay,
+                        request: ::ipiis_common::io => RelayAccept,
+                        sign: self.sign(*relay, token)?,
+                        inputs: { },
+                        outputs: open,
+                    );
+                    let (send, recv) = session.into_inner();
+
+                    // handle the stream as an incoming one
+                    router.handle(relay_address, send, recv).await
+                }
+
+                /// Synchronize the directory with the peer primaries periodically, until the shutdown.
+                pub async fn run_replication(self: Arc<Self>) -> Result<()> {
+                    ::ipiis_api_common::replication::anti_entropy(
+                        &self.client.book,
+                        ::ipiis_api_common::replication::DEFAULT_SYNC_INTERVAL,
+                        &self.shutdown,
+                        |peer, cursor| self.sync_peer(peer, cursor),
+                    )
+                    .await
+                }
+
+                /// Exchange the changes and the address records since the cursor
+                /// with the peer primary, returning the new cursor.
+                async fn sync_peer(
+                    &self,
+                    peer: ::ipis::core::account::AccountRef,
+                    cursor: ::ipiis_api_common::replication::Cursor,
+                ) -> Result<::ipiis_api_common::replication::Cursor> {
+                    // pull the changes and the address records
+                    let (changes, records, (pulled, pulled_records)) = external_call!(
+                        client: &self.client,
+                        target: None => &peer,
+                        request: ::ipiis_common::io => SyncDirectory,
+                        sign: self.sign(peer, (cursor.pulled, cursor.pulled_records))?,
+                        inputs: { },
+                        outputs: { changes, records, cursor, },
+                    );
+                    self.client.book.merge(changes)?;
+                    self.client.book.merge_records(records)?;
+
+                    // push the changes and the address records
+                    let (changes, pushed) = self.client.book.changes_since(cursor.pushed)?;
+                    let (records, pushed_records) =
+                        self.client.book.records_since(cursor.pushed_records)?;
+                    Self::push(&self.client, peer, changes, records).await?;
+
+                    Ok(::ipiis_api_common::replication::Cursor {
+                        pulled,
+                        pushed,
+                        pulled_records,
+                        pushed_records,
+                    })
+                }
+
+                /// Push the changes and the address records to a peer primary.
+                async fn push(
+                    client: &$client,
+                    peer: ::ipis::core::account::AccountRef,
+                    changes: Vec<::ipiis_api_common::replication::SignedChange>,
+                    records: Vec<
+                        ::ipiis_common::record::AddressRecord<<$client as Ipiis>::Address>,
+                    >,
+                ) -> Result<()> {
+                    if changes.is_empty() && records.is_empty() {
+                        return Ok(());
+                    }
+
+                    // external call
+                    external_call!(
+                        client: client,
+                        target: None => &peer,
+                        request: ::ipiis_common::io => Replicate,
+                        sign: client.sign(peer, (changes.len() + records.len()) as u64)?,
+                        inputs: {
+                            changes: changes,
+                            records: records,
+                        },
+                    );
+                    Ok(())
+                }
+
+                /// Push the changes and the address records to all peer primaries in background.
+                ///
+                /// A failure is only logged, as the peer will catch up on the next anti-entropy round.
+                fn broadcast(
+                    &self,
+                    changes: Vec<::ipiis_api_common::replication::SignedChange>,
+                    records: Vec<
+                        ::ipiis_common::record::AddressRecord<<$client as Ipiis>::Address>,
+                    >,
+                ) {
+                    let client: $client = self.client.clone();
+                    ::ipis::tokio::spawn(async move {
+                        for peer in ::ipiis_api_common::replication::peers(&client.book)? {
+                            if let Err(e) =
+                                Self::push(&client, peer, changes.clone(), records.clone()).await
+                            {
+                                ::ipis::log::warn!(
+                                    "failed to replicate the directory to {peer}: {e}"
+                                );
+                            }
+                        }
+                        Ok::<_, ::ipis::core::anyhow::Error>(())
+                    });
+                }
+
+                /// Push the latest change of the entry to the peer primaries in background.
+                fn replicate_change(
+                    &self,
+                    kind: Option<&::ipis::core::value::hash::Hash>,
+                    account: Option<&::ipis::core::account::AccountRef>,
+                ) {
+                    if let Ok(Some(change)) = self.client.book.get_change(kind, account) {
+                        self.broadcast(vec![change], vec![]);
+                    }
+                }
+
+                /// Push the address record of the account to the peer primaries in background.
+                fn replicate_record(
+                    &self,
+                    kind: Option<&::ipis::core::value::hash::Hash>,
+                    account: &::ipis::core::account::AccountRef,
+                ) {
+                    if let Ok(Some(record)) = self.client.book.get_record(kind, account) {
+                        self.broadcast(vec![], vec![record]);
+                    }
+                }
+
+                /// Apply the interceptor to every incoming request, after the ones added before.
+                ///
+                /// Note that it should be added before running the server.
+                pub fn add_server_interceptor<I>(&mut self, interceptor: I)
+                where
+                    I: ::ipiis_common::interceptor::Interceptor + 'static,
+                {
+                    self.interceptors.push(Arc::new(interceptor));
+                }
+
+                /// Set the policy which decides who may write to the address book.
+                pub fn set_authorization_policy<P>(&mut self, policy: P)
+                where
+                    P: ::ipiis_api_common::policy::AuthorizationPolicy + 'static,
+                {
+                    self.policy = Arc::new(policy);
+                }
+
+                /// Get a handle to stop the server gracefully.
+                pub fn shutdown_handle(&self) -> ::ipiis_api_common::shutdown::Shutdown {
+                    self.shutdown.clone()
+                }
+
+                /// Set the time to wait for the in-flight requests on shutdown.
+                pub fn set_drain_timeout(&mut self, timeout: ::core::time::Duration) {
+                    self.drain_timeout = timeout;
+                }
+
+                /// Accept the peers which cannot be dialed directly, relaying the incoming streams to them.
+                pub fn set_relay(&mut self, enabled: bool) {
+                    self.relay = if enabled {
+                        Some(Default::default())
+                    } else {
+                        None
+                    };
+                }
+
+                /// The usage of the streams which are forwarded for the requester by this node.
+                pub fn forward_usage(
+                    &self,
+                    requester: &::ipis::core::account::AccountRef,
+                ) -> ::ipiis_api_common::forward::ForwardUsage {
+                    self.forwarding.usage(requester)
+                }
+
+                /// Set the size limits of the incoming messages.
+                pub fn set_payload_limits(
+                    &mut self,
+                    limits: ::ipiis_common::payload::PayloadLimits,
+                ) {
+                    self.client.set_payload_limits(limits);
+                }
+
+                /// Set the admission limits, which are applied when the server runs.
+                pub fn set_limits(&mut self, limits: ::ipiis_api_common::limits::Limits) {
+                    self.limits = limits;
+                }
+
+                /// Limit the request rate of each account, which is shared by all the routers.
+                pub fn set_rate_limiter(
+                    &mut self,
+                    limiter: Option<::ipiis_api_common::rate_limit::RateLimiter>,
+                ) {
+                    self.rate_limiter = limiter.map(Arc::new);
+                }
+
+                /// Create an empty router with the server-side interceptors.
+                pub fn router(&self) -> ::ipiis_common::router::Router<$client> {
+                    let mut interceptors: Vec<Arc<dyn ::ipiis_common::interceptor::Interceptor>> =
+                        Default::default();
+
+                    // admit the request before the user-defined interceptors
+                    if let Some(limiter) = &self.rate_limiter {
+                        interceptors.push(limiter.clone());
+                    }
+                    let limits = &self.limits;
+                    if limits.max_streams_per_account.is_some()
+                        || limits.max_sessions_per_account.is_some()
+                    {
+                        interceptors.push(Arc::new(
+                            ::ipiis_api_common::limits::AccountLimiter::new(
+                                limits.max_streams_per_account,
+                                limits.max_sessions_per_account,
+                            ),
+                        ));
+                    }
+                    interceptors.extend(self.interceptors.iter().cloned());
+
+                    let mut router =
+                        ::ipiis_common::router::Router::with_interceptors(interceptors);
+                    router.set_payload_limits(self.client.payload_limits());
+                    router
                 }
 
                 async fn handle_get_account_primary(
@@ -62,10 +418,27 @@ macro_rules! impl_ipiis_server {
 
                     // unpack data
                     let kind = sign_as_guarantee.data.data;
+                    let requester = sign_as_guarantee.guarantee.account;
 
-                    // handle data
-                    let account = client.get_account_primary(kind.as_ref()).await?;
-                    let address = client.book.get(kind.as_ref(), &account)?;
+                    // handle data, beginning the path with the requester to detect a cycle
+                    let (account, address) = match kind.as_ref() {
+                        Some(kind) => {
+                            let ::ipiis_common::resolve::PrimaryResolution {
+                                account, address, ..
+                            } = client
+                                .resolve_account_primary(
+                                    kind,
+                                    &[requester],
+                                    ::ipiis_common::resolve::DEFAULT_MAX_HOPS,
+                                )
+                                .await?;
+                            (account, address)
+                        }
+                        None => {
+                            let account = client.get_account_primary(None).await?;
+                            (account, client.book.get(None, &account)?)
+                        }
+                    };
 
                     // sign data
                     let sign = client.sign_as_guarantor(sign_as_guarantee)?;
@@ -86,15 +459,24 @@ macro_rules! impl_ipiis_server {
                     // unpack sign
                     let sign_as_guarantee = req.__sign.into_owned().await?;
 
-                    // verify as root
-                    sign_as_guarantee.ensure_self_signed()?;
-
                     // unpack data
                     let kind = sign_as_guarantee.data.data.0;
                     let account = sign_as_guarantee.data.data.1;
 
+                    // authorize
+                    client
+                        .policy
+                        .authorize(&::ipiis_api_common::policy::DirectoryWrite {
+                            op: ::ipiis_api_common::policy::DirectoryOp::SetAccountPrimary,
+                            requester: sign_as_guarantee.guarantee.account,
+                            self_signed: sign_as_guarantee.ensure_self_signed().is_ok(),
+                            kind: kind.as_ref(),
+                            account: &account,
+                        })?;
+
                     // handle data
                     client.set_account_primary(kind.as_ref(), &account).await?;
+                    client.replicate_change(kind.as_ref(), None);
 
                     // sign data
                     let sign = client.sign_as_guarantor(sign_as_guarantee)?;
@@ -121,9 +503,18 @@ macro_rules! impl_ipiis_server {
                     // unpack data
                     let kind = sign_as_guarantee.data.data.0;
                     let account = sign_as_guarantee.data.data.1;
+                    let requester = sign_as_guarantee.guarantee.account;
 
-                    // handle data
-                    let address = client.get_address(kind.as_ref(), &account).await?;
+                    // handle data, beginning the path with the requester to detect a cycle
+                    let address = client
+                        .resolve(
+                            kind.as_ref(),
+                            &account,
+                            &[requester],
+                            ::ipiis_common::resolve::DEFAULT_MAX_HOPS,
+                        )
+                        .await?
+                        .address;
 
                     // sign data
                     let sign = client.sign_as_guarantor(sign_as_guarantee)?;
@@ -148,18 +539,27 @@ macro_rules! impl_ipiis_server {
                     // unpack sign
                     let sign_as_guarantee = req.__sign.into_owned().await?;
 
-                    // verify as root
-                    sign_as_guarantee.ensure_self_signed()?;
-
                     // unpack data
                     let kind = sign_as_guarantee.data.data.0;
                     let account = sign_as_guarantee.data.data.1;
                     let address = sign_as_guarantee.data.data.2;
 
+                    // authorize
+                    client
+                        .policy
+                        .authorize(&::ipiis_api_common::policy::DirectoryWrite {
+                            op: ::ipiis_api_common::policy::DirectoryOp::SetAddress,
+                            requester: sign_as_guarantee.guarantee.account,
+                            self_signed: sign_as_guarantee.ensure_self_signed().is_ok(),
+                            kind: kind.as_ref(),
+                            account: &account,
+                        })?;
+
                     // handle data
                     client
                         .set_address(kind.as_ref(), &account, &address)
                         .await?;
+                    client.replicate_change(kind.as_ref(), Some(&account));
 
                     // sign data
                     let sign = client.sign_as_guarantor(sign_as_guarantee)?;
@@ -170,6 +570,658 @@ macro_rules! impl_ipiis_server {
                         __sign: ::ipis::stream::DynStream::Owned(sign),
                     })
                 }
+
+                async fn handle_register(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Register<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                ) -> Result<
+                    ::ipiis_common::io::response::Register<'static, <$client as Ipiis>::Address>,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = &sign_as_guarantee.data.data.0;
+                    let account = &sign_as_guarantee.data.data.1;
+                    let address = &sign_as_guarantee.data.data.2;
+
+                    // verify the registrant
+                    if account != &sign_as_guarantee.guarantee.account {
+                        ::ipis::core::anyhow::bail!(::ipiis_common::error::ServerError::new(
+                            ::ipiis_common::error::ErrorKind::Unauthorized,
+                            format!(
+                                "{} cannot register {account}",
+                                sign_as_guarantee.guarantee.account,
+                            ),
+                        ));
+                    }
+
+                    // authorize
+                    client
+                        .policy
+                        .authorize(&::ipiis_api_common::policy::DirectoryWrite {
+                            op: ::ipiis_api_common::policy::DirectoryOp::SetAddress,
+                            requester: sign_as_guarantee.guarantee.account,
+                            self_signed: sign_as_guarantee.ensure_self_signed().is_ok(),
+                            kind: kind.as_ref(),
+                            account,
+                        })?;
+
+                    // handle data
+                    client.book.set(kind.as_ref(), account, address)?;
+                    client.replicate_change(kind.as_ref(), Some(account));
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::Register {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    })
+                }
+
+                async fn handle_resolve(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Resolve<'static, <$client as Ipiis>::Address>,
+                ) -> Result<
+                    ::ipiis_common::io::response::Resolve<'static, <$client as Ipiis>::Address>,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = &sign_as_guarantee.data.data.0;
+                    let account = &sign_as_guarantee.data.data.1;
+                    let path = &sign_as_guarantee.data.data.2;
+                    let hops = sign_as_guarantee.data.data.3;
+
+                    // handle data
+                    let resolution = client.resolve(kind.as_ref(), account, path, hops).await?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::Resolve {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        address: ::ipis::stream::DynStream::Owned(resolution.address),
+                        path: ::ipis::stream::DynStream::Owned(resolution.path),
+                    })
+                }
+
+                async fn handle_replicate(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Replicate<'static, <$client as Ipiis>::Address>,
+                ) -> Result<
+                    ::ipiis_common::io::response::Replicate<'static, <$client as Ipiis>::Address>,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // authorize
+                    ::ipiis_api_common::replication::ensure_peer(
+                        &client.book,
+                        &sign_as_guarantee.guarantee.account,
+                    )?;
+
+                    // unpack data
+                    let changes = req.changes.into_owned().await?;
+                    let records = req.records.into_owned().await?;
+
+                    // handle data
+                    client.book.merge(changes)?;
+                    client.book.merge_records(records)?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::Replicate {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    })
+                }
+
+                async fn handle_sync_directory(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::SyncDirectory<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                ) -> Result<
+                    ::ipiis_common::io::response::SyncDirectory<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let (cursor, cursor_records) = sign_as_guarantee.data.data;
+
+                    // authorize
+                    ::ipiis_api_common::replication::ensure_peer(
+                        &client.book,
+                        &sign_as_guarantee.guarantee.account,
+                    )?;
+
+                    // handle data
+                    let (changes, cursor) = client.book.changes_since(cursor)?;
+                    let (records, cursor_records) = client.book.records_since(cursor_records)?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::SyncDirectory {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        changes: ::ipis::stream::DynStream::Owned(changes),
+                        records: ::ipis::stream::DynStream::Owned(records),
+                        cursor: ::ipis::stream::DynStream::Owned((cursor, cursor_records)),
+                    })
+                }
+
+                async fn handle_publish_record(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::PublishRecord<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                ) -> Result<
+                    ::ipiis_common::io::response::PublishRecord<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = sign_as_guarantee.data.data.0;
+                    let account = sign_as_guarantee.guarantee.account;
+
+                    // verify the record
+                    ::ipiis_common::record::verify_record(
+                        &sign_as_guarantee,
+                        kind.as_ref(),
+                        &account,
+                    )?;
+
+                    // handle data
+                    client
+                        .book
+                        .set_record(kind.as_ref(), &account, sign_as_guarantee.clone())?;
+                    client.replicate_change(kind.as_ref(), Some(&account));
+                    client.replicate_record(kind.as_ref(), &account);
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::PublishRecord {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    })
+                }
+
+                async fn handle_get_address_record(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::GetAddressRecord<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                ) -> Result<
+                    ::ipiis_common::io::response::GetAddressRecord<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = sign_as_guarantee.data.data.0;
+                    let account = sign_as_guarantee.data.data.1;
+
+                    // handle data
+                    let record = match client.book.get_record(kind.as_ref(), &account)? {
+                        Some(record) => record,
+                        None => {
+                            ::ipis::core::anyhow::bail!(::ipiis_common::error::ServerError::new(
+                                ::ipiis_common::error::ErrorKind::Unresolvable,
+                                format!("no address record of {account}"),
+                            ))
+                        }
+                    };
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::GetAddressRecord {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        record: ::ipis::stream::DynStream::Owned(record),
+                    })
+                }
+
+                async fn handle_resolve_account_primary(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::ResolveAccountPrimary<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                ) -> Result<
+                    ::ipiis_common::io::response::ResolveAccountPrimary<
+                        'static,
+                        <$client as Ipiis>::Address,
+                    >,
+                > {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = &sign_as_guarantee.data.data.0;
+                    let path = &sign_as_guarantee.data.data.1;
+                    let hops = sign_as_guarantee.data.data.2;
+
+                    // handle data
+                    let resolution = client.resolve_account_primary(kind, path, hops).await?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::ResolveAccountPrimary {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        account: ::ipis::stream::DynStream::Owned(resolution.account),
+                        address: ::ipis::stream::DynStream::Owned(resolution.address),
+                        path: ::ipis::stream::DynStream::Owned(resolution.path),
+                    })
+                }
+
+                async fn handle_register_replica(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::RegisterReplica<'static>,
+                ) -> Result<::ipiis_common::io::response::RegisterReplica<'static>> {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = sign_as_guarantee.data.data.0;
+                    let account = sign_as_guarantee.data.data.1;
+                    let weight = sign_as_guarantee.data.data.2;
+
+                    // verify the registrant
+                    if account != sign_as_guarantee.guarantee.account {
+                        ::ipis::core::anyhow::bail!(::ipiis_common::error::ServerError::new(
+                            ::ipiis_common::error::ErrorKind::Unauthorized,
+                            format!(
+                                "{} cannot register {account}",
+                                sign_as_guarantee.guarantee.account,
+                            ),
+                        ));
+                    }
+
+                    // authorize
+                    client
+                        .policy
+                        .authorize(&::ipiis_api_common::policy::DirectoryWrite {
+                            op: ::ipiis_api_common::policy::DirectoryOp::RegisterReplica,
+                            requester: sign_as_guarantee.guarantee.account,
+                            self_signed: sign_as_guarantee.ensure_self_signed().is_ok(),
+                            kind: Some(&kind),
+                            account: &account,
+                        })?;
+
+                    // handle data
+                    client.book.set_replica(Some(&kind), &account, weight)?;
+                    client.replicate_change(Some(&kind), None);
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::RegisterReplica {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    })
+                }
+
+                async fn handle_get_account_replicas(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::GetAccountReplicas<'static>,
+                ) -> Result<::ipiis_common::io::response::GetAccountReplicas<'static>> {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = sign_as_guarantee.data.data;
+
+                    // handle data
+                    let replicas = client.book.get_replicas(kind.as_ref())?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    Ok(::ipiis_common::io::response::GetAccountReplicas {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        replicas: ::ipis::stream::DynStream::Owned(replicas),
+                    })
+                }
+
+                async fn handle_watch<__IpiisClient>(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Watch<'static>,
+                    pending: ::ipiis_common::duplex::Pending<'_, __IpiisClient>,
+                ) -> Result<()>
+                where
+                    __IpiisClient: Ipiis,
+                {
+                    use ::ipis::tokio::sync::broadcast::error::RecvError;
+
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let kind = sign_as_guarantee.data.data.0;
+                    let account = sign_as_guarantee.data.data.1;
+
+                    // subscribe before the current state, so that no change is missed
+                    let mut changes = client.book.subscribe();
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    let mut res = ::ipiis_common::io::response::Watch {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    };
+
+                    // begin a session
+                    let mut session = res.accept(client, pending).await?.signed(true);
+
+                    // send the current state
+                    let mut current = client
+                        .book
+                        .get_change(kind.as_ref(), account.as_ref())?
+                        .map(|change| change.data.data);
+                    if let Some(change) = current.clone() {
+                        session.send(change).await?;
+                    }
+
+                    // push the changes until the client leaves
+                    loop {
+                        let change = ::ipis::tokio::select! {
+                            change = changes.recv() => change,
+                            _ = client.shutdown.wait() => break Ok(()),
+                        };
+
+                        let change = match change {
+                            Ok(change) if change.0 == kind && change.1 == account => change,
+                            Ok(_) => continue,
+                            // resend the latest state of the entry, skipping the missed ones
+                            Err(RecvError::Lagged(_)) => {
+                                match client.book.get_change(kind.as_ref(), account.as_ref())? {
+                                    Some(change) if Some(&change.data.data) != current.as_ref() => {
+                                        change.data.data
+                                    }
+                                    _ => continue,
+                                }
+                            }
+                            Err(RecvError::Closed) => break Ok(()),
+                        };
+
+                        current = Some(change.clone());
+                        session.send(change).await?;
+                    }
+                }
+
+                fn relay(
+                    &self,
+                ) -> Result<
+                    &Arc<
+                        ::ipiis_api_common::relay::Relay<
+                            <$client as Ipiis>::Writer,
+                            <$client as Ipiis>::Reader,
+                        >,
+                    >,
+                > {
+                    match &self.relay {
+                        Some(relay) => Ok(relay),
+                        None => {
+                            ::ipis::core::anyhow::bail!(::ipiis_common::error::ServerError::new(
+                                ::ipiis_common::error::ErrorKind::Unauthorized,
+                                "relaying is disabled on this server",
+                            ))
+                        }
+                    }
+                }
+
+                async fn handle_relay<__IpiisClient>(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Relay<'static>,
+                    pending: ::ipiis_common::duplex::Pending<'_, __IpiisClient>,
+                ) -> Result<()>
+                where
+                    __IpiisClient: Ipiis,
+                {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let account = sign_as_guarantee.data.data;
+
+                    // verify the relayed account
+                    if account != sign_as_guarantee.guarantee.account {
+                        ::ipis::core::anyhow::bail!(::ipiis_common::error::ServerError::new(
+                            ::ipiis_common::error::ErrorKind::Unauthorized,
+                            format!(
+                                "{} cannot be relayed as {account}",
+                                sign_as_guarantee.guarantee.account,
+                            ),
+                        ));
+                    }
+
+                    // handle data
+                    let relay = client.relay()?.clone();
+                    let mut tokens = relay.listen(account);
+                    let allocation = client.allocate_relay(relay, account).await?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    let mut res = ::ipiis_common::io::response::Relay {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        port: ::ipis::stream::DynStream::Owned(allocation.port()),
+                    };
+
+                    // begin a session
+                    let mut session = res.accept(client, pending).await?.signed(true);
+
+                    // notify the incoming streams until the account leaves or reconnects
+                    loop {
+                        let token = ::ipis::tokio::select! {
+                            token = tokens.recv() => match token {
+                                Some(token) => token,
+                                None => break Ok(()),
+                            },
+                            _ = client.shutdown.wait() => break Ok(()),
+                        };
+                        session.send(token).await?;
+                    }
+                }
+
+                async fn handle_relay_accept<__IpiisClient>(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::RelayAccept<'static>,
+                    pending: ::ipiis_common::duplex::Pending<'_, __IpiisClient>,
+                ) -> Result<()>
+                where
+                    __IpiisClient: Ipiis,
+                {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let token = sign_as_guarantee.data.data;
+
+                    // handle data
+                    let stream = client
+                        .relay()?
+                        .take(&sign_as_guarantee.guarantee.account, token)?;
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    let mut res = ::ipiis_common::io::response::RelayAccept {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                    };
+
+                    // begin a session
+                    let session = res.accept(client, pending).await?;
+
+                    // splice the incoming stream
+                    ::ipiis_api_common::relay::splice(stream, session.into_inner()).await?;
+                    Ok(())
+                }
+
+                async fn handle_forward<__IpiisClient>(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Forward<'static>,
+                    pending: ::ipiis_common::duplex::Pending<'_, __IpiisClient>,
+                ) -> Result<()>
+                where
+                    __IpiisClient: Ipiis,
+                {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let requester = sign_as_guarantee.guarantee.account;
+                    let kind = sign_as_guarantee.data.data.0;
+                    let target = sign_as_guarantee.data.data.1;
+                    let path = sign_as_guarantee.data.data.2.clone();
+                    let hops = sign_as_guarantee.data.data.3;
+
+                    // forward only for the accounts allowed by the policy, as anyone may register
+                    client
+                        .policy
+                        .authorize(&::ipiis_api_common::policy::DirectoryWrite {
+                            op: ::ipiis_api_common::policy::DirectoryOp::Forward,
+                            requester,
+                            self_signed: sign_as_guarantee.ensure_self_signed().is_ok(),
+                            kind: kind.as_ref(),
+                            account: &target,
+                        })?;
+
+                    // handle data
+                    let visited =
+                        ::ipiis_common::resolve::visit(&path, client.account_me().account_ref())?;
+                    let (stream, path) = match client.call_direct(kind.as_ref(), &target).await {
+                        Ok(stream) => (stream, visited),
+                        // ask the primaries to forward the stream to the next hop
+                        Err(_) => client.forward(kind.as_ref(), &target, &path, hops).await?,
+                    };
+                    client.forwarding.begin(&requester);
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    let mut res = ::ipiis_common::io::response::Forward {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        path: ::ipis::stream::DynStream::Owned(path),
+                    };
+
+                    // begin a session
+                    let session = res.accept(client, pending).await?;
+
+                    // splice the stream to the target, recording the usage even if it fails
+                    let ((sent, received), result) =
+                        ::ipiis_api_common::relay::splice_counted(session.into_inner(), stream)
+                            .await;
+                    client.forwarding.record(&requester, sent, received);
+                    result
+                }
+
+                async fn handle_rendezvous<__IpiisClient>(
+                    client: &$server,
+                    req: ::ipiis_common::io::request::Rendezvous<'static>,
+                    pending: ::ipiis_common::duplex::Pending<'_, __IpiisClient>,
+                ) -> Result<()>
+                where
+                    __IpiisClient: Ipiis,
+                {
+                    // unpack sign
+                    let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                    // unpack data
+                    let account = sign_as_guarantee.guarantee.account;
+                    let target = sign_as_guarantee.data.data;
+                    let observed = pending.addr().to_string();
+
+                    // handle data
+                    let (address, introductions) = match target {
+                        // introduce the account to the target
+                        Some(target) => {
+                            let address = client
+                                .rendezvous()?
+                                .introduce(&account, &observed, &target)?;
+                            (address, None)
+                        }
+                        // wait for the introductions, notifying the account of its observed address
+                        None => {
+                            let introductions =
+                                client.rendezvous()?.listen(account, observed.clone());
+                            (observed, Some(introductions))
+                        }
+                    };
+
+                    // sign data
+                    let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                    // pack data
+                    let mut res = ::ipiis_common::io::response::Rendezvous {
+                        __lifetime: Default::default(),
+                        __sign: ::ipis::stream::DynStream::Owned(sign),
+                        address: ::ipis::stream::DynStream::Owned(address),
+                    };
+
+                    // begin a session
+                    let mut session = res.accept(client, pending).await?.signed(true);
+
+                    // notify the introductions until the account leaves or reconnects
+                    if let Some(mut introductions) = introductions {
+                        loop {
+                            let introduction = ::ipis::tokio::select! {
+                                introduction = introductions.recv() => match introduction {
+                                    Some(introduction) => introduction,
+                                    None => break,
+                                },
+                                _ = client.shutdown.wait() => break,
+                            };
+                            session.send(introduction).await?;
+                        }
+                    }
+                    Ok(())
+                }
             }
         };
     };
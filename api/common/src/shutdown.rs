@@ -0,0 +1,82 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipis::tokio::sync::{mpsc, watch};
+
+/// The default time to wait for the in-flight requests on shutdown.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A handle to stop a running server gracefully.
+///
+/// All clones share the same state, so that any of them can stop the server.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    tx: Arc<watch::Sender<bool>>,
+    rx: watch::Receiver<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(false);
+        Self { tx: tx.into(), rx }
+    }
+}
+
+impl Shutdown {
+    /// Stop accepting new requests and begin draining the in-flight ones.
+    pub fn shutdown(&self) {
+        // the receiver is owned by `self`, so it cannot fail
+        let _ = self.tx.send(true);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Wait until the shutdown is requested.
+    pub async fn wait(&self) {
+        let mut rx = self.rx.clone();
+        while !*rx.borrow() {
+            if rx.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Tracks the in-flight tasks, so that the server can wait for them on shutdown.
+#[derive(Debug)]
+pub struct Drain {
+    tx: mpsc::Sender<()>,
+    rx: mpsc::Receiver<()>,
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel(1);
+        Self { tx, rx }
+    }
+}
+
+impl Drain {
+    /// Mark a task as in-flight until the guard is dropped.
+    pub fn guard(&self) -> DrainGuard {
+        DrainGuard {
+            _tx: self.tx.clone(),
+        }
+    }
+
+    /// Wait until all the guards are dropped.
+    pub async fn wait(self) {
+        let Self { tx, mut rx } = self;
+        drop(tx);
+
+        // nothing is sent, so it returns only when all the senders are dropped
+        let _ = rx.recv().await;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DrainGuard {
+    _tx: mpsc::Sender<()>,
+}
@@ -0,0 +1,94 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::Duration,
+};
+
+use ipiis_api_common::{
+    book::AddressBook,
+    discovery::{self, Beacon, Discovery},
+    shutdown::Shutdown,
+};
+use ipiis_common::record;
+use ipis::{
+    core::{account::Account, metadata::Metadata, value::hash::Hash},
+    stream::DynStream,
+    tokio,
+};
+
+#[tokio::test]
+async fn test_discovery_on_loopback() {
+    let group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 42, 99), 9803);
+    let discovery = Discovery::new(group)
+        .with_interface(Ipv4Addr::LOCALHOST)
+        .with_interval(Duration::from_millis(100));
+    let shutdown = Shutdown::default();
+
+    let kind = Hash::with_str("my_kind");
+    let address_a: SocketAddr = "127.0.0.1:9811".parse().unwrap();
+    let book_a = Arc::new(AddressBook::new(Account::generate(), "discovery_a").unwrap());
+    let book_b = Arc::new(AddressBook::new(Account::generate(), "discovery_b").unwrap());
+
+    // announce the node A only
+    let tasks = vec![
+        tokio::spawn({
+            let (book, shutdown) = (book_a.clone(), shutdown.clone());
+            async move {
+                discovery
+                    .run(&book, Some(&address_a), &[kind], &shutdown)
+                    .await
+            }
+        }),
+        tokio::spawn({
+            let (book, shutdown) = (book_b.clone(), shutdown.clone());
+            async move { discovery.run(&book, None, &[], &shutdown).await }
+        }),
+    ];
+
+    // wait for the beacon
+    let account_a = book_a.account_me.account_ref();
+    let mut found = None;
+    for _ in 0..50 {
+        found = book_b.get(Some(&kind), &account_a).unwrap();
+        if found.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(found, Some(address_a));
+    assert_eq!(book_b.get(None, &account_a).unwrap(), Some(address_a));
+
+    shutdown.shutdown();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_discovery_rejects_forged_beacons() {
+    let alice = Account::generate();
+    let bob = Account::generate();
+    let book: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "forged").unwrap();
+    let address: SocketAddr = "127.0.0.1:9812".parse().unwrap();
+
+    // accept a beacon signed by the announced account
+    let beacon = discovery::encode_beacon(&alice, &address, &[])
+        .await
+        .unwrap();
+    let max_age = Duration::from_secs(10);
+    let account = discovery::accept_beacon(&book, &beacon, max_age)
+        .await
+        .unwrap();
+    assert_eq!(account, Some(alice.account_ref()));
+
+    // reject a beacon which is not signed by itself
+    let data = (address.to_string(), vec![], record::now());
+    let beacon: Beacon = Metadata::builder()
+        .build(&alice, bob.account_ref(), data)
+        .unwrap();
+    let mut buf = Vec::new();
+    DynStream::Owned(beacon).copy_to(&mut buf).await.unwrap();
+    assert!(discovery::accept_beacon(&book, &buf, max_age)
+        .await
+        .is_err());
+}
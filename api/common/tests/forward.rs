@@ -0,0 +1,29 @@
+use ipiis_api_common::forward::{ForwardAccounting, ForwardUsage};
+use ipis::core::account::Account;
+
+#[test]
+fn test_forward_accounting() {
+    let a = Account::generate().account_ref();
+    let b = Account::generate().account_ref();
+
+    let accounting = ForwardAccounting::default();
+    assert_eq!(accounting.usage(&a), ForwardUsage::default());
+
+    // account the streams per requester
+    accounting.begin(&a);
+    accounting.record(&a, 10, 20);
+    accounting.begin(&a);
+    accounting.record(&a, 1, 2);
+    accounting.begin(&b);
+
+    assert_eq!(
+        accounting.usage(&a),
+        ForwardUsage {
+            streams: 2,
+            sent: 11,
+            received: 22,
+        },
+    );
+    assert_eq!(accounting.usage(&b).streams, 1);
+    assert_eq!(accounting.usages().len(), 2);
+}
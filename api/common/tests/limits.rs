@@ -0,0 +1,111 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api_common::limits::AccountLimiter;
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::{dispatch, Context, Interceptor},
+};
+use ipis::{
+    core::{account::Account, anyhow::Result},
+    futures::FutureExt,
+    tokio::{self, sync::Notify, task::JoinHandle},
+};
+
+fn context(account: &Account, session: bool) -> Context {
+    Context {
+        service: "ipiis",
+        opcode: if session { "Watch" } else { "GetAddress" },
+        code: 0,
+        addr: None,
+        peer: Some(account.account_ref()),
+        session,
+    }
+}
+
+/// Hold a request in the interceptors until it is released.
+fn hold(
+    interceptors: &[Arc<dyn Interceptor>],
+    ctx: Context,
+    release: &Arc<Notify>,
+) -> JoinHandle<Result<()>> {
+    let interceptors = interceptors.to_vec();
+    let release = release.clone();
+    tokio::spawn(async move {
+        let handler = async move {
+            release.notified().await;
+            Ok(())
+        };
+        dispatch(&interceptors, &ctx, handler.boxed()).await
+    })
+}
+
+async fn call(interceptors: &[Arc<dyn Interceptor>], ctx: Context) -> Result<()> {
+    dispatch(interceptors, &ctx, async { Ok(()) }.boxed()).await
+}
+
+fn assert_overloaded(result: Result<()>, resource: &str) {
+    let error = result.unwrap_err().downcast::<ServerError>().unwrap();
+    assert_eq!(error.kind, ErrorKind::Overloaded);
+    assert!(error.message.contains(resource));
+}
+
+#[tokio::test]
+async fn test_account_limiter() {
+    let alice = Account::generate();
+    let bob = Account::generate();
+
+    let interceptors: Vec<Arc<dyn Interceptor>> =
+        vec![Arc::new(AccountLimiter::new(Some(2), Some(1)))];
+    let release = Arc::new(Notify::new());
+
+    // fill the caps of alice
+    let held = vec![
+        hold(&interceptors, context(&alice, false), &release),
+        hold(&interceptors, context(&alice, false), &release),
+        hold(&interceptors, context(&alice, true), &release),
+    ];
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // reject the excess requests and sessions of alice
+    assert_overloaded(
+        call(&interceptors, context(&alice, false)).await,
+        "requests",
+    );
+    assert_overloaded(call(&interceptors, context(&alice, true)).await, "sessions");
+
+    // accept the requests of the others
+    call(&interceptors, context(&bob, false)).await.unwrap();
+    call(&interceptors, context(&bob, true)).await.unwrap();
+
+    // release the permits when finished
+    release.notify_waiters();
+    for handle in held {
+        handle.await.unwrap().unwrap();
+    }
+    call(&interceptors, context(&alice, false)).await.unwrap();
+    call(&interceptors, context(&alice, true)).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_account_limiter_sessions() {
+    let alice = Account::generate();
+
+    let interceptors: Vec<Arc<dyn Interceptor>> =
+        vec![Arc::new(AccountLimiter::new(Some(1), Some(4)))];
+    let release = Arc::new(Notify::new());
+
+    // keep the long-lived sessions open
+    let held: Vec<_> = (0..4)
+        .map(|_| hold(&interceptors, context(&alice, true), &release))
+        .collect();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // do not count the sessions against the requests
+    call(&interceptors, context(&alice, false)).await.unwrap();
+
+    release.notify_waiters();
+    for handle in held {
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use ipiis_api_common::policy::{AuthorizationPolicy, DirectoryOp, DirectoryPolicy, DirectoryWrite};
+use ipis::core::{
+    account::{Account, AccountRef},
+    value::hash::Hash,
+};
+
+fn write<'a>(
+    op: DirectoryOp,
+    requester: &Account,
+    kind: Option<&'a Hash>,
+    account: &'a AccountRef,
+) -> DirectoryWrite<'a> {
+    DirectoryWrite {
+        op,
+        requester: requester.account_ref(),
+        self_signed: false,
+        kind,
+        account,
+    }
+}
+
+#[test]
+fn test_directory_policy() {
+    let admin = Account::generate();
+    let owner = Account::generate();
+    let publisher = Account::generate();
+    let stranger = Account::generate().account_ref();
+
+    let kind = Hash::with_str("my kind");
+    let other_kind = Hash::with_str("other kind");
+
+    // the default policy accepts the self-signed writes only
+    let policy = DirectoryPolicy::default();
+    let mut request = write(DirectoryOp::SetAddress, &admin, None, &stranger);
+    assert!(policy.authorize(&request).is_err());
+    request.self_signed = true;
+    assert!(policy.authorize(&request).is_ok());
+
+    let policy = DirectoryPolicy::default()
+        .admin(admin.account_ref())
+        .owner(Some(kind), owner.account_ref())
+        .allow_own_address(true);
+
+    // the admins may write anything
+    let request = write(DirectoryOp::SetAccountPrimary, &admin, None, &stranger);
+    assert!(policy.authorize(&request).is_ok());
+
+    // the owners may write their kind only
+    let request = write(
+        DirectoryOp::SetAccountPrimary,
+        &owner,
+        Some(&kind),
+        &stranger,
+    );
+    assert!(policy.authorize(&request).is_ok());
+    let request = write(
+        DirectoryOp::SetAccountPrimary,
+        &owner,
+        Some(&other_kind),
+        &stranger,
+    );
+    assert!(policy.authorize(&request).is_err());
+
+    // the accounts may set their own address only
+    let publisher_ref = publisher.account_ref();
+    let request = write(DirectoryOp::SetAddress, &publisher, None, &publisher_ref);
+    assert!(policy.authorize(&request).is_ok());
+    let request = write(DirectoryOp::SetAddress, &publisher, None, &stranger);
+    assert!(policy.authorize(&request).is_err());
+    let request = write(
+        DirectoryOp::SetAccountPrimary,
+        &publisher,
+        None,
+        &publisher_ref,
+    );
+    assert!(policy.authorize(&request).is_err());
+
+    // the strangers may not be forwarded through the server
+    let request = write(DirectoryOp::Forward, &publisher, None, &stranger);
+    assert!(policy.authorize(&request).is_err());
+    let request = write(DirectoryOp::Forward, &admin, None, &stranger);
+    assert!(policy.authorize(&request).is_ok());
+}
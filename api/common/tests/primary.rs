@@ -0,0 +1,48 @@
+use ipiis_api_common::primary::PrimaryHealth;
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::{
+    core::{account::Account, anyhow::anyhow},
+    tokio,
+};
+
+#[tokio::test]
+async fn test_primary_failover() {
+    let a = Account::generate().account_ref();
+    let b = Account::generate().account_ref();
+    let c = Account::generate().account_ref();
+
+    let health = PrimaryHealth::default();
+
+    // fail over to the next primary
+    let value = health
+        .failover(vec![a, b, c], |primary| async move {
+            if primary == a {
+                Err(anyhow!("connection refused"))
+            } else {
+                Ok(primary)
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(value, b);
+
+    // demote the unreachable primary
+    assert!(!health.is_healthy(&a));
+    let mut primaries = vec![a, b, c];
+    health.sort(&mut primaries);
+    assert_eq!(primaries, vec![b, c, a]);
+
+    // an error response is not a failure of the primary
+    let error = health
+        .failover(vec![b], |_| async move {
+            Err::<(), _>(ServerError::new(ErrorKind::Unauthorized, "denied").into())
+        })
+        .await
+        .unwrap_err();
+    assert!(error.downcast_ref::<ServerError>().is_some());
+    assert!(health.is_healthy(&b));
+
+    // recover after a success
+    health.report_success(&a);
+    assert!(health.is_healthy(&a));
+}
@@ -0,0 +1,55 @@
+use ipiis_api_common::rate_limit::{Quota, RateLimiter};
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::Context,
+};
+use ipis::core::account::Account;
+
+fn context(opcode: &'static str, account: &Account) -> Context {
+    Context {
+        service: "ipiis",
+        opcode,
+        code: 0,
+        addr: None,
+        peer: Some(account.account_ref()),
+        session: false,
+    }
+}
+
+#[test]
+fn test_rate_limit() {
+    let alice = Account::generate();
+    let bob = Account::generate();
+    let trusted = Account::generate();
+
+    let limiter = RateLimiter::new(Quota::per_second(1).with_burst(2))
+        .with_opcode("ipiis", "GetAddress", Quota::per_second(1).with_burst(3))
+        .allow(trusted.account_ref());
+
+    // consume the burst
+    for _ in 0..2 {
+        limiter.check(&context("SetAddress", &alice)).unwrap();
+    }
+
+    // reject the excess work with a distinguishable error
+    let error = limiter
+        .check(&context("SetAddress", &alice))
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::RateLimited);
+
+    // the overridden opcode has its own bucket
+    for _ in 0..3 {
+        limiter.check(&context("GetAddress", &alice)).unwrap();
+    }
+    assert!(limiter.check(&context("GetAddress", &alice)).is_err());
+
+    // the other accounts are not affected
+    limiter.check(&context("SetAddress", &bob)).unwrap();
+
+    // the trusted accounts are never limited
+    for _ in 0..10 {
+        limiter.check(&context("SetAddress", &trusted)).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::net::SocketAddr;
+
+use ipiis_api_common::book::AddressBook;
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    record::{self, AddressRecord},
+};
+use ipis::core::{account::Account, metadata::Metadata, value::hash::Hash};
+
+fn sign(
+    account: &Account,
+    kind: Option<Hash>,
+    address: &str,
+    timestamp: u64,
+) -> AddressRecord<SocketAddr> {
+    let address: SocketAddr = address.parse().unwrap();
+    let data = (kind, address, timestamp, record::now() + 60_000);
+
+    Metadata::builder()
+        .build(account, account.account_ref(), data)
+        .unwrap()
+}
+
+#[test]
+fn test_address_records() {
+    let a: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "book_ra").unwrap();
+    let b: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "book_rb").unwrap();
+    let alice = Account::generate();
+    let kind = Hash::with_str("__ipis__ipiis__records__");
+    let timestamp = record::now();
+
+    // store the record along with its address
+    let record = sign(&alice, Some(kind), "127.0.0.1:9801", timestamp);
+    a.set_record(Some(&kind), &alice.account_ref(), record)
+        .unwrap();
+    assert_eq!(
+        a.get(Some(&kind), &alice.account_ref()).unwrap(),
+        Some("127.0.0.1:9801".parse().unwrap()),
+    );
+
+    // reject the record which is older than the stored one
+    let stale = sign(&alice, Some(kind), "127.0.0.1:9802", timestamp - 1);
+    let error = a
+        .set_record(Some(&kind), &alice.account_ref(), stale)
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::Unauthorized);
+    assert_eq!(
+        a.get(Some(&kind), &alice.account_ref()).unwrap(),
+        Some("127.0.0.1:9801".parse().unwrap()),
+    );
+
+    // replicate the records since the cursor
+    let (records, cursor) = a.records_since(0).unwrap();
+    assert_eq!(b.merge_records(records).unwrap(), 1);
+    assert_eq!(
+        b.get(Some(&kind), &alice.account_ref()).unwrap(),
+        Some("127.0.0.1:9801".parse().unwrap()),
+    );
+    assert!(b
+        .get_record(Some(&kind), &alice.account_ref())
+        .unwrap()
+        .is_some());
+
+    // skip the records which are already applied
+    assert_eq!(b.merge_records(a.records_since(0).unwrap().0).unwrap(), 0);
+    assert!(a.records_since(cursor).unwrap().0.is_empty());
+}
@@ -0,0 +1,64 @@
+use ipiis_api_common::relay::{self, Relay};
+use ipis::{
+    core::account::Account,
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf},
+    },
+};
+
+type Stream = (WriteHalf<DuplexStream>, ReadHalf<DuplexStream>);
+
+/// A pair of connected streams, e.g. the caller's and the relay's.
+fn pipe() -> (Stream, Stream) {
+    let (a, b) = tokio::io::duplex(64);
+    let (recv_a, send_a) = tokio::io::split(a);
+    let (recv_b, send_b) = tokio::io::split(b);
+    ((send_a, recv_a), (send_b, recv_b))
+}
+
+#[tokio::test]
+async fn test_relay() {
+    let relayed = Account::generate().account_ref();
+    let other = Account::generate().account_ref();
+
+    let relay: Relay<_, _> = Relay::default();
+    let (mut caller, incoming) = pipe();
+
+    // reject the streams to the accounts without sessions
+    let (_, unknown) = pipe();
+    assert!(relay.enqueue(&relayed, unknown.0, unknown.1).is_err());
+
+    // notify the incoming stream to the relayed account
+    let mut tokens = relay.listen(relayed);
+    assert!(relay.is_listening(&relayed));
+    let token = relay.enqueue(&relayed, incoming.0, incoming.1).unwrap();
+    assert_eq!(tokens.recv().await, Some(token));
+
+    // accept the stream only by the relayed account
+    assert!(relay.take(&other, token).is_err());
+    let incoming = relay.take(&relayed, token).unwrap();
+    assert!(relay.take(&relayed, token).is_err());
+
+    // splice the streams in both directions
+    let (accepted, mut peer) = pipe();
+    let splice = tokio::spawn(relay::splice(incoming, accepted));
+
+    caller.0.write_all(b"ping").await.unwrap();
+    caller.0.shutdown().await.unwrap();
+    let mut buf = vec![];
+    peer.1.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"ping");
+
+    peer.0.write_all(b"pong").await.unwrap();
+    peer.0.shutdown().await.unwrap();
+    let mut buf = vec![];
+    caller.1.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"pong");
+
+    assert_eq!(splice.await.unwrap().unwrap(), (4, 4));
+
+    // close the session
+    drop(tokens);
+    assert!(!relay.is_listening(&relayed));
+}
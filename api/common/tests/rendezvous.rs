@@ -0,0 +1,24 @@
+use ipiis_api_common::rendezvous::Rendezvous;
+use ipis::core::account::Account;
+
+#[test]
+fn test_rendezvous() {
+    let a = Account::generate().account_ref();
+    let b = Account::generate().account_ref();
+    let a_address = "203.0.113.1:9801";
+    let b_address = "198.51.100.2:9801";
+
+    let rendezvous = Rendezvous::default();
+
+    // fail to meet the accounts without sessions
+    assert!(rendezvous.introduce(&a, a_address, &b).is_err());
+
+    // exchange the observed addresses
+    let mut introductions = rendezvous.listen(b, b_address.into());
+    assert_eq!(rendezvous.introduce(&a, a_address, &b).unwrap(), b_address);
+    assert_eq!(introductions.try_recv().unwrap(), (a, a_address.into()));
+
+    // forget the closed session
+    drop(introductions);
+    assert!(rendezvous.introduce(&a, a_address, &b).is_err());
+}
@@ -0,0 +1,72 @@
+use std::net::SocketAddr;
+
+use ipiis_api_common::{book::AddressBook, replication};
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::core::account::Account;
+
+#[test]
+fn test_replication_last_writer_wins() {
+    let a: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "book_a").unwrap();
+    let b: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "book_b").unwrap();
+    let target = Account::generate().account_ref();
+
+    // both books are primaries of each other
+    let primaries = [a.account_me.account_ref(), b.account_me.account_ref()];
+    a.set_primaries(None, &primaries).unwrap();
+    b.set_primaries(None, &primaries).unwrap();
+
+    // replicate a change
+    a.set(None, &target, &"127.0.0.1:9801".parse().unwrap())
+        .unwrap();
+    let (changes, cursor) = a.changes_since(0).unwrap();
+    assert!(b.merge(changes).unwrap() >= 1);
+    assert_eq!(b.get(None, &target).unwrap(), a.get(None, &target).unwrap());
+
+    // skip the change which is already applied
+    assert_eq!(b.merge(a.changes_since(0).unwrap().0).unwrap(), 0);
+    let (changes, next) = a.changes_since(cursor).unwrap();
+    assert!(changes.is_empty());
+    assert_eq!(next, cursor);
+
+    // prefer the newer change
+    let old = a.get_change(None, Some(&target)).unwrap().unwrap();
+    let mut new = old.data.data.clone();
+    new.2 = "127.0.0.1:9802".to_string();
+    new.3 += 1;
+    let new = replication::sign_change(&b.account_me, new).unwrap();
+    assert_eq!(a.merge(vec![new.clone(), old]).unwrap(), 1);
+    assert_eq!(
+        a.get(None, &target).unwrap(),
+        Some("127.0.0.1:9802".parse().unwrap()),
+    );
+    let (changes, next) = a.changes_since(cursor).unwrap();
+    assert_eq!(
+        changes
+            .into_iter()
+            .map(|change| change.data.data)
+            .collect::<Vec<_>>(),
+        [new.data.data],
+    );
+    assert_eq!(next, cursor + 1);
+
+    // reject the change which is not signed by a primary
+    let mut forged = a
+        .get_change(None, Some(&target))
+        .unwrap()
+        .unwrap()
+        .data
+        .data;
+    forged.2 = "127.0.0.1:9803".to_string();
+    forged.3 += 1;
+    let forged = replication::sign_change(&Account::generate(), forged).unwrap();
+    let error = a
+        .merge(vec![forged])
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::Unauthorized);
+    assert_eq!(
+        a.get(None, &target).unwrap(),
+        Some("127.0.0.1:9802".parse().unwrap()),
+    );
+}
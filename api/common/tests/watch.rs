@@ -0,0 +1,34 @@
+use std::net::SocketAddr;
+
+use ipiis_api_common::book::AddressBook;
+use ipis::core::{account::Account, value::hash::Hash};
+
+#[test]
+fn test_watch_changes() {
+    let book: AddressBook<SocketAddr> = AddressBook::new(Account::generate(), "book_w").unwrap();
+    let target = Account::generate().account_ref();
+    let kind = Hash::with_str("__ipis__ipiis__watch__");
+
+    let mut changes = book.subscribe();
+
+    // notify the changes of an address
+    let address: SocketAddr = "127.0.0.1:9801".parse().unwrap();
+    book.set(None, &target, &address).unwrap();
+
+    let change = changes.try_recv().unwrap();
+    assert_eq!(change.1, Some(target));
+    assert_eq!(change.2, address.to_string());
+
+    // skip the stale changes
+    let mut stale = change;
+    stale.3 -= 1;
+    assert!(!book.apply(stale).unwrap());
+    assert!(changes.try_recv().is_err());
+
+    // notify the changes of the primaries of a kind
+    book.set_replica(Some(&kind), &target, 2).unwrap();
+
+    let change = changes.try_recv().unwrap();
+    assert_eq!(change.0, Some(kind));
+    assert_eq!(change.1, None);
+}
@@ -3,7 +3,10 @@ use std::sync::Arc;
 
 use ipiis_api::{
     client::IpiisClient,
-    common::{define_io, external_call, handle_external_call, Ipiis, ServerResult, CLIENT_DUMMY},
+    common::{
+        define_io, duplex::Pending, external_call, handle_external_call, Ipiis, ServerResult,
+        CLIENT_DUMMY,
+    },
     server::IpiisServer,
 };
 use ipis::{
@@ -93,6 +96,32 @@ async fn main() -> Result<()> {
             // verify data
             assert_eq!(msg, format!("hello, {} years old {}!", &name, age));
         }
+
+        // handle Chat
+        {
+            // external call
+            let (_, session) = external_call!(
+                client: &client,
+                target: None => &server,
+                request: crate::io => Chat,
+                sign: client.sign(server, CLIENT_DUMMY)?,
+                inputs: { },
+                outputs: open,
+            );
+            let (mut sender, mut receiver) = session.signed(true).split();
+
+            // talk with the server
+            for _ in 0..3 {
+                sender.send(name.clone()).await?;
+
+                // verify data
+                assert_eq!(receiver.recv().await?, Some(format!("hello, {}!", &name)));
+            }
+
+            // close the session
+            sender.close().await?;
+            assert_eq!(receiver.recv().await?, None);
+        }
     }
     Ok(())
 }
@@ -158,6 +187,9 @@ handle_external_call!(
     request_raw: crate::io => {
         Raw => handle_raw,
     },
+    request_duplex: crate::io => {
+        Chat => handle_chat,
+    },
 );
 
 impl PingPongServer {
@@ -228,10 +260,42 @@ impl PingPongServer {
             msg: ::ipis::stream::DynStream::Owned(msg),
         })
     }
+
+    async fn handle_chat<__IpiisClient>(
+        client: &IpiisServer,
+        req: crate::io::request::Chat<'static>,
+        pending: Pending<'_, __IpiisClient>,
+    ) -> Result<()>
+    where
+        __IpiisClient: Ipiis,
+    {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        let mut res = crate::io::response::Chat {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        };
+
+        // begin a session
+        let mut session = res.accept(client, pending).await?.require_signed(true);
+
+        // handle messages until the client closes the session
+        while let Some(name) = session.recv().await? {
+            session.send(format!("hello, {}!", &name)).await?;
+        }
+        Ok(())
+    }
 }
 
 define_io! {
-    Ok {
+    service: "ping_pong",
+    version: 1,
+    Ok = 1 {
         inputs: {
             name: String,
             age: u32,
@@ -243,7 +307,7 @@ define_io! {
         output_sign: GuarantorSigned<u8>,
         generics: { },
     },
-    Err {
+    Err = 2 {
         inputs: {
             name: String,
             age: u32,
@@ -255,7 +319,7 @@ define_io! {
         output_sign: GuarantorSigned<u8>,
         generics: { },
     },
-    Raw {
+    Raw = 3 {
         inputs: {
             name: String,
             age: u32,
@@ -267,4 +331,15 @@ define_io! {
         output_sign: GuarantorSigned<u8>,
         generics: { },
     },
+    Chat = 4 {
+        inputs: { },
+        input_sign: GuaranteeSigned<u8>,
+        outputs: { },
+        output_sign: GuarantorSigned<u8>,
+        generics: { },
+        duplex: {
+            inputs: String,
+            outputs: String,
+        },
+    },
 }
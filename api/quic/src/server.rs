@@ -1,25 +1,63 @@
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use ipiis_api_common::impl_ipiis_server;
-use ipiis_common::Ipiis;
+use ipiis_api_common::{
+    forward::ForwardAccounting,
+    impl_ipiis_server,
+    limits::{Limiter, Limits},
+    policy::{AuthorizationPolicy, DirectoryPolicy},
+    primary::infer_primaries,
+    rate_limit::{infer_quota, RateLimiter},
+    relay::{Allocation, Relay},
+    rendezvous::{Rendezvous, DEFAULT_RENDEZVOUS_RETRY_INTERVAL},
+    shutdown::{Drain, DrainGuard, Shutdown, DEFAULT_DRAIN_TIMEOUT},
+};
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    external_call,
+    interceptor::Interceptor,
+    router::Router,
+    Ipiis,
+};
 use ipis::{
     async_trait::async_trait,
     core::{
         account::{Account, AccountRef},
-        anyhow::{bail, Result},
+        anyhow::{bail, Error, Result},
     },
     env::{infer, Infer},
-    futures::{Future, StreamExt},
-    log::{error, info, warn},
-    tokio::sync::Mutex,
+    futures::{future, Future, StreamExt},
+    log::{debug, error, info, warn},
+    tokio::{self, sync::Mutex},
 };
 use quinn::{Endpoint, Incoming, IncomingBiStreams, ServerConfig};
 
 impl_ipiis_server!(client: crate::client::IpiisClient, server: IpiisServer,);
 
+/// The application close code which is sent to the peers on shutdown.
+pub const CLOSE_CODE_SHUTDOWN: u32 = 0x1;
+
+/// The application close code which is sent to the peers exceeding the connection limit.
+pub const CLOSE_CODE_OVERLOADED: u32 = 0x2;
+
 pub struct IpiisServer {
     pub(crate) client: crate::client::IpiisClient,
+    forwarding: ForwardAccounting,
+    interceptors: Vec<Arc<dyn Interceptor>>,
     incoming: Mutex<Incoming>,
+    limits: Limits,
+    policy: Arc<dyn AuthorizationPolicy>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    relay: Option<
+        Arc<
+            Relay<
+                <crate::client::IpiisClient as Ipiis>::Writer,
+                <crate::client::IpiisClient as Ipiis>::Reader,
+            >,
+        >,
+    >,
+    rendezvous: Rendezvous,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
 }
 
 impl ::core::ops::Deref for IpiisServer {
@@ -37,10 +75,10 @@ impl<'a> Infer<'a> for IpiisServer {
 
     async fn try_infer() -> Result<Self> {
         let account_me = infer("ipis_account_me")?;
-        let account_primary = infer("ipiis_account_primary").ok();
+        let account_primaries = infer_primaries("ipiis_account_primary").unwrap_or_default();
         let account_port = infer("ipiis_server_port")?;
 
-        Self::new(account_me, account_primary, account_port).await
+        Self::with_primaries(account_me, account_primaries, account_port).await
     }
 
     async fn genesis(
@@ -48,10 +86,10 @@ impl<'a> Infer<'a> for IpiisServer {
     ) -> Result<<Self as Infer<'a>>::GenesisResult> {
         // generate an account
         let account = Account::generate();
-        let account_primary = infer("ipiis_account_primary").ok();
+        let account_primaries = infer_primaries("ipiis_account_primary").unwrap_or_default();
 
         // init a server
-        let server = Self::new(account, account_primary, port).await?;
+        let server = Self::with_primaries(account, account_primaries, port).await?;
 
         Ok(server)
     }
@@ -62,6 +100,15 @@ impl IpiisServer {
         account_me: Account,
         account_primary: Option<AccountRef>,
         port: u16,
+    ) -> Result<Self> {
+        Self::with_primaries(account_me, account_primary.into_iter().collect(), port).await
+    }
+
+    /// Create a server with the ordered primaries, which are tried in turn.
+    pub async fn with_primaries(
+        account_me: Account,
+        account_primaries: Vec<AccountRef>,
+        port: u16,
     ) -> Result<Self> {
         let (endpoint, incoming) = {
             let crypto = ::rustls::ClientConfig::builder()
@@ -70,18 +117,7 @@ impl IpiisServer {
                 .with_no_client_auth();
             let client_config = ::quinn::ClientConfig::new(Arc::new(crypto));
 
-            let server_config = {
-                let (priv_key, cert_chain) = crate::cert::generate(&account_me)?;
-
-                let mut config = ServerConfig::with_single_cert(cert_chain, priv_key)?;
-                config.transport = {
-                    let mut config = Arc::try_unwrap(config.transport).unwrap();
-                    config.max_idle_timeout(Some(Duration::from_secs(10).try_into()?));
-                    config.keep_alive_interval(Some(Duration::from_secs(5)));
-                    config.into()
-                };
-                config
-            };
+            let server_config = Self::server_config(&account_me)?;
             let addr = format!("0.0.0.0:{port}").parse()?;
 
             let (mut endpoint, incoming) = Endpoint::server(server_config, addr)?;
@@ -93,31 +129,78 @@ impl IpiisServer {
         Ok(Self {
             client: crate::client::IpiisClient::with_address_db_path(
                 account_me,
-                account_primary,
+                account_primaries,
                 "ipiis_server_address_db",
                 endpoint,
             )
             .await?,
+            forwarding: Default::default(),
+            interceptors: Default::default(),
             incoming: Mutex::new(incoming),
+            limits: Default::default(),
+            policy: Arc::new(DirectoryPolicy::default()),
+            rate_limiter: infer_quota("ipiis_server_rate_limit")
+                .ok()
+                .map(RateLimiter::new)
+                .map(Arc::new),
+            relay: None,
+            rendezvous: Default::default(),
+            shutdown: Default::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
         })
     }
 
-    pub async fn run<C, F, Fut>(&self, client: Arc<C>, handler: F)
+    fn server_config(account_me: &Account) -> Result<ServerConfig> {
+        let (priv_key, cert_chain) = crate::cert::generate(account_me)?;
+
+        let mut config = ServerConfig::with_single_cert(cert_chain, priv_key)?;
+        config.transport = {
+            let mut config = Arc::try_unwrap(config.transport).unwrap();
+            config.max_idle_timeout(Some(Duration::from_secs(10).try_into()?));
+            config.keep_alive_interval(Some(Duration::from_secs(5)));
+            config.into()
+        };
+        Ok(config)
+    }
+
+    /// Run the server until the shutdown handle is triggered.
+    pub async fn run(&self, router: Arc<Router<crate::client::IpiisClient>>) -> Result<()> {
+        self.run_until(router, future::pending()).await
+    }
+
+    /// Run the server until either the signal or the shutdown handle is triggered.
+    ///
+    /// Then it ends the sessions, refuses the new streams, waits for the in-flight requests
+    /// within the drain timeout, closes all connections with [`CLOSE_CODE_SHUTDOWN`],
+    /// and flushes the address book.
+    pub async fn run_until<F>(
+        &self,
+        router: Arc<Router<crate::client::IpiisClient>>,
+        signal: F,
+    ) -> Result<()>
     where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-                Arc<C>,
-                <crate::client::IpiisClient as Ipiis>::Writer,
-                <crate::client::IpiisClient as Ipiis>::Reader,
-            ) -> Fut
-            + Copy
-            + Send
-            + 'static,
-        Fut: Future<Output = Result<()>> + Send,
+        F: Future<Output = ()>,
     {
         let mut incoming = self.incoming.lock().await;
 
-        while let Some(connection) = incoming.next().await {
+        let drain = Drain::default();
+        let stop = Shutdown::default();
+
+        let connections = Limiter::new(self.limits.max_connections);
+        let in_flight = Limiter::new(self.limits.max_in_flight);
+
+        let signal = future::select(Box::pin(signal), Box::pin(self.shutdown.wait()));
+        tokio::pin!(signal);
+
+        loop {
+            let connection = tokio::select! {
+                _ = &mut signal => break,
+                connection = incoming.next() => match connection {
+                    Some(connection) => connection,
+                    None => break,
+                },
+            };
+
             match connection.await {
                 Ok(quinn::NewConnection {
                     connection: conn,
@@ -127,12 +210,31 @@ impl IpiisServer {
                     let addr = conn.remote_address();
                     info!("incoming connection: addr={addr}");
 
+                    // admit the connection
+                    let permit = match connections.try_acquire("connections") {
+                        Ok(permit) => permit,
+                        Err(e) => {
+                            warn!("rejecting connection: addr={addr}, {e}");
+                            conn.close(CLOSE_CODE_OVERLOADED.into(), b"overloaded");
+                            continue;
+                        }
+                    };
+
                     {
                         // Each stream initiated by the client constitutes a new request.
-                        let client = client.clone();
+                        let router = router.clone();
+                        let guard = drain.guard();
+                        let stop = stop.clone();
+                        let streams = Limiter::new(self.limits.max_streams_per_connection);
+                        let in_flight = in_flight.clone();
 
                         ::ipis::tokio::spawn(async move {
-                            Self::handle_connection(client, addr, bi_streams, handler).await
+                            let limiters = (streams, in_flight);
+                            Self::handle_connection(
+                                router, addr, bi_streams, limiters, guard, stop,
+                            )
+                            .await;
+                            drop(permit)
                         });
                     }
                 }
@@ -141,50 +243,74 @@ impl IpiisServer {
                 }
             }
         }
+
+        // end the sessions (e.g. watching and relaying), even if stopped by the signal
+        self.shutdown.shutdown();
+
+        // refuse the new streams
+        stop.shutdown();
+
+        // wait for the in-flight requests
+        info!("shutting down: draining the in-flight requests");
+        if tokio::time::timeout(self.drain_timeout, drain.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                "shutting down: drain timeout is reached: {:?}",
+                self.drain_timeout,
+            );
+        }
+
+        // close the connections
+        self.client
+            .endpoint
+            .close(CLOSE_CODE_SHUTDOWN.into(), b"shutdown");
+
+        // flush the address book
+        self.client.book.flush()?;
+
+        info!("shutting down: done");
+        Ok(())
     }
 
-    async fn handle_connection<C, F, Fut>(
-        client: Arc<C>,
+    async fn handle_connection(
+        router: Arc<Router<crate::client::IpiisClient>>,
         addr: SocketAddr,
         bi_streams: IncomingBiStreams,
-        handler: F,
-    ) where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-                Arc<C>,
-                <crate::client::IpiisClient as Ipiis>::Writer,
-                <crate::client::IpiisClient as Ipiis>::Reader,
-            ) -> Fut
-            + Copy
-            + Send
-            + 'static,
-        Fut: Future<Output = Result<()>> + Send,
-    {
-        match Self::try_handle_connection(client, addr, bi_streams, handler).await {
+        limiters: (Limiter, Limiter),
+        guard: DrainGuard,
+        stop: Shutdown,
+    ) {
+        match Self::try_handle_connection(router, addr, bi_streams, limiters, guard, stop).await {
             Ok(_) => (),
             Err(e) => warn!("handling error: addr={addr}, {e}"),
         }
     }
 
-    async fn try_handle_connection<C, F, Fut>(
-        client: Arc<C>,
+    async fn try_handle_connection(
+        router: Arc<Router<crate::client::IpiisClient>>,
         addr: SocketAddr,
         mut bi_streams: IncomingBiStreams,
-        handler: F,
-    ) -> Result<()>
-    where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-                Arc<C>,
-                <crate::client::IpiisClient as Ipiis>::Writer,
-                <crate::client::IpiisClient as Ipiis>::Reader,
-            ) -> Fut
-            + Copy
-            + Send
-            + 'static,
-        Fut: Future<Output = Result<()>> + Send,
-    {
-        while let Some(stream) = bi_streams.next().await {
+        (streams, in_flight): (Limiter, Limiter),
+        guard: DrainGuard,
+        stop: Shutdown,
+    ) -> Result<()> {
+        // the connection holds the drain until the shutdown, and then only its in-flight requests do
+        let mut guard = Some(guard);
+
+        loop {
+            let stream = tokio::select! {
+                _ = stop.wait(), if guard.is_some() => {
+                    guard = None;
+                    continue;
+                }
+                stream = bi_streams.next() => match stream {
+                    Some(stream) => stream,
+                    None => break,
+                },
+            };
+
             match stream {
                 Err(quinn::ConnectionError::ApplicationClosed { .. }) => {
                     info!("connection closed: addr={addr}");
@@ -193,59 +319,196 @@ impl IpiisServer {
                 Err(e) => {
                     bail!("connection error: {e}");
                 }
-                Ok(stream) => {
-                    let client = client.clone();
+                // refuse the new requests while draining
+                Ok((send, _)) if guard.is_none() => {
+                    let error = ServerError::new(ErrorKind::Overloaded, "shutting down");
+                    ::ipis::tokio::spawn(Self::reject(addr, send, error.into()));
+                }
+                Ok((send, recv)) => {
+                    // admit the request
+                    let permits = streams
+                        .try_acquire("streams in the connection")
+                        .and_then(|s| Ok((s, in_flight.try_acquire("in-flight requests")?)));
+
+                    match permits {
+                        Ok(permits) => {
+                            let router = router.clone();
+                            let guard = guard.clone();
 
-                    ::ipis::tokio::spawn(async move {
-                        Self::handle(client, addr, stream, handler).await
-                    });
+                            ::ipis::tokio::spawn(async move {
+                                Self::handle(router, addr, (send, recv)).await;
+                                drop((permits, guard))
+                            });
+                        }
+                        Err(e) => {
+                            ::ipis::tokio::spawn(Self::reject(addr, send, e));
+                        }
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    async fn handle<C, F, Fut>(
-        client: Arc<C>,
+    fn rendezvous(&self) -> Result<&Rendezvous> {
+        Ok(&self.rendezvous)
+    }
+
+    /// Wait for the introductions at the rendezvous node, until the shutdown.
+    ///
+    /// Each introduced account is dialed at the same time as it dials this node,
+    /// so that both NATs let the connection through.
+    pub async fn run_rendezvous(self: Arc<Self>, rendezvous: AccountRef) -> Result<()> {
+        loop {
+            if let Err(e) = self.serve_rendezvous(&rendezvous).await {
+                warn!("the rendezvous session is closed: {e}");
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(DEFAULT_RENDEZVOUS_RETRY_INTERVAL) => continue,
+                _ = self.shutdown.wait() => break Ok(()),
+            }
+        }
+    }
+
+    async fn serve_rendezvous(self: &Arc<Self>, rendezvous: &AccountRef) -> Result<()> {
+        // open a session
+        let (mut res, session) = external_call!(
+            client: &self.client,
+            target: None => rendezvous,
+            request: ::ipiis_common::io => Rendezvous,
+            sign: self.sign(*rendezvous, None::<AccountRef>)?,
+            inputs: { },
+            outputs: open,
+        );
+        let address = res.address.to_owned().await?;
+        info!("waiting for the rendezvous: observed address={address}");
+        let (_, mut introductions) = session.require_signed(true).split();
+
+        // punch holes toward the introduced accounts
+        loop {
+            let (account, address) = tokio::select! {
+                introduction = introductions.recv() => match introduction? {
+                    Some(introduction) => introduction,
+                    None => bail!("closed by the rendezvous node"),
+                },
+                _ = self.shutdown.wait() => break Ok(()),
+            };
+            let address: SocketAddr = match address.parse() {
+                Ok(address) => address,
+                Err(e) => {
+                    warn!("malformed introduction: account={account}, {e}");
+                    continue;
+                }
+            };
+
+            let server = self.clone();
+            ::ipis::tokio::spawn(async move {
+                if server.client.punch(&account, address).await.is_some() {
+                    debug!("punched a hole: account={account}, addr={address}");
+                }
+            });
+        }
+    }
+
+    /// Listen on an ephemeral port for the relayed account, holding the incoming streams
+    /// in the relay until the account accepts them.
+    async fn allocate_relay(
+        &self,
+        relay: Arc<
+            Relay<
+                <crate::client::IpiisClient as Ipiis>::Writer,
+                <crate::client::IpiisClient as Ipiis>::Reader,
+            >,
+        >,
+        account: AccountRef,
+    ) -> Result<Allocation> {
+        let (endpoint, mut incoming) = {
+            let server_config = Self::server_config(self.account_me())?;
+            let addr = "0.0.0.0:0".parse()?;
+
+            Endpoint::server(server_config, addr)?
+        };
+        let port = endpoint.local_addr()?.port();
+
+        let stop = Shutdown::default();
+        let allocation = Allocation::new(port, stop.clone());
+        let shutdown = self.shutdown.clone();
+
+        ::ipis::tokio::spawn(async move {
+            loop {
+                let connection = tokio::select! {
+                    _ = stop.wait() => break,
+                    _ = shutdown.wait() => break,
+                    connection = incoming.next() => match connection {
+                        Some(connection) => connection,
+                        None => break,
+                    },
+                };
+
+                let relay = relay.clone();
+                let stop = stop.clone();
+                ::ipis::tokio::spawn(async move {
+                    let (addr, mut bi_streams) = match connection.await {
+                        Ok(quinn::NewConnection {
+                            connection: conn,
+                            bi_streams,
+                            ..
+                        }) => (conn.remote_address(), bi_streams),
+                        Err(e) => {
+                            warn!("incoming relayed connection error: {e}");
+                            return;
+                        }
+                    };
+                    info!("incoming relayed connection: addr={addr}, account={account}");
+
+                    // Each stream is relayed as a new request.
+                    loop {
+                        let (send, recv) = tokio::select! {
+                            _ = stop.wait() => break,
+                            stream = bi_streams.next() => match stream {
+                                Some(Ok(stream)) => stream,
+                                Some(Err(_)) | None => break,
+                            },
+                        };
+
+                        if let Err(e) = relay.enqueue(&account, send, recv) {
+                            warn!("rejecting relayed stream: addr={addr}, {e}");
+                        }
+                    }
+                });
+            }
+
+            // release the port
+            endpoint.close(CLOSE_CODE_SHUTDOWN.into(), b"shutdown");
+        });
+        Ok(allocation)
+    }
+
+    async fn reject(
         addr: SocketAddr,
-        stream: (
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ),
-        handler: F,
-    ) where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-            Arc<C>,
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ) -> Fut,
-        Fut: Future<Output = Result<()>>,
-    {
-        match Self::try_handle(client, stream, handler).await {
-            Ok(_) => (),
-            Err(e) => error!("error handling: addr={addr}, {e}"),
+        mut send: <crate::client::IpiisClient as Ipiis>::Writer,
+        error: Error,
+    ) {
+        warn!("rejecting request: addr={addr}, {error}");
+
+        let error = ServerError::from_anyhow(error);
+        if let Err(e) = error.send(&mut send).await {
+            warn!("error rejecting: addr={addr}, {e}");
         }
     }
 
-    fn try_handle<C, F, Fut>(
-        client: Arc<C>,
+    async fn handle(
+        router: Arc<Router<crate::client::IpiisClient>>,
+        addr: SocketAddr,
         (send, recv): (
             <crate::client::IpiisClient as Ipiis>::Writer,
             <crate::client::IpiisClient as Ipiis>::Reader,
         ),
-        handler: F,
-    ) -> impl Future<Output = Result<()>>
-    where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-            Arc<C>,
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ) -> Fut,
-        Fut: Future<Output = Result<()>>,
-    {
-        // handle data
-        handler(client, send, recv)
+    ) {
+        match router.handle(addr, send, recv).await {
+            Ok(_) => (),
+            Err(e) => error!("error handling: addr={addr}, {e}"),
+        }
     }
 }
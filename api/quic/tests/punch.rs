@@ -0,0 +1,103 @@
+use core::time::Duration;
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use ipiis_api_quic::{client::IpiisClient, server::IpiisServer};
+use ipiis_common::{external_call, Ipiis};
+use ipis::{
+    core::{
+        account::{Account, AccountRef},
+        anyhow::Result,
+    },
+    tokio::{self, net::UdpSocket},
+};
+
+/// A NAT in front of the target, which drops all the unsolicited datagrams.
+async fn spawn_nat(addr: SocketAddr) -> Result<Arc<AtomicUsize>> {
+    let socket = UdpSocket::bind(addr).await?;
+    let dropped = Arc::new(AtomicUsize::default());
+
+    let counter = dropped.clone();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65536];
+        while socket.recv_from(&mut buf).await.is_ok() {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    Ok(dropped)
+}
+
+/// Ask the target for the address of the rendezvous node, through the dialed connection.
+async fn get_address(
+    client: &IpiisClient,
+    target: &AccountRef,
+    account: &AccountRef,
+) -> Result<SocketAddr> {
+    let (address,) = external_call!(
+        client: client,
+        target: None => target,
+        request: ::ipiis_common::io => GetAddress,
+        sign: client.sign(*target, (None, *account))?,
+        inputs: { },
+        outputs: { address, },
+    );
+    Ok(address)
+}
+
+#[tokio::test]
+async fn test_punch_through_nat() -> Result<()> {
+    let rendezvous = Account::generate();
+    let target = Account::generate();
+    let rendezvous_ref = rendezvous.account_ref();
+    let target_ref = target.account_ref();
+    let rendezvous_addr: SocketAddr = "127.0.0.1:5108".parse()?;
+    let nat_addr: SocketAddr = "127.0.0.1:5110".parse()?;
+
+    // init the rendezvous node and the target behind the NAT
+    let dropped = spawn_nat(nat_addr).await?;
+    let rendezvous = Arc::new(IpiisServer::new(rendezvous, None, 5108).await?);
+    let target = Arc::new(IpiisServer::new(target, Some(rendezvous_ref), 5109).await?);
+    target.book().set(None, &rendezvous_ref, &rendezvous_addr)?;
+
+    tokio::spawn(rendezvous.clone().run_ipiis());
+    tokio::spawn(target.clone().run_ipiis());
+    tokio::spawn(target.clone().run_rendezvous(rendezvous_ref));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client, which knows only the address of the NAT
+    let mut client = IpiisClient::new(Account::generate(), Some(rendezvous_ref)).await?;
+    client.set_rendezvous(Some(rendezvous_ref));
+    client.book().set(None, &rendezvous_ref, &rendezvous_addr)?;
+    client.book().set(None, &target_ref, &nat_addr)?;
+
+    // punch a hole after failing to dial the registered address
+    let address = get_address(&client, &target_ref, &rendezvous_ref).await?;
+    assert_eq!(address, rendezvous_addr);
+    assert!(dropped.load(Ordering::SeqCst) > 0);
+
+    // init a client, which treats the address of the NAT as relayed
+    let relay = Account::generate().account_ref();
+    let relay_addr: SocketAddr = "127.0.0.1:5111".parse()?;
+
+    let mut client = IpiisClient::new(Account::generate(), Some(rendezvous_ref)).await?;
+    client.set_rendezvous(Some(rendezvous_ref));
+    client.set_relays(vec![relay]);
+    client.book().set(None, &relay, &relay_addr)?;
+    client.book().set(None, &rendezvous_ref, &rendezvous_addr)?;
+    client.book().set(None, &target_ref, &nat_addr)?;
+
+    // punch a hole before dialing the relayed address
+    let dropped_before = dropped.load(Ordering::SeqCst);
+    let address = get_address(&client, &target_ref, &rendezvous_ref).await?;
+    assert_eq!(address, rendezvous_addr);
+    assert_eq!(dropped.load(Ordering::SeqCst), dropped_before);
+
+    rendezvous.shutdown_handle().shutdown();
+    target.shutdown_handle().shutdown();
+    Ok(())
+}
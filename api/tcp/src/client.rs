@@ -1,19 +1,37 @@
-use ipiis_api_common::book::AddressBook;
-use ipiis_common::{external_call, Ipiis};
+use std::sync::Arc;
+
+use ipiis_api_common::{book::AddressBook, primary::infer_primaries, replication::DirectoryChange};
+use ipiis_common::{
+    balance::{Balancer, Pick, Replica, Strategy},
+    error::{ErrorKind, ServerError},
+    external_call,
+    interceptor::Interceptor,
+    payload::PayloadLimits,
+    record::{self, AddressRecord},
+    resolve::{self, PrimaryResolution, Resolution},
+    Ipiis,
+};
 use ipis::{
     async_trait::async_trait,
     core::{
         account::{Account, AccountRef},
-        anyhow::{anyhow, bail, Result},
+        anyhow::{bail, Context, Result},
         value::hash::Hash,
     },
     env::{infer, Infer},
+    futures::{Stream, StreamExt},
+    log::debug,
     tokio,
 };
 
 #[derive(Clone)]
 pub struct IpiisClient {
     pub(crate) book: AddressBook<<Self as Ipiis>::Address>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    payload_limits: PayloadLimits,
+    require_signed_records: bool,
+    balancer: Arc<Balancer>,
+    balance_strategy: Strategy,
 }
 
 #[async_trait]
@@ -23,32 +41,43 @@ impl<'a> Infer<'a> for IpiisClient {
 
     async fn try_infer() -> Result<Self> {
         let account_me = infer("ipis_account_me")?;
-        let account_primary = infer("ipiis_account_primary").ok();
+        let account_primaries = infer_primaries("ipiis_account_primary").unwrap_or_default();
 
-        Self::new(account_me, account_primary).await
+        Self::with_primaries(account_me, account_primaries).await
     }
 
     async fn genesis(
         account_primary: <Self as Infer>::GenesisArgs,
     ) -> Result<<Self as Infer<'a>>::GenesisResult> {
-        let account_primary = account_primary.or_else(|| infer("ipiis_account_primary").ok());
+        let account_primaries = match account_primary {
+            Some(account_primary) => vec![account_primary],
+            None => infer_primaries("ipiis_account_primary").unwrap_or_default(),
+        };
 
         // generate an account
         let account = Account::generate();
 
         // init an endpoint
-        Self::new(account, account_primary).await
+        Self::with_primaries(account, account_primaries).await
     }
 }
 
 impl IpiisClient {
     pub async fn new(account_me: Account, account_primary: Option<AccountRef>) -> Result<Self> {
-        Self::with_address_db_path(account_me, account_primary, "ipiis_client_address_db").await
+        Self::with_primaries(account_me, account_primary.into_iter().collect()).await
+    }
+
+    /// Create a client with the ordered primaries, which are tried in turn.
+    pub async fn with_primaries(
+        account_me: Account,
+        account_primaries: Vec<AccountRef>,
+    ) -> Result<Self> {
+        Self::with_address_db_path(account_me, account_primaries, "ipiis_client_address_db").await
     }
 
     pub(crate) async fn with_address_db_path<P>(
         account_me: Account,
-        account_primary: Option<AccountRef>,
+        account_primaries: Vec<AccountRef>,
         book_path: P,
     ) -> Result<Self>
     where
@@ -56,19 +85,58 @@ impl IpiisClient {
     {
         let client = Self {
             book: AddressBook::new(account_me, book_path)?,
+            interceptors: Default::default(),
+            payload_limits: Default::default(),
+            require_signed_records: false,
+            balancer: Default::default(),
+            balance_strategy: Default::default(),
         };
 
-        // try to add the primary account's address
-        if let Some(account_primary) = account_primary {
-            client.book.set_primary(None, &account_primary)?;
+        // try to add the primary accounts' addresses, in the same order
+        if !account_primaries.is_empty() {
+            client.book.set_primaries(None, &account_primaries)?;
 
-            if let Ok(address) = infer("ipiis_account_primary_address") {
-                client.book.set(None, &account_primary, &address)?;
+            let addresses: String = infer("ipiis_account_primary_address").unwrap_or_default();
+            for (account_primary, address) in account_primaries.iter().zip(addresses.split(',')) {
+                if let Ok(address) = address.trim().parse() {
+                    client.book.set(None, account_primary, &address)?;
+                }
             }
         }
 
         Ok(client)
     }
+
+    /// Apply the interceptor to every outgoing request, after the ones added before.
+    pub fn add_interceptor<I>(&mut self, interceptor: I)
+    where
+        I: Interceptor + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+    }
+
+    /// Set the size limits of the incoming messages.
+    pub fn set_payload_limits(&mut self, limits: PayloadLimits) {
+        self.payload_limits = limits;
+    }
+
+    /// Accept only the addresses which are signed by their accounts, except for the primaries.
+    ///
+    /// It is off by default, as the addresses which are given locally (e.g. configured by
+    /// the operator, or discovered on the LAN) are not signed. Without it, the signed records
+    /// are still preferred whenever an address is unknown or its record is no longer valid.
+    pub fn set_require_signed_records(&mut self, require: bool) {
+        self.require_signed_records = require;
+    }
+
+    /// Set how to pick one of the accounts serving a kind.
+    pub fn set_balance_strategy(&mut self, strategy: Strategy) {
+        self.balance_strategy = strategy;
+    }
+
+    pub fn book(&self) -> &AddressBook<<Self as Ipiis>::Address> {
+        &self.book
+    }
 }
 
 #[async_trait]
@@ -86,18 +154,12 @@ impl Ipiis for IpiisClient {
             Some(address) => Ok(address),
             None => match kind {
                 Some(kind) => {
-                    // next target
-                    let primary = self.get_account_primary(None).await?;
-
-                    // external call
-                    let (account, address) = external_call!(
-                        client: self,
-                        target: None => &primary,
-                        request: ::ipiis_common::io => GetAccountPrimary,
-                        sign: self.sign(primary, Some(*kind))?,
-                        inputs: { },
-                        outputs: { account, address, },
-                    );
+                    // resolve recursively
+                    let PrimaryResolution {
+                        account, address, ..
+                    } = self
+                        .resolve_account_primary(kind, &[], resolve::DEFAULT_MAX_HOPS)
+                        .await?;
 
                     // store response
                     self.book.set_primary(Some(kind), &account)?;
@@ -137,31 +199,45 @@ impl Ipiis for IpiisClient {
         kind: Option<&Hash>,
         target: &AccountRef,
     ) -> Result<<Self as Ipiis>::Address> {
-        match self.book.get(kind, target)? {
-            Some(address) => Ok(address),
-            None => match self.book.get_primary(None)? {
-                Some(primary) => {
-                    // external call
-                    let (address,) = external_call!(
-                        client: self,
-                        target: None => &primary,
-                        request: ::ipiis_common::io => GetAddress,
-                        sign: self.sign(primary, (kind.copied(), *target))?,
-                        inputs: { },
-                        outputs: { address, },
-                    );
+        // skip the accounts which are known to be dead
+        if self.book.is_dead(target) {
+            bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("the account is dead: {target}"),
+            ));
+        }
 
-                    // store response
-                    self.book.set(kind, target, &address)?;
+        // accept only the signed records, except for the primaries
+        if self.require_signed_records && !self.book.get_primaries(None)?.contains(target) {
+            let record = self.get_address_record(kind, target).await?;
+            return Ok(record.data.data.1);
+        }
 
-                    // unpack response
-                    Ok(address)
-                }
-                None => {
-                    let addr = target.to_string();
-                    bail!("failed to get address: {addr}")
+        // refresh the address if its record is no longer valid, e.g. expired
+        let address = match self.book.get_record(kind, target)? {
+            Some(record) if record::verify_record(&record, kind, target).is_err() => None,
+            _ => self.book.get(kind, target)?,
+        };
+
+        match address {
+            Some(address) => Ok(address),
+            None => {
+                // prefer the signed record
+                if let Ok(record) = self.get_address_record(kind, target).await {
+                    return Ok(record.data.data.1);
                 }
-            },
+
+                // resolve recursively
+                let Resolution { address, .. } = self
+                    .resolve(kind, target, &[], resolve::DEFAULT_MAX_HOPS)
+                    .await?;
+
+                // store response
+                self.book.set(kind, target, &address)?;
+
+                // unpack response
+                Ok(address)
+            }
         }
     }
 
@@ -189,13 +265,234 @@ impl Ipiis for IpiisClient {
         Ok(())
     }
 
+    async fn register(
+        &self,
+        kind: Option<&Hash>,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<()> {
+        let account_me = self.account_me().account_ref();
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+
+        // store locally if you are a root
+        if primaries.contains(&account_me) {
+            let record = record::sign_record(self, account_me, kind, *address)?;
+            self.book.set_record(kind, &account_me, record)?;
+            return Ok(());
+        }
+
+        // external call
+        self.book
+            .primary_health
+            .failover(primaries, |primary| async move {
+                external_call!(
+                    client: self,
+                    target: None => &primary,
+                    request: ::ipiis_common::io => Register,
+                    sign: self.sign(primary, (kind.copied(), account_me, *address))?,
+                    inputs: { },
+                );
+
+                // publish the signed record
+                external_call!(
+                    client: self,
+                    target: None => &primary,
+                    request: ::ipiis_common::io => PublishRecord,
+                    sign: record::sign_record(self, primary, kind, *address)?,
+                    inputs: { },
+                );
+                Ok(())
+            })
+            .await
+    }
+
+    async fn resolve(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<Resolution<<Self as Ipiis>::Address>> {
+        let path = resolve::visit(path, self.account_me().account_ref())?;
+
+        // find locally
+        if let Some(address) = self.book.get(kind, target)? {
+            return Ok(Resolution { address, path });
+        }
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+        if primaries.is_empty() {
+            bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no primary for {target}: {}", resolve::display_path(&path)),
+            ));
+        }
+        resolve::ensure_hops(&path, hops)?;
+
+        // external call
+        let (address, path) = self
+            .book
+            .primary_health
+            .failover(primaries, |primary| {
+                let path = path.clone();
+                async move {
+                    Ok(external_call!(
+                        client: self,
+                        target: None => &primary,
+                        request: ::ipiis_common::io => Resolve,
+                        sign: self.sign(primary, (kind.copied(), *target, path, hops - 1))?,
+                        inputs: { },
+                        outputs: { address, path, },
+                    ))
+                }
+            })
+            .await?;
+
+        Ok(Resolution { address, path })
+    }
+
+    async fn get_account_replicas(&self, kind: Option<&Hash>) -> Result<Vec<Replica>> {
+        // find locally
+        let replicas = self.book.get_replicas(kind)?;
+        if !replicas.is_empty() || kind.is_none() {
+            return Ok(replicas);
+        }
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+        if primaries.contains(&self.account_me().account_ref()) {
+            return Ok(replicas);
+        }
+
+        // external call
+        let replicas = self
+            .book
+            .primary_health
+            .failover(primaries, |primary| async move {
+                let (replicas,) = external_call!(
+                    client: self,
+                    target: None => &primary,
+                    request: ::ipiis_common::io => GetAccountReplicas,
+                    sign: self.sign(primary, kind.copied())?,
+                    inputs: { },
+                    outputs: { replicas, },
+                );
+                Ok(replicas)
+            })
+            .await?;
+
+        // store response
+        self.book.set_replicas(kind, &replicas)?;
+
+        // unpack response
+        Ok(replicas)
+    }
+
+    async fn register_replica(&self, kind: &Hash, weight: u32) -> Result<()> {
+        let account_me = self.account_me().account_ref();
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+
+        // store locally if you are a root
+        if primaries.contains(&account_me) {
+            return self.book.set_replica(Some(kind), &account_me, weight);
+        }
+
+        // external call
+        self.book
+            .primary_health
+            .failover(primaries, |primary| async move {
+                external_call!(
+                    client: self,
+                    target: None => &primary,
+                    request: ::ipiis_common::io => RegisterReplica,
+                    sign: self.sign(primary, (*kind, account_me, weight))?,
+                    inputs: { },
+                );
+                Ok(())
+            })
+            .await
+    }
+
+    async fn pick(&self, kind: Option<&Hash>, key: &[u8]) -> Result<Pick> {
+        // skip the accounts which are known to be dead
+        let replicas: Vec<_> = self
+            .get_account_replicas(kind)
+            .await?
+            .into_iter()
+            .filter(|(account, _)| !self.book.is_dead(account))
+            .collect();
+
+        let strategy = self.balance_strategy;
+        match self.balancer.pick(strategy, kind, &replicas, key) {
+            Some(pick) => Ok(pick),
+            None => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no accounts serving the kind: {kind:?}"),
+            )),
+        }
+    }
+
+    fn interceptors(&self) -> &[Arc<dyn Interceptor>] {
+        &self.interceptors
+    }
+
+    fn payload_limits(&self) -> PayloadLimits {
+        self.payload_limits
+    }
+
     async fn call_raw(
         &self,
         kind: Option<&Hash>,
         target: &AccountRef,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        let error = match self.call_direct(kind, target).await {
+            Ok(stream) => return Ok(stream),
+            Err(error) => error,
+        };
+
+        // forward only if the target cannot be connected (e.g. behind NAT),
+        // or cannot be resolved (e.g. a leaf behind a gateway)
+        let is_unreachable = error.downcast_ref::<::std::io::Error>().is_some()
+            || matches!(
+                error.downcast_ref::<ServerError>(),
+                Some(error) if error.kind == ErrorKind::Unresolvable
+            );
+        if !is_unreachable {
+            return Err(error);
+        }
+
+        // the primaries should be reachable directly
+        if self.book.get_primaries(None)?.contains(target) {
+            return Err(error);
+        }
+
+        // ask the primaries to forward the stream
+        match self
+            .forward(kind, target, &[], resolve::DEFAULT_MAX_HOPS)
+            .await
+        {
+            Ok((stream, path)) => {
+                debug!("forwarded: {}", resolve::display_path(&path));
+                Ok(stream)
+            }
+            Err(e) => {
+                debug!("failed to forward to {target}: {e}");
+                Err(error)
+            }
+        }
+    }
+
+    async fn call_raw_at(
+        &self,
+        _target: &AccountRef,
+        address: &<Self as Ipiis>::Address,
     ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
         // connect to the target
-        let conn = self.get_connection(kind, target).await?;
+        let conn = self.connect(*address).await?;
 
         // open stream
         let (recv, send) = tokio::io::split(conn);
@@ -206,17 +503,218 @@ impl Ipiis for IpiisClient {
 }
 
 impl IpiisClient {
+    /// Open a stream to the target, which is connected directly.
+    pub(crate) async fn call_direct(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        // connect to the target
+        let conn = self.get_connection(kind, target).await?;
+
+        // open stream
+        let (recv, send) = tokio::io::split(conn);
+
+        // send data
+        Ok((send, recv))
+    }
+
+    /// Resolve the primary of the kind recursively through the chain of primaries.
+    ///
+    /// The visited accounts are carried along, so that a cycle between the primaries
+    /// is rejected instead of being followed forever.
+    pub async fn resolve_account_primary(
+        &self,
+        kind: &Hash,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<PrimaryResolution<<Self as Ipiis>::Address>> {
+        let path = resolve::visit(path, self.account_me().account_ref())?;
+
+        // find locally
+        if let Some(account) = self.book.get_primary(Some(kind))? {
+            let address = self.book.get(Some(kind), &account)?;
+            return Ok(PrimaryResolution {
+                account,
+                address,
+                path,
+            });
+        }
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+        if primaries.is_empty() {
+            bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no primary for {kind:?}: {}", resolve::display_path(&path)),
+            ));
+        }
+        resolve::ensure_hops(&path, hops)?;
+
+        // external call
+        let (account, address, path) = self
+            .book
+            .primary_health
+            .failover(primaries, |primary| {
+                let path = path.clone();
+                async move {
+                    Ok(external_call!(
+                        client: self,
+                        target: None => &primary,
+                        request: ::ipiis_common::io => ResolveAccountPrimary,
+                        sign: self.sign(primary, (*kind, path, hops - 1))?,
+                        inputs: { },
+                        outputs: { account, address, path, },
+                    ))
+                }
+            })
+            .await?;
+
+        Ok(PrimaryResolution {
+            account,
+            address,
+            path,
+        })
+    }
+
+    /// Open a stream to the target through the primaries, which forward it hop by hop
+    /// until an account can connect to the target directly.
+    ///
+    /// Returns the stream along with the visited accounts, from this node to the last hop.
+    pub async fn forward(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<(
+        (<Self as Ipiis>::Writer, <Self as Ipiis>::Reader),
+        Vec<AccountRef>,
+    )> {
+        let path = resolve::visit(path, self.account_me().account_ref())?;
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+        if primaries.is_empty() {
+            bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no primary for {target}: {}", resolve::display_path(&path)),
+            ));
+        }
+        resolve::ensure_hops(&path, hops)?;
+
+        // external call
+        self.book
+            .primary_health
+            .failover(primaries, |primary| {
+                let path = path.clone();
+                async move {
+                    let (mut res, session) = external_call!(
+                        client: self,
+                        target: None => &primary,
+                        request: ::ipiis_common::io => Forward,
+                        sign: self.sign(primary, (kind.copied(), *target, path, hops - 1))?,
+                        inputs: { },
+                        outputs: open,
+                    );
+                    let path = res.path.to_owned().await?;
+                    Ok((session.into_inner(), path))
+                }
+            })
+            .await
+    }
+
+    /// Subscribe the changes of the entry from the primary, applying them to the address book.
+    ///
+    /// The entry is the primaries of the kind if `account` is `None`.
+    /// The stream yields the applied changes, beginning with the current state of the entry.
+    pub async fn watch(
+        &self,
+        kind: Option<&Hash>,
+        account: Option<&AccountRef>,
+    ) -> Result<impl Stream<Item = Result<DirectoryChange>> + '_> {
+        // next target
+        let primary = match self.book.get_primary(None)? {
+            Some(primary) => primary,
+            None => bail!("failed to get primary address"),
+        };
+
+        // external call
+        let (_, session) = external_call!(
+            client: self,
+            target: None => &primary,
+            request: ::ipiis_common::io => Watch,
+            sign: self.sign(primary, (kind.copied(), account.copied()))?,
+            inputs: { },
+            outputs: open,
+        );
+        let (_, receiver) = session.require_signed(true).split();
+
+        // apply the changes
+        Ok(receiver.into_stream().map(move |change| {
+            let change = change?;
+            self.book.apply(change.clone())?;
+            Ok(change)
+        }))
+    }
+
+    /// Get the address record of the target, which is verified to be signed by the target itself.
+    pub async fn get_address_record(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<AddressRecord<<Self as Ipiis>::Address>> {
+        // find locally
+        if let Some(record) = self.book.get_record(kind, target)? {
+            if record::verify_record(&record, kind, target).is_ok() {
+                return Ok(record);
+            }
+        }
+
+        // next targets
+        let primaries = self.book.get_primaries(None)?;
+
+        // external call
+        let record = self
+            .book
+            .primary_health
+            .failover(primaries, |primary| async move {
+                let (record,) = external_call!(
+                    client: self,
+                    target: None => &primary,
+                    request: ::ipiis_common::io => GetAddressRecord,
+                    sign: self.sign(primary, (kind.copied(), *target))?,
+                    inputs: { },
+                    outputs: { record, },
+                );
+
+                // verify response
+                record::verify_record(&record, kind, target)?;
+                Ok(record)
+            })
+            .await?;
+
+        // store response
+        self.book.set_record(kind, target, record.clone())?;
+
+        // unpack response
+        Ok(record)
+    }
+
     async fn get_connection(
         &self,
         kind: Option<&Hash>,
         target: &AccountRef,
     ) -> Result<tokio::net::TcpStream> {
         let addr = self.get_address(kind, target).await?;
+        self.connect(addr).await
+    }
 
+    async fn connect(&self, addr: <Self as Ipiis>::Address) -> Result<tokio::net::TcpStream> {
         let new_conn = tokio::net::TcpSocket::new_v4()?
             .connect(addr)
             .await
-            .map_err(|e| anyhow!("failed to connect: {e}"))?;
+            .with_context(|| format!("failed to connect: {addr}"))?;
 
         Ok(new_conn)
     }
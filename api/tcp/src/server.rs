@@ -1,15 +1,30 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
-use ipiis_api_common::impl_ipiis_server;
-use ipiis_common::Ipiis;
+use ipiis_api_common::{
+    forward::ForwardAccounting,
+    impl_ipiis_server,
+    limits::{Limiter, Limits},
+    policy::{AuthorizationPolicy, DirectoryPolicy},
+    primary::infer_primaries,
+    rate_limit::{infer_quota, RateLimiter},
+    relay::{Allocation, Relay},
+    rendezvous::Rendezvous,
+    shutdown::{Drain, Shutdown, DEFAULT_DRAIN_TIMEOUT},
+};
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::Interceptor,
+    router::Router,
+    Ipiis,
+};
 use ipis::{
     async_trait::async_trait,
     core::{
         account::{Account, AccountRef},
-        anyhow::Result,
+        anyhow::{bail, Error, Result},
     },
     env::{infer, Infer},
-    futures::Future,
+    futures::{future, Future},
     log::{error, info, warn},
     tokio,
 };
@@ -18,7 +33,22 @@ impl_ipiis_server!(client: crate::client::IpiisClient, server: IpiisServer,);
 
 pub struct IpiisServer {
     pub(crate) client: crate::client::IpiisClient,
+    forwarding: ForwardAccounting,
+    interceptors: Vec<Arc<dyn Interceptor>>,
     incoming: tokio::net::TcpListener,
+    limits: Limits,
+    policy: Arc<dyn AuthorizationPolicy>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    relay: Option<
+        Arc<
+            Relay<
+                <crate::client::IpiisClient as Ipiis>::Writer,
+                <crate::client::IpiisClient as Ipiis>::Reader,
+            >,
+        >,
+    >,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
 }
 
 impl ::core::ops::Deref for IpiisServer {
@@ -36,10 +66,10 @@ impl<'a> Infer<'a> for IpiisServer {
 
     async fn try_infer() -> Result<Self> {
         let account_me = infer("ipis_account_me")?;
-        let account_primary = infer("ipiis_account_primary").ok();
+        let account_primaries = infer_primaries("ipiis_account_primary").unwrap_or_default();
         let account_port = infer("ipiis_server_port")?;
 
-        Self::new(account_me, account_primary, account_port).await
+        Self::with_primaries(account_me, account_primaries, account_port).await
     }
 
     async fn genesis(
@@ -47,10 +77,10 @@ impl<'a> Infer<'a> for IpiisServer {
     ) -> Result<<Self as Infer<'a>>::GenesisResult> {
         // generate an account
         let account = Account::generate();
-        let account_primary = infer("ipiis_account_primary").ok();
+        let account_primaries = infer_primaries("ipiis_account_primary").unwrap_or_default();
 
         // init a server
-        let server = Self::new(account, account_primary, port).await?;
+        let server = Self::with_primaries(account, account_primaries, port).await?;
 
         Ok(server)
     }
@@ -61,6 +91,15 @@ impl IpiisServer {
         account_me: Account,
         account_primary: Option<AccountRef>,
         port: u16,
+    ) -> Result<Self> {
+        Self::with_primaries(account_me, account_primary.into_iter().collect(), port).await
+    }
+
+    /// Create a server with the ordered primaries, which are tried in turn.
+    pub async fn with_primaries(
+        account_me: Account,
+        account_primaries: Vec<AccountRef>,
+        port: u16,
     ) -> Result<Self> {
         let incoming = {
             let addr: SocketAddr = format!("0.0.0.0:{port}").parse()?;
@@ -71,91 +110,202 @@ impl IpiisServer {
         Ok(Self {
             client: crate::client::IpiisClient::with_address_db_path(
                 account_me,
-                account_primary,
+                account_primaries,
                 "ipiis_server_address_db",
             )
             .await?,
+            forwarding: Default::default(),
+            interceptors: Default::default(),
             incoming,
+            limits: Default::default(),
+            policy: Arc::new(DirectoryPolicy::default()),
+            rate_limiter: infer_quota("ipiis_server_rate_limit")
+                .ok()
+                .map(RateLimiter::new)
+                .map(Arc::new),
+            relay: None,
+            shutdown: Default::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
         })
     }
 
-    pub async fn run<C, F, Fut>(&self, client: Arc<C>, handler: F)
+    /// Run the server until the shutdown handle is triggered.
+    pub async fn run(&self, router: Arc<Router<crate::client::IpiisClient>>) -> Result<()> {
+        self.run_until(router, future::pending()).await
+    }
+
+    /// Run the server until either the signal or the shutdown handle is triggered.
+    ///
+    /// Then it refuses the new requests, waits for the in-flight requests within the drain
+    /// timeout, and flushes the address book.
+    pub async fn run_until<F>(
+        &self,
+        router: Arc<Router<crate::client::IpiisClient>>,
+        signal: F,
+    ) -> Result<()>
     where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-                Arc<C>,
-                <crate::client::IpiisClient as Ipiis>::Writer,
-                <crate::client::IpiisClient as Ipiis>::Reader,
-            ) -> Fut
-            + Copy
-            + Send
-            + 'static,
-        Fut: Future<Output = Result<()>> + Send,
+        F: Future<Output = ()>,
     {
+        let drain = Drain::default();
+
+        // Each connection serves a single request.
+        let connections = Limiter::new(self.limits.max_connections);
+        let in_flight = Limiter::new(self.limits.max_in_flight);
+
+        let signal = future::select(Box::pin(signal), Box::pin(self.shutdown.wait()));
+        tokio::pin!(signal);
+
         loop {
-            match self.incoming.accept().await {
-                Ok((stream, addr)) => {
-                    info!("incoming connection: addr={addr}");
+            tokio::select! {
+                _ = &mut signal => break,
+                incoming = self.incoming.accept() => match incoming {
+                    Ok((stream, addr)) => {
+                        info!("incoming connection: addr={addr}");
 
-                    {
                         // Each stream initiated by the client constitutes a new request.
-                        let client = client.clone();
-
                         let (recv, send) = tokio::io::split(stream);
 
-                        ::ipis::tokio::spawn(async move {
-                            Self::handle(client, addr, (send, recv), handler).await
-                        });
+                        // admit the request
+                        let permits = connections
+                            .try_acquire("connections")
+                            .and_then(|c| Ok((c, in_flight.try_acquire("in-flight requests")?)));
+
+                        match permits {
+                            Ok(permits) => {
+                                let router = router.clone();
+                                let guard = drain.guard();
+
+                                ::ipis::tokio::spawn(async move {
+                                    Self::handle(router, addr, (send, recv)).await;
+                                    drop((permits, guard))
+                                });
+                            }
+                            Err(e) => {
+                                ::ipis::tokio::spawn(Self::reject(addr, send, e));
+                            }
+                        }
                     }
+                    Err(e) => {
+                        warn!("incoming connection error: {e}");
+                    }
+                },
+            }
+        }
+
+        // end the sessions (e.g. watching and relaying), even if stopped by the signal
+        self.shutdown.shutdown();
+
+        // wait for the in-flight requests
+        info!("shutting down: draining the in-flight requests");
+        let drained = tokio::time::timeout(self.drain_timeout, drain.wait());
+        tokio::pin!(drained);
+
+        loop {
+            tokio::select! {
+                drained = &mut drained => {
+                    if drained.is_err() {
+                        warn!(
+                            "shutting down: drain timeout is reached: {:?}",
+                            self.drain_timeout,
+                        );
+                    }
+                    break;
                 }
-                Err(e) => {
-                    warn!("incoming connection error: {e}");
-                }
+                // refuse the new requests while draining
+                incoming = self.incoming.accept() => if let Ok((stream, addr)) = incoming {
+                    let (_, send) = tokio::io::split(stream);
+                    let error = ServerError::new(ErrorKind::Overloaded, "shutting down");
+                    ::ipis::tokio::spawn(Self::reject(addr, send, error.into()));
+                },
             }
         }
+
+        // flush the address book
+        self.client.book.flush()?;
+
+        info!("shutting down: done");
+        Ok(())
+    }
+
+    /// The holes cannot be punched through the NATs over TCP, so the introductions are rejected.
+    fn rendezvous(&self) -> Result<&Rendezvous> {
+        bail!(ServerError::new(
+            ErrorKind::UnknownOpcode,
+            "the rendezvous is not served over TCP",
+        ))
+    }
+
+    /// Listen on an ephemeral port for the relayed account, holding the incoming streams
+    /// in the relay until the account accepts them.
+    async fn allocate_relay(
+        &self,
+        relay: Arc<
+            Relay<
+                <crate::client::IpiisClient as Ipiis>::Writer,
+                <crate::client::IpiisClient as Ipiis>::Reader,
+            >,
+        >,
+        account: AccountRef,
+    ) -> Result<Allocation> {
+        let incoming = {
+            let addr: SocketAddr = "0.0.0.0:0".parse()?;
+
+            tokio::net::TcpListener::bind(addr).await?
+        };
+        let port = incoming.local_addr()?.port();
+
+        let stop = Shutdown::default();
+        let allocation = Allocation::new(port, stop.clone());
+        let shutdown = self.shutdown.clone();
+
+        ::ipis::tokio::spawn(async move {
+            loop {
+                let (stream, addr) = tokio::select! {
+                    _ = stop.wait() => break,
+                    _ = shutdown.wait() => break,
+                    incoming = incoming.accept() => match incoming {
+                        Ok(incoming) => incoming,
+                        Err(e) => {
+                            warn!("incoming relayed connection error: {e}");
+                            continue;
+                        }
+                    },
+                };
+                info!("incoming relayed connection: addr={addr}, account={account}");
+
+                let (recv, send) = tokio::io::split(stream);
+                if let Err(e) = relay.enqueue(&account, send, recv) {
+                    warn!("rejecting relayed connection: addr={addr}, {e}");
+                }
+            }
+        });
+        Ok(allocation)
     }
 
-    async fn handle<C, F, Fut>(
-        client: Arc<C>,
+    async fn reject(
         addr: SocketAddr,
-        stream: (
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ),
-        handler: F,
-    ) where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-            Arc<C>,
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ) -> Fut,
-        Fut: Future<Output = Result<()>>,
-    {
-        match Self::try_handle(client, stream, handler).await {
-            Ok(_) => (),
-            Err(e) => error!("error handling: addr={addr}, {e}"),
+        mut send: <crate::client::IpiisClient as Ipiis>::Writer,
+        error: Error,
+    ) {
+        warn!("rejecting request: addr={addr}, {error}");
+
+        let error = ServerError::from_anyhow(error);
+        if let Err(e) = error.send(&mut send).await {
+            warn!("error rejecting: addr={addr}, {e}");
         }
     }
 
-    fn try_handle<C, F, Fut>(
-        client: Arc<C>,
+    async fn handle(
+        router: Arc<Router<crate::client::IpiisClient>>,
+        addr: SocketAddr,
         (send, recv): (
             <crate::client::IpiisClient as Ipiis>::Writer,
             <crate::client::IpiisClient as Ipiis>::Reader,
         ),
-        handler: F,
-    ) -> impl Future<Output = Result<()>>
-    where
-        C: AsRef<crate::client::IpiisClient> + Send + Sync + 'static,
-        F: Fn(
-            Arc<C>,
-            <crate::client::IpiisClient as Ipiis>::Writer,
-            <crate::client::IpiisClient as Ipiis>::Reader,
-        ) -> Fut,
-        Fut: Future<Output = Result<()>>,
-    {
-        // handle data
-        handler(client, send, recv)
+    ) {
+        match router.handle(addr, send, recv).await {
+            Ok(_) => (),
+            Err(e) => error!("error handling: addr={addr}, {e}"),
+        }
     }
 }
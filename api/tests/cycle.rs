@@ -0,0 +1,87 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, Ipiis,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::{
+        account::{Account, AccountRef},
+        anyhow::{Error, Result},
+        value::hash::Hash,
+    },
+    env::Infer,
+    tokio,
+};
+
+/// Ask the primary of the kind in the legacy way, which carries no path.
+async fn get_account_primary(
+    client: &IpiisClient,
+    target: &AccountRef,
+    kind: &Hash,
+) -> Result<AccountRef> {
+    let (account, _) = external_call!(
+        client: client,
+        target: None => target,
+        request: ::ipiis_api::common::io => GetAccountPrimary,
+        sign: client.sign(*target, Some(*kind))?,
+        inputs: { },
+        outputs: { account, address, },
+    );
+    Ok(account)
+}
+
+fn assert_unresolvable(error: Error) {
+    let error = error.downcast::<ServerError>().unwrap();
+    assert_eq!(error.kind, ErrorKind::Unresolvable);
+}
+
+#[tokio::test]
+async fn test_primary_cycle() -> Result<()> {
+    // init two primaries, which refer to each other
+    let alice = Account::generate();
+    let bob = Account::generate();
+    let alice_ref = alice.account_ref();
+    let bob_ref = bob.account_ref();
+    let alice_addr = "127.0.0.1:5106".parse()?;
+    let bob_addr = "127.0.0.1:5107".parse()?;
+
+    let alice = Arc::new(IpiisServer::with_primaries(alice, vec![bob_ref], 5106).await?);
+    let bob = Arc::new(IpiisServer::with_primaries(bob, vec![alice_ref], 5107).await?);
+    alice.book().set(None, &bob_ref, &bob_addr)?;
+    bob.book().set(None, &alice_ref, &alice_addr)?;
+
+    tokio::spawn(alice.clone().run_ipiis());
+    tokio::spawn(bob.clone().run_ipiis());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::new(Account::generate(), Some(alice_ref)).await?;
+    client.book().set(None, &alice_ref, &alice_addr)?;
+
+    let kind = Hash::with_str("unknown");
+    let timeout = Duration::from_secs(10);
+
+    // reject the cycle instead of following it forever
+    let error = tokio::time::timeout(timeout, client.get_account_primary(Some(&kind)))
+        .await?
+        .unwrap_err();
+    assert_unresolvable(error);
+
+    // as well as for the legacy requests
+    let error = tokio::time::timeout(timeout, get_account_primary(&client, &alice_ref, &kind))
+        .await?
+        .unwrap_err();
+    assert_unresolvable(error);
+
+    // resolve the kind once the primary is known
+    let carol = Account::generate().account_ref();
+    bob.book().set_primary(Some(&kind), &carol)?;
+    assert_eq!(client.get_account_primary(Some(&kind)).await?, carol);
+    Ok(())
+}
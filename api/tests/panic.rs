@@ -0,0 +1,128 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, handle_external_call, Ipiis, CLIENT_DUMMY,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::anyhow::{Error, Result},
+    env::Infer,
+    tokio,
+};
+
+mod io {
+    use ipiis_api::common::{define_io, Ipiis, ServerResult};
+    use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+    define_io! {
+        service: "panicky",
+        version: 1,
+        Panic = 1 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: { },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+        Ping = 2 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: { },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+    }
+}
+
+struct PanickyServer {
+    client: Arc<IpiisServer>,
+}
+
+handle_external_call!(
+    server: PanickyServer => IpiisServer,
+    name: run,
+    request: crate::io => {
+        Panic => handle_panic,
+        Ping => handle_ping,
+    },
+);
+
+impl PanickyServer {
+    async fn handle_panic(
+        _client: &IpiisServer,
+        _req: crate::io::request::Panic<'static>,
+    ) -> Result<crate::io::response::Panic<'static>> {
+        panic!("the secret details of a bug")
+    }
+
+    async fn handle_ping(
+        client: &IpiisServer,
+        req: crate::io::request::Ping<'static>,
+    ) -> Result<crate::io::response::Ping<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(crate::io::response::Ping {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_handler_panic() -> Result<()> {
+    // init a server
+    let server = PanickyServer {
+        client: IpiisServer::genesis(5103).await?.into(),
+    };
+    let account = server.client.account_me().account_ref();
+    tokio::spawn(server.run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client
+        .set_address(None, &account, &"127.0.0.1:5103".parse()?)
+        .await?;
+
+    // isolate the panic as an internal error, hiding its details
+    let error = async {
+        external_call!(
+            client: &client,
+            target: None => &account,
+            request: crate::io => Panic,
+            sign: client.sign(account, CLIENT_DUMMY)?,
+            inputs: { },
+            outputs: { },
+        );
+        Ok::<_, Error>(())
+    }
+    .await
+    .unwrap_err();
+
+    let error = error.downcast::<ServerError>()?;
+    assert_eq!(error.kind, ErrorKind::Internal);
+    assert!(!error.message.contains("secret"));
+
+    // keep serving the other requests
+    for _ in 0..2 {
+        external_call!(
+            client: &client,
+            target: None => &account,
+            request: crate::io => Ping,
+            sign: client.sign(account, CLIENT_DUMMY)?,
+            inputs: { },
+            outputs: { },
+        );
+    }
+    Ok(())
+}
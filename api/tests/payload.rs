@@ -0,0 +1,122 @@
+use core::time::Duration;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, handle_external_call,
+        payload::PayloadLimits,
+        Ipiis, CLIENT_DUMMY,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::{
+        account::AccountRef,
+        anyhow::{Error, Result},
+    },
+    env::Infer,
+    tokio,
+};
+
+mod io {
+    use ipiis_api::common::{define_io, Ipiis, ServerResult};
+    use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+    define_io! {
+        service: "echo",
+        version: 1,
+        Echo = 1 {
+            inputs: {
+                data: Vec<u8>,
+            },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: {
+                data: Vec<u8>,
+            },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+    }
+}
+
+struct EchoServer {
+    client: std::sync::Arc<IpiisServer>,
+}
+
+handle_external_call!(
+    server: EchoServer => IpiisServer,
+    name: run,
+    request: crate::io => {
+        Echo => handle_echo,
+    },
+);
+
+impl EchoServer {
+    async fn handle_echo(
+        client: &IpiisServer,
+        req: crate::io::request::Echo<'static>,
+    ) -> Result<crate::io::response::Echo<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let data = req.data.into_owned().await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(crate::io::response::Echo {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            data: ::ipis::stream::DynStream::Owned(data),
+        })
+    }
+}
+
+async fn echo(client: &IpiisClient, target: &AccountRef, data: Vec<u8>) -> Result<Vec<u8>> {
+    let (data,) = external_call!(
+        client: client,
+        target: None => target,
+        request: crate::io => Echo,
+        sign: client.sign(*target, CLIENT_DUMMY)?,
+        inputs: {
+            data: data,
+        },
+        outputs: { data, },
+    );
+    Ok(data)
+}
+
+#[tokio::test]
+async fn test_payload_limits() -> Result<()> {
+    // init a server with the small limits
+    let mut server = IpiisServer::genesis(5105).await?;
+    server.set_payload_limits(PayloadLimits {
+        max_field_size: 1_024,
+        ..Default::default()
+    });
+
+    let server = EchoServer {
+        client: server.into(),
+    };
+    let account = server.client.account_me().account_ref();
+    tokio::spawn(server.run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client
+        .set_address(None, &account, &"127.0.0.1:5105".parse()?)
+        .await?;
+
+    // accept the fields within the limits
+    assert_eq!(echo(&client, &account, vec![42; 16]).await?, vec![42; 16]);
+
+    // reject the oversized field
+    let error: Error = echo(&client, &account, vec![42; 4_096]).await.unwrap_err();
+    let error = error.downcast::<ServerError>()?;
+    assert_eq!(error.kind, ErrorKind::PayloadTooLarge);
+    Ok(())
+}
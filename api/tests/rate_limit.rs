@@ -0,0 +1,60 @@
+use core::time::Duration;
+use std::{net::SocketAddr, sync::Arc};
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, Ipiis,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::{account::AccountRef, anyhow::Result},
+    env::Infer,
+    tokio,
+};
+
+async fn get_address(client: &IpiisClient, target: &AccountRef) -> Result<SocketAddr> {
+    let (address,) = external_call!(
+        client: client,
+        target: None => target,
+        request: ::ipiis_api::common::io => GetAddress,
+        sign: client.sign(*target, (None, *target))?,
+        inputs: { },
+        outputs: { address, },
+    );
+    Ok(address)
+}
+
+#[tokio::test]
+async fn test_rate_limit() -> Result<()> {
+    // init a server, accepting a request per second of each account
+    ::std::env::set_var("ipiis_server_rate_limit", "1");
+    let server = Arc::new(IpiisServer::genesis(5114).await?);
+    let target = server.account_me().account_ref();
+    let address: SocketAddr = "127.0.0.1:5114".parse()?;
+    server.book().set(None, &target, &address)?;
+
+    tokio::spawn(server.clone().run_ipiis());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client.set_address(None, &target, &address).await?;
+
+    // reject the burst over the wire
+    assert_eq!(get_address(&client, &target).await?, address);
+    let error = get_address(&client, &target).await.unwrap_err();
+    assert_eq!(
+        error.downcast::<ServerError>()?.kind,
+        ErrorKind::RateLimited
+    );
+
+    // accept the request again after refilled
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(get_address(&client, &target).await?, address);
+
+    server.shutdown_handle().shutdown();
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, handle_external_call, Ipiis, CLIENT_DUMMY,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::anyhow::{Error, Result},
+    env::Infer,
+    tokio,
+};
+
+macro_rules! define_service {
+    ( $name:ident, $service:literal ) => {
+        mod $name {
+            use ipiis_api::common::{define_io, Ipiis, ServerResult};
+            use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+            define_io! {
+                service: $service,
+                version: 1,
+                Ping = 1 {
+                    inputs: { },
+                    input_sign: GuaranteeSigned<u8>,
+                    outputs: {
+                        msg: String,
+                    },
+                    output_sign: GuarantorSigned<u8>,
+                    generics: { },
+                },
+            }
+        }
+    };
+    ( $name:ident, $service:literal, $server:ident ) => {
+        define_service!($name, $service);
+
+        struct $server;
+
+        handle_external_call!(
+            server: $server => IpiisServer,
+            request: crate::$name::io => {
+                Ping => handle_ping,
+            },
+        );
+
+        impl $server {
+            async fn handle_ping(
+                client: &IpiisServer,
+                req: crate::$name::io::request::Ping<'static>,
+            ) -> Result<crate::$name::io::response::Ping<'static>> {
+                // unpack sign
+                let sign_as_guarantee = req.__sign.into_owned().await?;
+
+                // sign data
+                let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+                // pack data
+                Ok(crate::$name::io::response::Ping {
+                    __lifetime: Default::default(),
+                    __sign: ::ipis::stream::DynStream::Owned(sign),
+                    msg: ::ipis::stream::DynStream::Owned($service.to_string()),
+                })
+            }
+        }
+    };
+}
+
+define_service!(alpha, "alpha", AlphaServer);
+define_service!(beta, "beta", BetaServer);
+define_service!(gamma, "gamma");
+
+#[tokio::test]
+async fn test_router() -> Result<()> {
+    // host two services on a server
+    let server = Arc::new(IpiisServer::genesis(5102).await?);
+    let account = server.account_me().account_ref();
+
+    let mut router = server.router();
+    AlphaServer::register_service::<IpiisClient>(server.clone(), &mut router)?;
+    BetaServer::register_service::<IpiisClient>(server.clone(), &mut router)?;
+    assert!(AlphaServer::register_service::<IpiisClient>(server.clone(), &mut router).is_err());
+
+    tokio::spawn(async move { server.run(Arc::new(router)).await });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client
+        .set_address(None, &account, &"127.0.0.1:5102".parse()?)
+        .await?;
+
+    // route the requests by their services
+    let (msg,) = external_call!(
+        client: &client,
+        target: None => &account,
+        request: crate::alpha::io => Ping,
+        sign: client.sign(account, CLIENT_DUMMY)?,
+        inputs: { },
+        outputs: { msg, },
+    );
+    assert_eq!(msg, "alpha");
+
+    let (msg,) = external_call!(
+        client: &client,
+        target: None => &account,
+        request: crate::beta::io => Ping,
+        sign: client.sign(account, CLIENT_DUMMY)?,
+        inputs: { },
+        outputs: { msg, },
+    );
+    assert_eq!(msg, "beta");
+
+    // reject the services which are not hosted
+    let error = async {
+        external_call!(
+            client: &client,
+            target: None => &account,
+            request: crate::gamma::io => Ping,
+            sign: client.sign(account, CLIENT_DUMMY)?,
+            inputs: { },
+            outputs: { msg, },
+        );
+        Ok::<_, Error>(())
+    }
+    .await
+    .unwrap_err();
+    assert_eq!(
+        error.downcast::<ServerError>()?.kind,
+        ErrorKind::UnknownService
+    );
+    Ok(())
+}
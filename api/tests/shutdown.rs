@@ -0,0 +1,127 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, handle_external_call, Ipiis, CLIENT_DUMMY,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::{account::AccountRef, anyhow::Result},
+    env::Infer,
+    tokio,
+};
+
+mod io {
+    use ipiis_api::common::{define_io, Ipiis, ServerResult};
+    use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+    define_io! {
+        service: "slow",
+        version: 1,
+        Sleep = 1 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u64>,
+            outputs: { },
+            output_sign: GuarantorSigned<u64>,
+            generics: { },
+        },
+    }
+}
+
+struct SlowServer;
+
+handle_external_call!(
+    server: SlowServer => IpiisServer,
+    request: crate::io => {
+        Sleep => handle_sleep,
+    },
+);
+
+impl SlowServer {
+    async fn handle_sleep(
+        client: &IpiisServer,
+        req: crate::io::request::Sleep<'static>,
+    ) -> Result<crate::io::response::Sleep<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // handle data
+        tokio::time::sleep(Duration::from_millis(sign_as_guarantee.data.data)).await;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(crate::io::response::Sleep {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+}
+
+async fn sleep(client: &IpiisClient, target: &AccountRef, millis: u64) -> Result<()> {
+    external_call!(
+        client: client,
+        target: None => target,
+        request: crate::io => Sleep,
+        sign: client.sign(*target, millis)?,
+        inputs: { },
+        outputs: { },
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown() -> Result<()> {
+    // init a server
+    let server = Arc::new(IpiisServer::genesis(5104).await?);
+    let account = server.account_me().account_ref();
+    let shutdown = server.shutdown_handle();
+
+    let mut router = server.router();
+    SlowServer::register_service::<IpiisClient>(server.clone(), &mut router)?;
+
+    // keep an entry in the book
+    let peer = IpiisClient::genesis(None).await?.account_me().account_ref();
+    let peer_addr = "127.0.0.1:5999".parse()?;
+    server.set_address(None, &peer, &peer_addr).await?;
+
+    let running = tokio::spawn({
+        let server = server.clone();
+        async move { server.run(Arc::new(router)).await }
+    });
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = Arc::new(IpiisClient::genesis(None).await?);
+    client
+        .set_address(None, &account, &"127.0.0.1:5104".parse()?)
+        .await?;
+
+    // begin an in-flight request
+    let in_flight = tokio::spawn({
+        let client = client.clone();
+        async move { sleep(&client, &account, 2_000).await }
+    });
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // shut down while the request is in flight
+    shutdown.shutdown();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // refuse the new requests
+    let error = sleep(&client, &account, 0).await.unwrap_err();
+    assert_eq!(error.downcast::<ServerError>()?.kind, ErrorKind::Overloaded);
+
+    // finish the in-flight request
+    in_flight.await??;
+
+    // flush the book and stop
+    running.await??;
+    assert_eq!(server.book().get(None, &peer)?, Some(peer_addr));
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, handle_external_call, Ipiis, CLIENT_DUMMY,
+    },
+    server::IpiisServer,
+};
+use ipis::{
+    core::anyhow::{Error, Result},
+    env::Infer,
+    tokio,
+};
+
+/// The module which is hosted on the server.
+mod v1 {
+    use ipiis_api::common::{define_io, Ipiis, ServerResult};
+    use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+    define_io! {
+        service: "versioned",
+        version: 1,
+        Ping = 1 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: { },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+    }
+}
+
+/// The newer module which is known to the client.
+mod v2 {
+    use ipiis_api::common::{define_io, Ipiis, ServerResult};
+    use ipis::core::account::{GuaranteeSigned, GuarantorSigned};
+
+    define_io! {
+        service: "versioned",
+        version: 2,
+        Ping = 1 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: { },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+        Pong = 2 {
+            inputs: { },
+            input_sign: GuaranteeSigned<u8>,
+            outputs: { },
+            output_sign: GuarantorSigned<u8>,
+            generics: { },
+        },
+    }
+}
+
+struct VersionedServer {
+    client: Arc<IpiisServer>,
+}
+
+handle_external_call!(
+    server: VersionedServer => IpiisServer,
+    name: run,
+    request: crate::v1::io => {
+        Ping => handle_ping,
+    },
+);
+
+impl VersionedServer {
+    async fn handle_ping(
+        client: &IpiisServer,
+        req: crate::v1::io::request::Ping<'static>,
+    ) -> Result<crate::v1::io::response::Ping<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(crate::v1::io::response::Ping {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_module_version() -> Result<()> {
+    // init a server
+    let server = VersionedServer {
+        client: IpiisServer::genesis(5101).await?.into(),
+    };
+    let account = server.client.account_me().account_ref();
+    tokio::spawn(server.run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client
+        .set_address(None, &account, &"127.0.0.1:5101".parse()?)
+        .await?;
+
+    // serve the opcodes of the older version
+    external_call!(
+        client: &client,
+        target: None => &account,
+        request: crate::v2::io => Ping,
+        sign: client.sign(account, CLIENT_DUMMY)?,
+        inputs: { },
+        outputs: { },
+    );
+
+    // reject the opcodes of the newer version, telling both versions
+    let error = async {
+        external_call!(
+            client: &client,
+            target: None => &account,
+            request: crate::v2::io => Pong,
+            sign: client.sign(account, CLIENT_DUMMY)?,
+            inputs: { },
+            outputs: { },
+        );
+        Ok::<_, Error>(())
+    }
+    .await
+    .unwrap_err();
+
+    let error = error.downcast::<ServerError>()?;
+    assert_eq!(error.kind, ErrorKind::IncompatibleVersion);
+    assert!(error.message.contains("client=2, server=1"));
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use core::ops::Deref;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use ipis::core::{account::AccountRef, value::hash::Hash};
+use rand::Rng;
+
+/// An account serving a kind, with its relative weight.
+pub type Replica = (AccountRef, u32);
+
+/// How to pick one of the accounts serving a kind.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Visit the accounts in turn, as many times as their weights.
+    #[default]
+    RoundRobin,
+    /// Pick an account randomly, in proportion to its weight.
+    Random,
+    /// Pick the account with the fewest in-flight requests relative to its weight.
+    LeastOutstanding,
+    /// Pick the same account for the same key, moving as few keys as possible
+    /// when the accounts change (weighted rendezvous hashing).
+    ///
+    /// The keys are hashed stably, so that every node picks the same account for a key.
+    ConsistentHash,
+}
+
+/// The picked account, which is counted as outstanding until dropped.
+#[derive(Debug)]
+pub struct Pick {
+    account: AccountRef,
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl Deref for Pick {
+    type Target = AccountRef;
+
+    fn deref(&self) -> &Self::Target {
+        &self.account
+    }
+}
+
+impl Drop for Pick {
+    fn drop(&mut self) {
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The state shared by the picks, such as the cursors and the in-flight requests.
+#[derive(Debug, Default)]
+pub struct Balancer {
+    cursors: Mutex<HashMap<Option<Hash>, u64>>,
+    outstanding: Mutex<HashMap<AccountRef, Arc<AtomicUsize>>>,
+}
+
+impl Balancer {
+    /// Pick one of the replicas which has a positive weight.
+    ///
+    /// The `key` is used only by [`Strategy::ConsistentHash`].
+    pub fn pick(
+        &self,
+        strategy: Strategy,
+        kind: Option<&Hash>,
+        replicas: &[Replica],
+        key: &[u8],
+    ) -> Option<Pick> {
+        let replicas: Vec<_> = replicas.iter().filter(|(_, weight)| *weight > 0).collect();
+        let total: u64 = replicas.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let account = match strategy {
+            Strategy::RoundRobin => {
+                let cursor = {
+                    let mut cursors = self.cursors.lock().unwrap();
+                    let next = cursors.entry(kind.copied()).or_default();
+                    let cursor = *next;
+                    *next = cursor.wrapping_add(1);
+                    cursor
+                };
+                by_position(&replicas, cursor % total)
+            }
+            Strategy::Random => by_position(&replicas, ::rand::thread_rng().gen_range(0..total)),
+            Strategy::LeastOutstanding => {
+                let outstanding = self.outstanding.lock().unwrap();
+                let load = |account: &AccountRef| {
+                    outstanding
+                        .get(account)
+                        .map(|count| count.load(Ordering::SeqCst) as u64)
+                        .unwrap_or_default()
+                };
+
+                // compare `load / weight` without dividing
+                replicas
+                    .iter()
+                    .min_by(|(a, wa), (b, wb)| (load(a) * *wb as u64).cmp(&(load(b) * *wa as u64)))
+                    .map(|(account, _)| *account)?
+            }
+            Strategy::ConsistentHash => replicas
+                .iter()
+                .map(|(account, weight)| (*account, score(key, account, *weight)))
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(account, _)| account)?,
+        };
+
+        let outstanding = self
+            .outstanding
+            .lock()
+            .unwrap()
+            .entry(account)
+            .or_default()
+            .clone();
+        outstanding.fetch_add(1, Ordering::SeqCst);
+
+        Some(Pick {
+            account,
+            outstanding,
+        })
+    }
+
+    /// The number of the in-flight requests to the account.
+    pub fn outstanding(&self, account: &AccountRef) -> usize {
+        self.outstanding
+            .lock()
+            .unwrap()
+            .get(account)
+            .map(|count| count.load(Ordering::SeqCst))
+            .unwrap_or_default()
+    }
+}
+
+/// Find the replica which covers the position in the cumulative weights.
+fn by_position(replicas: &[&Replica], mut position: u64) -> AccountRef {
+    for (account, weight) in replicas {
+        match position.checked_sub(*weight as u64) {
+            Some(rest) => position = rest,
+            None => return *account,
+        }
+    }
+    unreachable!("the position should be less than the total weight")
+}
+
+/// The score of the weighted rendezvous hashing, where the highest one wins.
+fn score(key: &[u8], account: &AccountRef, weight: u32) -> f64 {
+    let hash = Hash::with_bytes(&[account.as_bytes().as_ref(), key].concat());
+    let mut bits = [0; 8];
+    bits.copy_from_slice(&hash[..8]);
+
+    // map the hash into (0, 1), keeping the 53 bits of the precision
+    let unit = ((u64::from_be_bytes(bits) >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+    weight as f64 / -unit.ln()
+}
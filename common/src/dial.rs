@@ -0,0 +1,138 @@
+use core::str::FromStr;
+use std::sync::Arc;
+
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{Account, AccountRef},
+        anyhow::Result,
+        value::hash::Hash,
+    },
+};
+
+use crate::{
+    balance::{Pick, Replica},
+    interceptor::Interceptor,
+    payload::PayloadLimits,
+    resolve::Resolution,
+    Ipiis,
+};
+
+/// The client which dials the peer directly, without storing its address into the address book.
+///
+/// It is used to contact the peers whose addresses are only reported by the others,
+/// e.g. in the routing table of a DHT or the membership list of a gossip.
+pub struct Dialer<'a, IpiisClient> {
+    client: &'a IpiisClient,
+    peer: &'a (AccountRef, String),
+}
+
+impl<'a, IpiisClient> Dialer<'a, IpiisClient> {
+    pub fn new(client: &'a IpiisClient, peer: &'a (AccountRef, String)) -> Self {
+        Self { client, peer }
+    }
+}
+
+#[async_trait]
+impl<'a, IpiisClient> Ipiis for Dialer<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + Send + Sync,
+    <IpiisClient as Ipiis>::Address: FromStr,
+    <<IpiisClient as Ipiis>::Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+{
+    type Address = <IpiisClient as Ipiis>::Address;
+    type Reader = <IpiisClient as Ipiis>::Reader;
+    type Writer = <IpiisClient as Ipiis>::Writer;
+
+    fn account_me(&self) -> &Account {
+        self.client.account_me()
+    }
+
+    async fn get_account_primary(&self, kind: Option<&Hash>) -> Result<AccountRef> {
+        self.client.get_account_primary(kind).await
+    }
+
+    async fn set_account_primary(&self, kind: Option<&Hash>, account: &AccountRef) -> Result<()> {
+        self.client.set_account_primary(kind, account).await
+    }
+
+    async fn get_address(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<<Self as Ipiis>::Address> {
+        if target == &self.peer.0 {
+            self.peer.1.parse().map_err(Into::into)
+        } else {
+            self.client.get_address(kind, target).await
+        }
+    }
+
+    async fn set_address(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<()> {
+        self.client.set_address(kind, target, address).await
+    }
+
+    async fn register(
+        &self,
+        kind: Option<&Hash>,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<()> {
+        self.client.register(kind, address).await
+    }
+
+    async fn resolve(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<Resolution<<Self as Ipiis>::Address>> {
+        self.client.resolve(kind, target, path, hops).await
+    }
+
+    async fn get_account_replicas(&self, kind: Option<&Hash>) -> Result<Vec<Replica>> {
+        self.client.get_account_replicas(kind).await
+    }
+
+    async fn register_replica(&self, kind: &Hash, weight: u32) -> Result<()> {
+        self.client.register_replica(kind, weight).await
+    }
+
+    async fn pick(&self, kind: Option<&Hash>, key: &[u8]) -> Result<Pick> {
+        self.client.pick(kind, key).await
+    }
+
+    fn interceptors(&self) -> &[Arc<dyn Interceptor>] {
+        self.client.interceptors()
+    }
+
+    fn payload_limits(&self) -> PayloadLimits {
+        self.client.payload_limits()
+    }
+
+    async fn call_raw(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        if target == &self.peer.0 {
+            let address = self.peer.1.parse()?;
+            self.client.call_raw_at(target, &address).await
+        } else {
+            self.client.call_raw(kind, target).await
+        }
+    }
+
+    async fn call_raw_at(
+        &self,
+        target: &AccountRef,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        self.client.call_raw_at(target, address).await
+    }
+}
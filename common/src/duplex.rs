@@ -0,0 +1,386 @@
+use core::marker::PhantomData;
+use std::{
+    net::SocketAddr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use ipis::{
+    core::{
+        account::{Account, AccountRef, GuaranteeSigned, Verifier},
+        anyhow::{bail, Error, Result},
+        metadata::Metadata,
+        signature::SignatureSerializer,
+        value::hash::Hash,
+    },
+    futures::{Sink, Stream},
+    stream::DynStream,
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+use rkyv::{Archive, Serialize};
+
+use crate::{
+    error::ServerError,
+    payload::{recv_limited, PayloadLimits},
+    Ipiis, ServerResult,
+};
+
+/// A handshaked duplex stream which is waiting for the handler's response.
+///
+/// The handler should reply with `response::$case::accept` to begin the session.
+pub struct Pending<'s, IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    send: &'s mut <IpiisClient as Ipiis>::Writer,
+    recv: <IpiisClient as Ipiis>::Reader,
+    peer: AccountRef,
+    addr: SocketAddr,
+}
+
+impl<'s, IpiisClient> Pending<'s, IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    pub fn new(
+        send: &'s mut <IpiisClient as Ipiis>::Writer,
+        recv: <IpiisClient as Ipiis>::Reader,
+        peer: AccountRef,
+        addr: SocketAddr,
+        accepted: &'s AtomicBool,
+    ) -> Self {
+        Self {
+            send,
+            recv,
+            peer,
+            addr,
+            accepted,
+        }
+    }
+
+    pub fn peer(&self) -> &AccountRef {
+        &self.peer
+    }
+
+    /// The address of the peer, as observed by this node.
+    pub fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn into_parts(
+        self,
+    ) -> (
+        &'s mut <IpiisClient as Ipiis>::Writer,
+        <IpiisClient as Ipiis>::Reader,
+        AccountRef,
+    ) {
+        // the response begins here, so that any error cannot be sent anymore
+        self.accepted.store(true, Ordering::SeqCst);
+        (self.send, self.recv, self.peer)
+    }
+}
+
+/// A typed, long-lived conversation over a single stream.
+///
+/// Both sides send `Tx` and receive `Rx` messages, which can be optionally signed per message.
+/// The signed messages carry the session and their sequence numbers, so they cannot be replayed
+/// in the same session nor in another one.
+pub struct Duplex<'a, W, R, Tx, Rx> {
+    pub sender: DuplexSender<'a, W, Tx>,
+    pub receiver: DuplexReceiver<R, Rx>,
+}
+
+impl<'a, W, R, Tx, Rx> Duplex<'a, W, R, Tx, Rx> {
+    /// Begin a session, which is identified by the hash of the sign of its opening response,
+    /// as both sides have it.
+    pub fn new(account_me: &'a Account, peer: AccountRef, session: Hash, send: W, recv: R) -> Self {
+        Self {
+            sender: DuplexSender {
+                account_me,
+                peer,
+                session,
+                send,
+                signed: false,
+                seq: 0,
+                _msg: Default::default(),
+            },
+            receiver: DuplexReceiver {
+                account_me: account_me.account_ref(),
+                peer,
+                session,
+                recv,
+                require_signed: false,
+                seq: 0,
+                limits: Default::default(),
+                _msg: Default::default(),
+            },
+        }
+    }
+
+    /// Sign each outgoing message as a guarantee.
+    pub fn signed(mut self, signed: bool) -> Self {
+        self.sender.signed = signed;
+        self
+    }
+
+    /// Reject any incoming message which is not signed by the peer.
+    pub fn require_signed(mut self, require_signed: bool) -> Self {
+        self.receiver.require_signed = require_signed;
+        self
+    }
+
+    /// Limit the size of each incoming message.
+    pub fn payload_limits(mut self, limits: PayloadLimits) -> Self {
+        self.receiver.limits = limits;
+        self
+    }
+
+    pub fn peer(&self) -> &AccountRef {
+        &self.sender.peer
+    }
+
+    pub fn split(self) -> (DuplexSender<'a, W, Tx>, DuplexReceiver<R, Rx>) {
+        (self.sender, self.receiver)
+    }
+
+    /// Take back the underlying streams, e.g. to carry another protocol over the session.
+    pub fn into_inner(self) -> (W, R) {
+        (self.sender.send, self.receiver.recv)
+    }
+}
+
+impl<'a, W, R, Tx, Rx> Duplex<'a, W, R, Tx, Rx>
+where
+    W: AsyncWrite + Send + Unpin + 'a,
+    R: AsyncRead + Send + Unpin + 'static,
+    Tx: Archive
+        + Serialize<SignatureSerializer>
+        + Serialize<::ipis::core::signed::Serializer>
+        + ::core::fmt::Debug
+        + PartialEq
+        + Send
+        + Sync
+        + 'a,
+    <Tx as Archive>::Archived: ::core::fmt::Debug + PartialEq,
+    GuaranteeSigned<(Hash, u64, Tx)>: Archive + Serialize<::ipis::core::signed::Serializer>,
+    Rx: Archive + ::core::fmt::Debug + PartialEq + Send + 'static,
+    <Rx as Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+        > + ::ipis::rkyv::Deserialize<Rx, ::ipis::rkyv::de::deserializers::SharedDeserializeMap>
+        + ::core::fmt::Debug
+        + PartialEq,
+    GuaranteeSigned<(Hash, u64, Rx)>:
+        Archive + Verifier + ::core::fmt::Debug + PartialEq + Send + 'static,
+    <GuaranteeSigned<(Hash, u64, Rx)> as Archive>::Archived:
+        for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+            > + ::ipis::rkyv::Deserialize<
+                GuaranteeSigned<(Hash, u64, Rx)>,
+                ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+            > + ::core::fmt::Debug
+            + PartialEq,
+{
+    pub async fn send(&mut self, msg: Tx) -> Result<()> {
+        self.sender.send(msg).await
+    }
+
+    pub async fn recv(&mut self) -> Result<Option<Rx>> {
+        self.receiver.recv().await
+    }
+
+    /// Split the session into a typed `Sink` and `Stream` pair.
+    pub fn into_sink_stream(
+        self,
+    ) -> (
+        impl Sink<Tx, Error = Error> + 'a,
+        impl Stream<Item = Result<Rx>>,
+    ) {
+        let (sender, receiver) = self.split();
+        (sender.into_sink(), receiver.into_stream())
+    }
+}
+
+pub struct DuplexSender<'a, W, Tx> {
+    account_me: &'a Account,
+    peer: AccountRef,
+    session: Hash,
+    send: W,
+    signed: bool,
+    /// The sequence number of the next signed message, which prevents replaying.
+    seq: u64,
+    _msg: PhantomData<Tx>,
+}
+
+impl<'a, W, Tx> DuplexSender<'a, W, Tx>
+where
+    W: AsyncWrite + Send + Unpin + 'a,
+    Tx: Archive
+        + Serialize<SignatureSerializer>
+        + Serialize<::ipis::core::signed::Serializer>
+        + ::core::fmt::Debug
+        + PartialEq
+        + Send
+        + Sync
+        + 'a,
+    <Tx as Archive>::Archived: ::core::fmt::Debug + PartialEq,
+    GuaranteeSigned<(Hash, u64, Tx)>: Archive + Serialize<::ipis::core::signed::Serializer>,
+{
+    pub async fn send(&mut self, msg: Tx) -> Result<()> {
+        if self.signed {
+            // sign data with the session and the sequence number
+            let data = Metadata::builder().build(
+                self.account_me,
+                self.peer,
+                (self.session, self.seq, msg),
+            )?;
+            let mut data = DynStream::Owned(data);
+            self.seq += 1;
+
+            // make a flag
+            let flag = ServerResult::ACK_OK | ServerResult::SIGNED;
+
+            // send flag
+            self.send.write_u8(flag.bits()).await?;
+
+            // send data
+            data.copy_to(&mut self.send).await?;
+        } else {
+            let mut data = DynStream::Owned(msg);
+
+            // make a flag
+            let flag = ServerResult::ACK_OK;
+
+            // send flag
+            self.send.write_u8(flag.bits()).await?;
+
+            // send data
+            data.copy_to(&mut self.send).await?;
+        }
+        self.send.flush().await.map_err(Into::into)
+    }
+
+    /// Notify the peer that no more messages will be sent.
+    pub async fn close(mut self) -> Result<()> {
+        self.send.shutdown().await.map_err(Into::into)
+    }
+
+    pub fn into_sink(self) -> impl Sink<Tx, Error = Error> + 'a {
+        ::ipis::futures::sink::unfold(self, |mut sender, msg| async move {
+            sender.send(msg).await?;
+            Ok(sender)
+        })
+    }
+}
+
+pub struct DuplexReceiver<R, Rx> {
+    account_me: AccountRef,
+    peer: AccountRef,
+    session: Hash,
+    recv: R,
+    require_signed: bool,
+    /// The expected sequence number of the next signed message.
+    seq: u64,
+    limits: PayloadLimits,
+    _msg: PhantomData<Rx>,
+}
+
+impl<R, Rx> DuplexReceiver<R, Rx>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    Rx: Archive + ::core::fmt::Debug + PartialEq + Send + 'static,
+    <Rx as Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+        > + ::ipis::rkyv::Deserialize<Rx, ::ipis::rkyv::de::deserializers::SharedDeserializeMap>
+        + ::core::fmt::Debug
+        + PartialEq,
+    GuaranteeSigned<(Hash, u64, Rx)>:
+        Archive + Verifier + ::core::fmt::Debug + PartialEq + Send + 'static,
+    <GuaranteeSigned<(Hash, u64, Rx)> as Archive>::Archived:
+        for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+            > + ::ipis::rkyv::Deserialize<
+                GuaranteeSigned<(Hash, u64, Rx)>,
+                ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+            > + ::core::fmt::Debug
+            + PartialEq,
+{
+    /// Receive the next message, or `None` if the peer has closed the session.
+    pub async fn recv(&mut self) -> Result<Option<Rx>> {
+        // recv flag
+        let flag = match self.recv.read_u8().await {
+            Ok(flag) => ServerResult::from_bits(flag),
+            Err(e) if e.kind() == ::std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => bail!("network error: {e}"),
+        };
+
+        match flag {
+            // parse the data
+            Some(ServerResult::ACK_OK) => {
+                if self.require_signed {
+                    bail!("unsigned message is not allowed");
+                }
+
+                // recv data
+                let msg: Rx = recv_limited(&mut self.recv, self.limits)
+                    .await?
+                    .to_owned()
+                    .await?;
+                Ok(Some(msg))
+            }
+            // parse the signed data
+            Some(flag) if flag == ServerResult::ACK_OK | ServerResult::SIGNED => {
+                // recv data
+                let msg: GuaranteeSigned<(Hash, u64, Rx)> =
+                    recv_limited(&mut self.recv, self.limits)
+                        .await?
+                        .into_owned()
+                        .await?;
+
+                // verify data
+                msg.verify(Some(self.account_me))?;
+                if msg.guarantee.account != self.peer {
+                    bail!("message is not signed by the peer");
+                }
+
+                // reject the replayed or reordered messages
+                let (session, seq, msg) = msg.data.data;
+                if session != self.session {
+                    bail!("message is not signed for this session");
+                }
+                if seq != self.seq {
+                    bail!(
+                        "unexpected sequence number: expected {}, got {seq}",
+                        self.seq
+                    );
+                }
+                self.seq += 1;
+                Ok(Some(msg))
+            }
+            // parse the error
+            Some(ServerResult::ACK_ERR) => {
+                // recv data
+                let error = ServerError::recv(&mut self.recv, self.limits).await?;
+
+                Err(error.into())
+            }
+            Some(flag) if flag.contains(ServerResult::ACK) => {
+                bail!("unknown ACK flag: {flag:?}")
+            }
+            Some(_) | None => {
+                bail!("cannot parse the message of session")
+            }
+        }
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<Rx>> {
+        ::ipis::futures::stream::unfold(Some(self), |receiver| async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Ok(Some(msg)) => Some((Ok(msg), Some(receiver))),
+                Ok(None) => None,
+                // terminate the stream after the first error
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
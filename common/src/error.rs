@@ -0,0 +1,127 @@
+use ipis::{
+    core::anyhow::{bail, Result},
+    stream::DynStream,
+    tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+};
+
+use crate::{
+    payload::{recv_limited, PayloadLimits},
+    ServerResult,
+};
+
+/// The kind of an error response, sent right after the `ACK_ERR` flag.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ErrorKind {
+    Internal = 0,
+    UnknownOpcode = 1,
+    IncompatibleVersion = 2,
+    UnknownService = 3,
+    Overloaded = 4,
+    RateLimited = 5,
+    PayloadTooLarge = 6,
+    Unauthorized = 7,
+    Unresolvable = 8,
+}
+
+impl ErrorKind {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Internal),
+            1 => Some(Self::UnknownOpcode),
+            2 => Some(Self::IncompatibleVersion),
+            3 => Some(Self::UnknownService),
+            4 => Some(Self::Overloaded),
+            5 => Some(Self::RateLimited),
+            6 => Some(Self::PayloadTooLarge),
+            7 => Some(Self::Unauthorized),
+            8 => Some(Self::Unresolvable),
+            _ => None,
+        }
+    }
+}
+
+/// An error response of the server, which can be distinguished by clients with its kind.
+///
+/// Handlers may return it (e.g. with `bail!`) to send a specific kind of error.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl ::core::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        let message = &self.message;
+        match self.kind {
+            ErrorKind::Internal => write!(f, "internal error: {message}"),
+            ErrorKind::UnknownOpcode => write!(f, "unknown opcode: {message}"),
+            ErrorKind::IncompatibleVersion => write!(f, "incompatible version: {message}"),
+            ErrorKind::UnknownService => write!(f, "unknown service: {message}"),
+            ErrorKind::Overloaded => write!(f, "overloaded: {message}"),
+            ErrorKind::RateLimited => write!(f, "rate limited: {message}"),
+            ErrorKind::PayloadTooLarge => write!(f, "payload too large: {message}"),
+            ErrorKind::Unauthorized => write!(f, "unauthorized: {message}"),
+            ErrorKind::Unresolvable => write!(f, "unresolvable: {message}"),
+        }
+    }
+}
+
+impl ::std::error::Error for ServerError {}
+
+impl ServerError {
+    pub fn new(kind: ErrorKind, message: impl ToString) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Convert any error into a server error, preserving its kind if possible.
+    pub fn from_anyhow(error: ::ipis::core::anyhow::Error) -> Self {
+        match error.downcast::<Self>() {
+            Ok(error) => error,
+            Err(error) => Self::new(ErrorKind::Internal, error),
+        }
+    }
+
+    pub async fn send<W>(self, mut send: W) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        // make a flag
+        let flag = ServerResult::ACK_ERR;
+
+        // send flag
+        send.write_u8(flag.bits()).await?;
+
+        // send kind
+        send.write_u8(self.kind as u8).await?;
+
+        // send data
+        let mut data = DynStream::Owned(self.message);
+        data.copy_to(&mut send).await?;
+
+        Ok(())
+    }
+
+    /// Receive an error response right after the `ACK_ERR` flag.
+    pub async fn recv<R>(mut recv: R, limits: PayloadLimits) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        // recv kind
+        let kind = recv.read_u8().await?;
+        let kind = match ErrorKind::from_u8(kind) {
+            Some(kind) => kind,
+            None => bail!("unknown error kind: {kind}"),
+        };
+
+        // recv data
+        let message: String = recv_limited(&mut recv, limits).await?.to_owned().await?;
+
+        // TODO: verify data
+
+        Ok(Self { kind, message })
+    }
+}
@@ -0,0 +1,117 @@
+use core::future::Future;
+use std::{any::Any, net::SocketAddr, panic::AssertUnwindSafe, sync::Arc};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    futures::{future::BoxFuture, FutureExt},
+    log::error,
+};
+
+use crate::error::{ErrorKind, ServerError};
+
+/// The request which is passing through the interceptors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Context {
+    pub service: &'static str,
+    pub opcode: &'static str,
+    pub code: u32,
+    /// The address of the requester, which is only known on the server side.
+    pub addr: Option<SocketAddr>,
+    /// The requester on the server side (if the request is signed),
+    /// or the target on the client side.
+    pub peer: Option<AccountRef>,
+    /// Whether the request begins a long-lived duplex session.
+    pub session: bool,
+}
+
+/// A composable hook around each request.
+///
+/// Each interceptor may inspect the context, reject the request with an error,
+/// or call `next.run(ctx)` to pass it to the remaining interceptors and then the handler.
+#[async_trait]
+pub trait Interceptor: Send + Sync {
+    async fn intercept(&self, ctx: &Context, next: Next<'_>) -> Result<()>;
+}
+
+/// The remaining interceptors and the handler of a request.
+pub struct Next<'a> {
+    interceptors: &'a [Arc<dyn Interceptor>],
+    handler: BoxFuture<'a, Result<()>>,
+}
+
+impl<'a> Next<'a> {
+    pub fn new(
+        interceptors: &'a [Arc<dyn Interceptor>],
+        handler: BoxFuture<'a, Result<()>>,
+    ) -> Self {
+        Self {
+            interceptors,
+            handler,
+        }
+    }
+
+    pub async fn run(self, ctx: &Context) -> Result<()> {
+        match self.interceptors.split_first() {
+            Some((interceptor, interceptors)) => {
+                let next = Self {
+                    interceptors,
+                    handler: self.handler,
+                };
+                interceptor.intercept(ctx, next).await
+            }
+            None => self.handler.await,
+        }
+    }
+}
+
+/// Run a request handler through the interceptors, isolating any panic as an internal error.
+pub async fn dispatch(
+    interceptors: &[Arc<dyn Interceptor>],
+    ctx: &Context,
+    handler: BoxFuture<'_, Result<()>>,
+) -> Result<()> {
+    let next = Next::new(interceptors, handler);
+    isolate(ctx, next.run(ctx)).await
+}
+
+/// Run a step of a request (e.g. decoding it), isolating any panic as an internal error.
+pub async fn isolate<T>(ctx: &Context, step: impl Future<Output = Result<T>>) -> Result<T> {
+    match AssertUnwindSafe(step).catch_unwind().await {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = panic_message(&*payload);
+            error!(
+                "handler panicked: service={}, opcode={} ({}), addr={}, peer={}: {message}",
+                ctx.service,
+                ctx.opcode,
+                ctx.code,
+                ctx.addr
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                ctx.peer
+                    .map(|peer| peer.to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+            );
+
+            // hide the details of the panic from the caller
+            bail!(ServerError::new(
+                ErrorKind::Internal,
+                format!("handler panicked: {}", ctx.opcode),
+            ))
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
@@ -1,8 +1,10 @@
+use std::sync::Arc;
+
 use ipis::{
     async_trait::async_trait,
     core::{
         account::{Account, AccountRef, GuaranteeSigned, GuarantorSigned, Signer},
-        anyhow::Result,
+        anyhow::{bail, Result},
         metadata::Metadata,
         signature::SignatureSerializer,
         value::hash::Hash,
@@ -11,6 +13,25 @@ use ipis::{
 };
 use rkyv::{Archive, Serialize};
 
+use crate::{
+    balance::{Balancer, Pick, Replica, Strategy},
+    error::{ErrorKind, ServerError},
+    interceptor::Interceptor,
+    payload::PayloadLimits,
+    resolve::{self, Resolution},
+};
+
+pub mod balance;
+pub mod dial;
+pub mod duplex;
+pub mod error;
+pub mod interceptor;
+pub mod payload;
+pub mod record;
+pub mod resolve;
+pub mod router;
+pub mod schema;
+
 #[async_trait]
 pub trait Ipiis {
     type Address: Send + Sync;
@@ -52,11 +73,98 @@ pub trait Ipiis {
         Signer::sign(self.account_me(), msg)
     }
 
+    /// Announce the reachable address of this account to the primary.
+    ///
+    /// By default, the address is stored as the one of this account.
+    async fn register(
+        &self,
+        kind: Option<&Hash>,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<()> {
+        let account_me = self.account_me().account_ref();
+        self.set_address(kind, &account_me, address).await
+    }
+
+    /// Resolve the address of the target recursively through the chain of primaries.
+    ///
+    /// The `path` is the accounts which have already been visited,
+    /// and `hops` is the number of the primaries which may be asked further.
+    ///
+    /// By default, the address is got directly without visiting the primaries.
+    async fn resolve(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<Resolution<<Self as Ipiis>::Address>> {
+        let path = resolve::visit(path, self.account_me().account_ref())?;
+        resolve::ensure_hops(&path, hops)?;
+
+        Ok(Resolution {
+            address: self.get_address(kind, target).await?,
+            path,
+        })
+    }
+
+    /// Get the accounts serving the kind with their weights, or the primaries if `None`.
+    ///
+    /// By default, only the primary of the kind serves it.
+    async fn get_account_replicas(&self, kind: Option<&Hash>) -> Result<Vec<Replica>> {
+        Ok(vec![(self.get_account_primary(kind).await?, 1)])
+    }
+
+    /// Serve the kind with this account, in proportion to the weight among the other ones.
+    ///
+    /// A zero weight withdraws this account.
+    ///
+    /// By default, it is not supported as the replicas are not kept.
+    async fn register_replica(&self, kind: &Hash, _weight: u32) -> Result<()> {
+        bail!("cannot register a replica of the kind: {kind:?}")
+    }
+
+    /// Pick one of the accounts serving the kind, which is counted as outstanding until dropped.
+    ///
+    /// The `key` picks the same account with [`Strategy::ConsistentHash`],
+    /// which is used by default as no state is kept across the picks.
+    async fn pick(&self, kind: Option<&Hash>, key: &[u8]) -> Result<Pick> {
+        let replicas = self.get_account_replicas(kind).await?;
+
+        match Balancer::default().pick(Strategy::ConsistentHash, kind, &replicas, key) {
+            Some(pick) => Ok(pick),
+            None => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no accounts serving the kind: {kind:?}"),
+            )),
+        }
+    }
+
+    /// The client-side interceptors, which are applied to every outgoing request.
+    fn interceptors(&self) -> &[Arc<dyn Interceptor>] {
+        &[]
+    }
+
+    /// The size limits of the incoming messages, which are checked before allocating them.
+    fn payload_limits(&self) -> PayloadLimits {
+        Default::default()
+    }
+
     async fn call_raw(
         &self,
         kind: Option<&Hash>,
         target: &AccountRef,
     ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)>;
+
+    /// Open a stream to the target at the given address, without looking up the address book.
+    ///
+    /// It lets an address reported by a peer be dialed without trusting it for the others.
+    async fn call_raw_at(
+        &self,
+        target: &AccountRef,
+        _address: &<Self as Ipiis>::Address,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        bail!("cannot dial {target} at the given address")
+    }
 }
 
 #[async_trait]
@@ -107,6 +215,44 @@ where
         (**self).sign(target, msg)
     }
 
+    async fn register(
+        &self,
+        kind: Option<&Hash>,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<()> {
+        (**self).register(kind, address).await
+    }
+
+    async fn resolve(
+        &self,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+        path: &[AccountRef],
+        hops: u32,
+    ) -> Result<Resolution<<Self as Ipiis>::Address>> {
+        (**self).resolve(kind, target, path, hops).await
+    }
+
+    async fn get_account_replicas(&self, kind: Option<&Hash>) -> Result<Vec<Replica>> {
+        (**self).get_account_replicas(kind).await
+    }
+
+    async fn register_replica(&self, kind: &Hash, weight: u32) -> Result<()> {
+        (**self).register_replica(kind, weight).await
+    }
+
+    async fn pick(&self, kind: Option<&Hash>, key: &[u8]) -> Result<Pick> {
+        (**self).pick(kind, key).await
+    }
+
+    fn interceptors(&self) -> &[Arc<dyn Interceptor>] {
+        (**self).interceptors()
+    }
+
+    fn payload_limits(&self) -> PayloadLimits {
+        (**self).payload_limits()
+    }
+
     async fn call_raw(
         &self,
         kind: Option<&Hash>,
@@ -114,8 +260,19 @@ where
     ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
         (**self).call_raw(kind, target).await
     }
+
+    async fn call_raw_at(
+        &self,
+        target: &AccountRef,
+        address: &<Self as Ipiis>::Address,
+    ) -> Result<(<Self as Ipiis>::Writer, <Self as Ipiis>::Reader)> {
+        (**self).call_raw_at(target, address).await
+    }
 }
 
+/// The version of the wire protocol, which is sent before each request.
+pub const PROTOCOL_VERSION: u16 = 1;
+
 pub const CLIENT_DUMMY: u8 = 42;
 ::ipis::bitflags::bitflags! {
 
@@ -123,6 +280,7 @@ pub const CLIENT_DUMMY: u8 = 42;
         const ACK = 0b10000000;
         const OK = 0b01000000;
         const ERR = 0b00100000;
+        const SIGNED = 0b00010000;
 
         const ACK_OK = Self::ACK.bits | Self::OK.bits;
         const ACK_ERR = Self::ACK.bits | Self::ERR.bits;
@@ -130,7 +288,9 @@ pub const CLIENT_DUMMY: u8 = 42;
 }
 
 define_io! {
-    GetAccountPrimary {
+    service: "ipiis",
+    version: 2,
+    GetAccountPrimary = 1 {
         inputs: { },
         input_sign: GuaranteeSigned<Option<Hash>>,
         outputs: {
@@ -140,14 +300,14 @@ define_io! {
         output_sign: GuarantorSigned<Option<Hash>>,
         generics: { Address, },
     },
-    SetAccountPrimary {
+    SetAccountPrimary = 2 {
         inputs: { },
         input_sign: GuaranteeSigned<(Option<Hash>, AccountRef)>,
         outputs: { },
         output_sign: GuarantorSigned<(Option<Hash>, AccountRef)>,
         generics: { },
     },
-    GetAddress {
+    GetAddress = 3 {
         inputs: { },
         input_sign: GuaranteeSigned<(Option<Hash>, AccountRef)>,
         outputs: {
@@ -156,38 +316,241 @@ define_io! {
         output_sign: GuarantorSigned<(Option<Hash>, AccountRef)>,
         generics: { Address, },
     },
-    SetAddress {
+    SetAddress = 4 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, AccountRef, Address)>,
+        outputs: { },
+        output_sign: GuarantorSigned<(Option<Hash>, AccountRef, Address)>,
+        generics: { Address, },
+    },
+    Register = 5 {
         inputs: { },
         input_sign: GuaranteeSigned<(Option<Hash>, AccountRef, Address)>,
         outputs: { },
         output_sign: GuarantorSigned<(Option<Hash>, AccountRef, Address)>,
         generics: { Address, },
     },
+    Resolve = 6 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, AccountRef, Vec<AccountRef>, u32)>,
+        outputs: {
+            address: Address,
+            path: Vec<AccountRef>,
+        },
+        output_sign: GuarantorSigned<(Option<Hash>, AccountRef, Vec<AccountRef>, u32)>,
+        generics: { Address, },
+    },
+    Replicate = 7 {
+        inputs: {
+            changes: Vec<GuaranteeSigned<(Option<Hash>, Option<AccountRef>, String, u64)>>,
+            records: Vec<GuaranteeSigned<(Option<Hash>, Address, u64, u64)>>,
+        },
+        input_sign: GuaranteeSigned<u64>,
+        outputs: { },
+        output_sign: GuarantorSigned<u64>,
+        generics: { Address, },
+    },
+    SyncDirectory = 8 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(u64, u64)>,
+        outputs: {
+            changes: Vec<GuaranteeSigned<(Option<Hash>, Option<AccountRef>, String, u64)>>,
+            records: Vec<GuaranteeSigned<(Option<Hash>, Address, u64, u64)>>,
+            cursor: (u64, u64),
+        },
+        output_sign: GuarantorSigned<(u64, u64)>,
+        generics: { Address, },
+    },
+    PublishRecord = 9 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, Address, u64, u64)>,
+        outputs: { },
+        output_sign: GuarantorSigned<(Option<Hash>, Address, u64, u64)>,
+        generics: { Address, },
+    },
+    GetAddressRecord = 10 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, AccountRef)>,
+        outputs: {
+            record: GuaranteeSigned<(Option<Hash>, Address, u64, u64)>,
+        },
+        output_sign: GuarantorSigned<(Option<Hash>, AccountRef)>,
+        generics: { Address, },
+    },
+    RegisterReplica = 11 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Hash, AccountRef, u32)>,
+        outputs: { },
+        output_sign: GuarantorSigned<(Hash, AccountRef, u32)>,
+        generics: { },
+    },
+    GetAccountReplicas = 12 {
+        inputs: { },
+        input_sign: GuaranteeSigned<Option<Hash>>,
+        outputs: {
+            replicas: Vec<(AccountRef, u32)>,
+        },
+        output_sign: GuarantorSigned<Option<Hash>>,
+        generics: { },
+    },
+    Watch = 13 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, Option<AccountRef>)>,
+        outputs: { },
+        output_sign: GuarantorSigned<(Option<Hash>, Option<AccountRef>)>,
+        generics: { },
+        duplex: {
+            inputs: u8,
+            outputs: (Option<Hash>, Option<AccountRef>, String, u64),
+        },
+    },
+    Relay = 14 {
+        inputs: { },
+        input_sign: GuaranteeSigned<AccountRef>,
+        outputs: {
+            port: u16,
+        },
+        output_sign: GuarantorSigned<AccountRef>,
+        generics: { },
+        duplex: {
+            inputs: u8,
+            outputs: u64,
+        },
+    },
+    RelayAccept = 15 {
+        inputs: { },
+        input_sign: GuaranteeSigned<u64>,
+        outputs: { },
+        output_sign: GuarantorSigned<u64>,
+        generics: { },
+        duplex: {
+            inputs: u8,
+            outputs: u8,
+        },
+    },
+    Rendezvous = 16 {
+        inputs: { },
+        input_sign: GuaranteeSigned<Option<AccountRef>>,
+        outputs: {
+            address: String,
+        },
+        output_sign: GuarantorSigned<Option<AccountRef>>,
+        generics: { },
+        duplex: {
+            inputs: u8,
+            outputs: (AccountRef, String),
+        },
+    },
+    Forward = 17 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, AccountRef, Vec<AccountRef>, u32)>,
+        outputs: {
+            path: Vec<AccountRef>,
+        },
+        output_sign: GuarantorSigned<(Option<Hash>, AccountRef, Vec<AccountRef>, u32)>,
+        generics: { },
+        duplex: {
+            inputs: u8,
+            outputs: u8,
+        },
+    },
+    ResolveAccountPrimary = 18 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Hash, Vec<AccountRef>, u32)>,
+        outputs: {
+            account: AccountRef,
+            address: Option<Address>,
+            path: Vec<AccountRef>,
+        },
+        output_sign: GuarantorSigned<(Hash, Vec<AccountRef>, u32)>,
+        generics: { Address, },
+    },
 }
 
 #[macro_export]
 macro_rules! define_io {
     (
-        $($case:ident {
-            inputs: { $( $input_field:ident : $input_ty:ty ,)* },
+        service: $service:literal,
+        version: $version:literal,
+        $($case:ident = $code:literal {
+            inputs: { $( $( #[$input_bulk:ident] )? $input_field:ident : $input_ty:ty ,)* },
             input_sign: $input_sign:ty,
-            outputs: { $( $output_field:ident : $output_ty:ty ,)* },
+            outputs: { $( $( #[$output_bulk:ident] )? $output_field:ident : $output_ty:ty ,)* },
             output_sign: $output_sign:ty,
             generics: { $( $generic:ident ,)* },
+            $( duplex: {
+                inputs: $duplex_input_ty:ty,
+                outputs: $duplex_output_ty:ty,
+            }, )?
         },)*
     ) => {::ipis::paste::paste! {
         pub mod io {
-            use bytecheck::CheckBytes;
-            use rkyv::{Archive, Deserialize, Serialize};
+            /// The name of this module, which is hashed to select the service on the server.
+            pub const SERVICE: &str = $service;
+
+            /// The version of this module, which should be bumped when adding opcodes.
+            ///
+            /// The server rejects the unknown opcodes of a newer client as `IncompatibleVersion`,
+            /// telling both versions, while the older clients are still served.
+            pub const VERSION: u32 = $version;
 
-            #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Archive, Serialize, Deserialize)]
-            #[archive(compare(PartialEq))]
-            #[archive_attr(derive(CheckBytes, Copy, Clone, Debug, PartialEq, Eq, Hash))]
+            pub fn service() -> ::ipis::core::value::hash::Hash {
+                ::ipis::core::value::hash::Hash::with_str(SERVICE)
+            }
+
+            #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+            #[repr(u32)]
             pub enum OpCode {$(
-                $case,
+                $case = $code,
             )*}
 
-            impl ::ipis::core::signed::IsSigned for OpCode {}
+            impl OpCode {
+                pub const fn code(&self) -> u32 {
+                    *self as u32
+                }
+
+                pub const fn from_code(code: u32) -> Option<Self> {
+                    match code {
+                        $(
+                            $code => Some(Self::$case),
+                        )*
+                        _ => None,
+                    }
+                }
+
+                pub const fn name(&self) -> &'static str {
+                    match self {
+                        $(
+                            Self::$case => stringify!($case),
+                        )*
+                    }
+                }
+            }
+
+            /// Describe the opcodes so that they can be recorded and checked for compatibility.
+            pub fn schema() -> $crate::schema::Schema {
+                $crate::schema::Schema {
+                    service: SERVICE.to_string(),
+                    version: VERSION,
+                    opcodes: vec![$(
+                        $crate::schema::OpCodeSchema {
+                            code: $code,
+                            name: stringify!($case).to_string(),
+                            inputs: <[&str]>::join(&[$(
+                                concat!(stringify!($input_field), ": ", stringify!($input_ty)),
+                            )*], ", "),
+                            input_sign: stringify!($input_sign).to_string(),
+                            outputs: <[&str]>::join(&[$(
+                                concat!(stringify!($output_field), ": ", stringify!($output_ty)),
+                            )*], ", "),
+                            output_sign: stringify!($output_sign).to_string(),
+                            duplex: concat!($(
+                                stringify!($duplex_input_ty), " -> ", stringify!($duplex_output_ty),
+                            )?).to_string(),
+                        },
+                    )*],
+                }
+            }
 
             pub mod request {
                 use super::super::*;
@@ -230,7 +593,7 @@ macro_rules! define_io {
                             target: &::ipis::core::account::AccountRef,
                         ) -> ::ipis::core::anyhow::Result<super::response::$case<'static, $( $generic, )* >>
                         where
-                            __IpiisClient: super::super::Ipiis,
+                            __IpiisClient: super::super::Ipiis + Sync,
                             <::ipis::core::account::GuaranteeSigned<String> as ::ipis::rkyv::Archive>::Archived: ::ipis::rkyv::Deserialize<
                                     ::ipis::core::account::GuaranteeSigned<String>,
                                     ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
@@ -287,7 +650,7 @@ macro_rules! define_io {
                             let recv = self.send(client, kind, target).await?;
 
                             // recv data
-                            super::response::$case::recv(target, recv).await
+                            super::response::$case::recv(target, client.payload_limits(), recv).await
                         }
 
                         pub async fn send<__IpiisClient>(
@@ -297,7 +660,7 @@ macro_rules! define_io {
                             target: &::ipis::core::account::AccountRef,
                         ) -> ::ipis::core::anyhow::Result<<__IpiisClient as super::super::Ipiis>::Reader>
                         where
-                            __IpiisClient: super::super::Ipiis,
+                            __IpiisClient: super::super::Ipiis + Sync,
                             <::ipis::core::account::GuaranteeSigned<String> as ::ipis::rkyv::Archive>::Archived: ::ipis::rkyv::Deserialize<
                                     ::ipis::core::account::GuaranteeSigned<String>,
                                     ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
@@ -339,60 +702,238 @@ macro_rules! define_io {
                                     + PartialEq,
                             )*
                         {
-                            use ipis::tokio::io::AsyncReadExt;
-
-                            // make a opcode
-                            let mut opcode = ::ipis::stream::DynStream::Owned(super::OpCode::$case);
+                            // send data
+                            let (_, recv) = self.send_raw(client, kind, target).await?;
+                            Ok(recv)
+                        }
 
-                            // pack data
-                            opcode.serialize_inner().await?;
-                            self.__sign.serialize_inner().await?;
+                        pub async fn send_raw<__IpiisClient>(
+                            &'__io mut self,
+                            client: &__IpiisClient,
+                            kind: Option<&::ipis::core::value::hash::Hash>,
+                            target: &::ipis::core::account::AccountRef,
+                        ) -> ::ipis::core::anyhow::Result<(
+                            <__IpiisClient as super::super::Ipiis>::Writer,
+                            <__IpiisClient as super::super::Ipiis>::Reader,
+                        )>
+                        where
+                            __IpiisClient: super::super::Ipiis + Sync,
+                            <::ipis::core::account::GuaranteeSigned<String> as ::ipis::rkyv::Archive>::Archived: ::ipis::rkyv::Deserialize<
+                                    ::ipis::core::account::GuaranteeSigned<String>,
+                                    ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                >,
                             $(
-                                {
-                                    self.$input_field.serialize_inner().await?;
-                                }
+                                $input_ty: ::ipis::core::signed::IsSigned
+                                    + ::ipis::rkyv::Archive
+                                    + ::ipis::rkyv::Serialize<::ipis::core::signature::SignatureSerializer>
+                                    + ::ipis::rkyv::Serialize<::ipis::core::signed::Serializer>
+                                    + Send
+                                    + Sync
+                                    + 'static,
+                                <$input_ty as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                        ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                    > + ::ipis::rkyv::Deserialize<
+                                        $input_ty,
+                                        ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                    >
+                                    + ::core::fmt::Debug
+                                    + PartialEq,
+                                )*
+                            $(
+                                $generic: ::ipis::core::signed::IsSigned
+                                    + ::ipis::rkyv::Archive
+                                    + ::ipis::rkyv::Serialize<::ipis::core::signature::SignatureSerializer>
+                                    + ::ipis::rkyv::Serialize<::ipis::core::signed::Serializer>
+                                    + ::core::fmt::Debug
+                                    + PartialEq
+                                    + Send
+                                    + Sync
+                                    + 'static,
+                                <$generic as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                        ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                    > + ::ipis::rkyv::Deserialize<
+                                        $generic,
+                                        ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                    >
+                                    + ::core::fmt::Debug
+                                    + PartialEq,
                             )*
+                        {
+                            use ipis::tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+                            // make a context
+                            let opcode = super::OpCode::$case;
+                            let context = $crate::interceptor::Context {
+                                service: super::SERVICE,
+                                opcode: opcode.name(),
+                                code: opcode.code(),
+                                addr: None,
+                                peer: Some(*target),
+                                session: false,
+                            };
 
-                            // make a connection
-                            let (mut send, mut recv) = client.call_raw(kind, target).await?;
+                            // send data through the interceptors
+                            let mut output = None;
+                            let handler = async {
+                                // make a header
+                                let mut service = ::ipis::stream::DynStream::Owned(super::service());
 
-                            // send opcode
-                            opcode.copy_to(&mut send).await?;
+                                // pack data
+                                service.serialize_inner().await?;
+                                self.__sign.serialize_inner().await?;
+                                $(
+                                    {
+                                        self.$input_field.serialize_inner().await?;
+                                    }
+                                )*
 
-                            // send sign
-                            self.__sign.copy_to(&mut send).await?;
+                                // make a connection
+                                let (mut send, mut recv) = client.call_raw(kind, target).await?;
 
-                            // send data
-                            $(
-                                {
-                                    self.$input_field.copy_to(&mut send).await?;
-                                }
-                            )*
+                                // send protocol version
+                                send.write_u16(super::super::PROTOCOL_VERSION).await?;
 
-                            // recv flag
-                            match recv.read_u8().await.map(super::super::ServerResult::from_bits) {
-                                // parse the data
-                                Ok(Some(super::super::ServerResult::ACK_OK)) => Ok(recv),
-                                // parse the error
-                                Ok(Some(super::super::ServerResult::ACK_ERR)) => {
-                                    // recv data
-                                    let res: String = ::ipis::stream::DynStream::recv(&mut recv)
-                                        .await?
-                                        .to_owned().await?;
+                                // send service
+                                service.copy_to(&mut send).await?;
 
-                                    // TODO: verify data
+                                // send module version
+                                send.write_u32(super::VERSION).await?;
 
-                                    ::ipis::core::anyhow::bail!("internal error: {res}")
-                                }
-                                Ok(Some(flag)) if flag.contains(super::super::ServerResult::ACK) => {
-                                    ::ipis::core::anyhow::bail!("unknown ACK flag: {flag:?}")
-                                }
-                                Ok(Some(_) | None) => {
-                                    ::ipis::core::anyhow::bail!("cannot parse the result of response")
-                                }
-                                Err(e) => {
-                                    ::ipis::core::anyhow::bail!("network error: {e}")
+                                // send opcode
+                                send.write_u32(opcode.code()).await?;
+
+                                // send sign
+                                self.__sign.copy_to(&mut send).await?;
+
+                                // send data
+                                $(
+                                    {
+                                        self.$input_field.copy_to(&mut send).await?;
+                                    }
+                                )*
+
+                                // recv flag
+                                match recv.read_u8().await.map(super::super::ServerResult::from_bits) {
+                                    // parse the data
+                                    Ok(Some(super::super::ServerResult::ACK_OK)) => {
+                                        output = Some((send, recv));
+                                        Ok::<_, ::ipis::core::anyhow::Error>(())
+                                    }
+                                    // parse the error
+                                    Ok(Some(super::super::ServerResult::ACK_ERR)) => {
+                                        // recv data
+                                        let error = $crate::error::ServerError::recv(&mut recv, client.payload_limits()).await?;
+
+                                        Err(error.into())
+                                    }
+                                    Ok(Some(flag)) if flag.contains(super::super::ServerResult::ACK) => {
+                                        ::ipis::core::anyhow::bail!("unknown ACK flag: {flag:?}")
+                                    }
+                                    Ok(Some(_) | None) => {
+                                        ::ipis::core::anyhow::bail!("cannot parse the result of response")
+                                    }
+                                    Err(e) => {
+                                        ::ipis::core::anyhow::bail!("network error: {e}")
+                                    }
                                 }
+                            };
+                            $crate::interceptor::Next::new(client.interceptors(), Box::pin(handler))
+                                .run(&context)
+                                .await?;
+
+                            output.ok_or_else(|| {
+                                ::ipis::core::anyhow::anyhow!("request is dropped by an interceptor")
+                            })
+                        }
+
+                        $crate::__define_io_duplex! {
+                            [ $( $duplex_input_ty )? ]
+                            pub async fn open<'__client, __IpiisClient>(
+                                &'__io mut self,
+                                client: &'__client __IpiisClient,
+                                kind: Option<&::ipis::core::value::hash::Hash>,
+                                target: &::ipis::core::account::AccountRef,
+                            ) -> ::ipis::core::anyhow::Result<(
+                                super::response::$case<'static, $( $generic, )* >,
+                                $crate::duplex::Duplex<
+                                    '__client,
+                                    <__IpiisClient as super::super::Ipiis>::Writer,
+                                    <__IpiisClient as super::super::Ipiis>::Reader,
+                                    $( $duplex_input_ty )?,
+                                    $( $duplex_output_ty )?,
+                                >,
+                            )>
+                            where
+                                __IpiisClient: super::super::Ipiis + Sync,
+                                <::ipis::core::account::GuaranteeSigned<String> as ::ipis::rkyv::Archive>::Archived: ::ipis::rkyv::Deserialize<
+                                        ::ipis::core::account::GuaranteeSigned<String>,
+                                        ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                    >,
+                                $(
+                                    $input_ty: ::ipis::core::signed::IsSigned
+                                        + ::ipis::rkyv::Archive
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signature::SignatureSerializer>
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signed::Serializer>
+                                        + Send
+                                        + Sync
+                                        + 'static,
+                                    <$input_ty as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                        > + ::ipis::rkyv::Deserialize<
+                                            $input_ty,
+                                            ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                        >
+                                        + ::core::fmt::Debug
+                                        + PartialEq,
+                                    )*
+                                $(
+                                    $output_ty: ::ipis::rkyv::Archive + ::core::fmt::Debug + PartialEq + 'static,
+                                    <$output_ty as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                        > + ::ipis::rkyv::Deserialize<
+                                            $output_ty,
+                                            ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                        >
+                                        + ::core::fmt::Debug
+                                        + PartialEq,
+                                )*
+                                $(
+                                    $generic: ::ipis::core::signed::IsSigned
+                                        + ::ipis::rkyv::Archive
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signature::SignatureSerializer>
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signed::Serializer>
+                                        + ::core::fmt::Debug
+                                        + PartialEq
+                                        + Send
+                                        + Sync
+                                        + 'static,
+                                    <$generic as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                        > + ::ipis::rkyv::Deserialize<
+                                            $generic,
+                                            ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                        >
+                                        + ::core::fmt::Debug
+                                        + PartialEq,
+                                )*
+                            {
+                                // send data
+                                let (send, mut recv) = self.send_raw(client, kind, target).await?;
+
+                                // recv data
+                                let mut res = super::response::$case::recv(target, client.payload_limits(), &mut recv).await?;
+
+                                // identify the session by the response sign
+                                let mut session = vec![];
+                                res.__sign.copy_to(&mut session).await?;
+                                let session = ::ipis::core::value::hash::Hash::with_bytes(&session);
+
+                                // begin a session
+                                Ok((
+                                    res,
+                                    $crate::duplex::Duplex::new(client.account_me(), *target, session, send, recv)
+                                        .payload_limits(client.payload_limits()),
+                                ))
                             }
                         }
                     }
@@ -448,11 +989,14 @@ macro_rules! define_io {
                             use ipis::core::account::Verifier;
 
                             // recv data
+                            let mut budget = $crate::payload::PayloadBudget::new(client.payload_limits());
                             let mut res = Self {
                                 __lifetime: Default::default(),
-                                __sign: ::ipis::stream::DynStream::recv(&mut recv).await?,
+                                __sign: budget.recv(&mut recv, false).await?,
                                 $(
-                                    $input_field: ::ipis::stream::DynStream::recv(&mut recv).await?,
+                                    $input_field: budget
+                                        .recv(&mut recv, $crate::__define_io_is_bulk!($( $input_bulk )?))
+                                        .await?,
                                 )*
                             };
 
@@ -566,6 +1110,90 @@ macro_rules! define_io {
                             )*
                             Ok(())
                         }
+
+                        $crate::__define_io_duplex! {
+                            [ $( $duplex_input_ty )? ]
+                            pub async fn accept<'__client, '__send, __IpiisClient, __IpiisSession>(
+                                &'__io mut self,
+                                client: &'__client __IpiisClient,
+                                pending: $crate::duplex::Pending<'__send, __IpiisSession>,
+                            ) -> ::ipis::core::anyhow::Result<
+                                $crate::duplex::Duplex<
+                                    '__client,
+                                    &'__send mut <__IpiisSession as super::super::Ipiis>::Writer,
+                                    <__IpiisSession as super::super::Ipiis>::Reader,
+                                    $( $duplex_output_ty )?,
+                                    $( $duplex_input_ty )?,
+                                >,
+                            >
+                            where
+                                __IpiisClient: super::super::Ipiis,
+                                __IpiisSession: super::super::Ipiis,
+                                <::ipis::core::account::GuaranteeSigned<String> as ::ipis::rkyv::Archive>::Archived: ::ipis::rkyv::Deserialize<
+                                        ::ipis::core::account::GuaranteeSigned<String>,
+                                        ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                    >,
+                                $(
+                                    $output_ty: ::ipis::rkyv::Archive + ::core::fmt::Debug + PartialEq + 'static,
+                                    <$output_ty as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                        > + ::ipis::rkyv::Deserialize<
+                                            $output_ty,
+                                            ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                        >
+                                        + ::core::fmt::Debug
+                                        + PartialEq,
+                                )*
+                                $(
+                                    $generic: ::ipis::core::signed::IsSigned
+                                        + ::ipis::rkyv::Archive
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signature::SignatureSerializer>
+                                        + ::ipis::rkyv::Serialize<::ipis::core::signed::Serializer>
+                                        + ::core::fmt::Debug
+                                        + PartialEq
+                                        + Send
+                                        + Sync
+                                        + 'static,
+                                    <$generic as ::ipis::rkyv::Archive>::Archived: for<'__bytecheck> ::ipis::bytecheck::CheckBytes<
+                                            ::ipis::rkyv::validation::validators::DefaultValidator<'__bytecheck>,
+                                        > + ::ipis::rkyv::Deserialize<
+                                            $generic,
+                                            ::ipis::rkyv::de::deserializers::SharedDeserializeMap,
+                                        >
+                                        + ::core::fmt::Debug
+                                        + PartialEq,
+                                )*
+                            {
+                                use ipis::tokio::io::AsyncWriteExt;
+
+                                let (send, recv, peer) = pending.into_parts();
+
+                                // make a flag
+                                let flag = super::super::ServerResult::ACK_OK;
+
+                                // send flag
+                                send.write_u8(flag.bits()).await?;
+
+                                // send sign
+                                self.__sign.copy_to(&mut *send).await?;
+
+                                // identify the session by the sign
+                                let mut session = vec![];
+                                self.__sign.copy_to(&mut session).await?;
+                                let session = ::ipis::core::value::hash::Hash::with_bytes(&session);
+
+                                // send data
+                                $(
+                                    {
+                                        self.$output_field.copy_to(&mut *send).await?;
+                                    }
+                                )*
+
+                                // begin a session
+                                Ok($crate::duplex::Duplex::new(client.account_me(), peer, session, send, recv)
+                                    .payload_limits(client.payload_limits()))
+                            }
+                        }
                     }
 
                     impl<$( $generic, )* > $case<'static, $( $generic, )* >
@@ -577,6 +1205,7 @@ macro_rules! define_io {
                     {
                         pub async fn recv(
                             target: &::ipis::core::account::AccountRef,
+                            limits: $crate::payload::PayloadLimits,
                             mut recv: impl ::ipis::tokio::io::AsyncRead + Unpin,
                         ) -> ::ipis::core::anyhow::Result<Self>
                         where
@@ -618,11 +1247,14 @@ macro_rules! define_io {
                             use ipis::core::account::Verifier;
 
                             // recv data
+                            let mut budget = $crate::payload::PayloadBudget::new(limits);
                             let mut res = Self {
                                 __lifetime: Default::default(),
-                                __sign: ::ipis::stream::DynStream::recv(&mut recv).await?,
+                                __sign: budget.recv(&mut recv, false).await?,
                                 $(
-                                    $output_field: ::ipis::stream::DynStream::recv(&mut recv).await?,
+                                    $output_field: budget
+                                        .recv(&mut recv, $crate::__define_io_is_bulk!($( $output_bulk )?))
+                                        .await?,
                                 )*
                             };
 
@@ -644,6 +1276,26 @@ macro_rules! define_io {
     }};
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_io_is_bulk {
+    () => {
+        false
+    };
+    (bulk) => {
+        true
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_io_duplex {
+    ( [ ] $( $body:tt )* ) => {};
+    ( [ $( $duplex:tt )+ ] $( $body:tt )* ) => {
+        $( $body )*
+    };
+}
+
 /// # External Call
 ///
 /// ## Usage
@@ -663,8 +1315,53 @@ macro_rules! define_io {
 /// );
 /// ```
 ///
+/// If the target is specified only by the kind, one of the accounts serving it is picked,
+/// and bound to the given name:
+///
+/// ```ignore
+/// // external call
+/// let (address,) = external_call!(
+///     client: self,
+///     target: KIND.as_ref() => pick(target),
+///     request: ::ipiis_common::io => GetAddress,
+///     sign: self.sign(target, (KIND.as_ref().copied(), account))?,
+///     inputs: { },
+///     outputs: { address, },
+/// );
+/// ```
+///
 #[macro_export]
 macro_rules! external_call {
+    (
+        client: $client:expr,
+        target: $kind:expr => pick($target:ident),
+        $( $rest:tt )*
+    ) => {
+        // keep the same target for this account by default
+        external_call!(
+            client: $client,
+            target: $kind => pick(
+                $target,
+                $crate::Ipiis::account_me($client).account_ref().as_bytes().as_ref(),
+            ),
+            $( $rest )*
+        )
+    };
+    (
+        client: $client:expr,
+        target: $kind:expr => pick($target:ident, $key:expr $(,)?),
+        $( $rest:tt )*
+    ) => {{
+        // pick a target, which is outstanding until the response
+        let __pick = $crate::Ipiis::pick($client, $kind, $key).await?;
+        let $target = *__pick;
+
+        external_call!(
+            client: $client,
+            target: $kind => &$target,
+            $( $rest )*
+        )
+    }};
     (
         client: $client:expr,
         target: $kind:expr => $target:expr,
@@ -758,6 +1455,30 @@ macro_rules! external_call {
         // recv response
         req.send($client, $kind, $target).await?
     }};
+    (
+        client: $client:expr,
+        target: $kind:expr => $target:expr,
+        request: $io:path => $req:ident,
+        sign: $input_sign:expr,
+        inputs: { $( $input_field:ident : $input_value:expr ,)* },
+        $( inputs_mode: $mode:ident ,)?
+        outputs: open,
+    ) => {{
+        // pack request
+        #[allow(clippy::redundant_field_names)]
+        let mut req = external_call!(
+            client: $client,
+            target: $kind => $target,
+            request: $io => $req,
+            sign: $input_sign,
+            inputs: { $( $input_field : $input_value ,)* },
+            $( inputs_mode: $mode ,)?
+            outputs: none,
+        );
+
+        // begin a duplex session
+        req.open($client, $kind, $target).await?
+    }};
     (
         client: $client:expr,
         target: $kind:expr => $target:expr,
@@ -855,13 +1576,20 @@ macro_rules! handle_external_call {
         name: $name:ident,
         request: $io:path => { $( $opcode:ident => $handler:ident ,)* },
         $( request_raw: $io_raw:path => { $( $opcode_raw:ident => $handler_raw:ident ,)* },)?
+        $( request_duplex: $io_duplex:path => { $( $opcode_duplex:ident => $handler_duplex:ident ,)* },)?
     ) => {
         impl $server {
-            pub async fn $name(self) {
+            pub async fn $name(self) -> Result<()> {
                 let client = self.client.clone();
 
                 let runtime: &IpiisServer = (*self.client).as_ref();
-                runtime.run(client, Self::__handle::<IpiisClient>).await
+
+                // register services
+                let mut router = runtime.router();
+                IpiisServer::register_service::<IpiisClient>(client.clone(), &mut router)?;
+                Self::register_service::<IpiisClient>(client, &mut router)?;
+
+                runtime.run(Arc::new(router)).await
             }
         }
 
@@ -869,83 +1597,173 @@ macro_rules! handle_external_call {
             server: $server => $client,
             request: $io => { $( $opcode => $handler ,)* },
             $( request_raw: $io_raw => { $( $opcode_raw => $handler_raw ,)* },)?
+            $( request_duplex: $io_duplex => { $( $opcode_duplex => $handler_duplex ,)* },)?
         );
     };
     (
         server: $server:ty => $client:ty,
         request: $io:path => { $( $opcode:ident => $handler:ident ,)* },
         $( request_raw: $io_raw:path => { $( $opcode_raw:ident => $handler_raw:ident ,)* },)?
+        $( request_duplex: $io_duplex:path => { $( $opcode_duplex:ident => $handler_duplex:ident ,)* },)?
     ) => {
         impl $server {
+            /// Register this module as a service, so that the router can dispatch its requests.
+            pub fn register_service<__IpiisClient>(
+                client: Arc<$client>,
+                router: &mut $crate::router::Router<__IpiisClient>,
+            ) -> Result<()>
+            where
+                $client: AsRef<__IpiisClient> + Send + Sync + 'static,
+                __IpiisClient: Ipiis + Send + Sync + 'static,
+            {
+                router.register($io::SERVICE, move |interceptors, addr, send, recv| {
+                    Self::__handle::<__IpiisClient>(client.clone(), interceptors, addr, send, recv)
+                })
+            }
+
             async fn __handle<__IpiisClient>(
                 client: Arc<$client>,
+                interceptors: $crate::router::Interceptors,
+                addr: ::std::net::SocketAddr,
                 mut send: <__IpiisClient as Ipiis>::Writer,
-                mut recv: <__IpiisClient as Ipiis>::Reader,
+                recv: <__IpiisClient as Ipiis>::Reader,
             ) -> Result<()>
             where
-                $client: AsRef<__IpiisClient>,
-                __IpiisClient: Ipiis,
+                $client: AsRef<__IpiisClient> + Send + Sync,
+                __IpiisClient: Ipiis + Send + Sync,
             {
                 use ipis::tokio::io::AsyncWriteExt;
 
-                match Self::__try_handle(&client, &mut send, recv).await {
+                let responded = ::std::sync::atomic::AtomicBool::new(false);
+                match Self::__try_handle(&client, &interceptors, addr, &mut send, recv, &responded).await {
                     Ok(()) => Ok(()),
+                    // the response has begun, so close the stream rather than corrupting it
+                    Err(e) if responded.load(::std::sync::atomic::Ordering::SeqCst) => {
+                        send.shutdown().await.ok();
+                        Err(e)
+                    }
                     Err(e) => {
                         // collect data
-                        let mut data = ::ipis::stream::DynStream::Owned(e.to_string());
-
-                        // make a flag
-                        let flag = ServerResult::ACK_ERR;
-
-                        // send flag
-                        send.write_u8(flag.bits()).await?;
+                        let error = $crate::error::ServerError::from_anyhow(e);
 
                         // send data
-                        data.copy_to(&mut send).await?;
-
-                        Ok(())
+                        error.send(&mut send).await
                     }
                 }
             }
 
             async fn __try_handle<__IpiisClient>(
                 client: &$client,
+                interceptors: &[Arc<dyn $crate::interceptor::Interceptor>],
+                addr: ::std::net::SocketAddr,
                 send: &mut <__IpiisClient as Ipiis>::Writer,
                 mut recv: <__IpiisClient as Ipiis>::Reader,
+                responded: &::std::sync::atomic::AtomicBool,
             ) -> Result<()>
             where
-                $client: AsRef<__IpiisClient>,
-                __IpiisClient: Ipiis,
+                $client: AsRef<__IpiisClient> + Send + Sync,
+                __IpiisClient: Ipiis + Send + Sync,
             {
-                use $io::{OpCode, request};
+                use ipis::tokio::io::AsyncReadExt;
+                use $crate::error::{ErrorKind, ServerError};
+                use $crate::interceptor::{dispatch, isolate, Context};
+                use $io::{OpCode, request, SERVICE, VERSION};
+
+                // recv module version
+                let version = recv.read_u32().await?;
 
                 // recv opcode
-                let opcode: OpCode = ::ipis::stream::DynStream::recv(&mut recv)
-                    .await?
-                    .to_owned()
-                    .await?;
+                let code = recv.read_u32().await?;
+                let opcode = match OpCode::from_code(code) {
+                    Some(opcode) => opcode,
+                    // the opcode is added in the newer version of the module
+                    None if version > VERSION => ::ipis::core::anyhow::bail!(ServerError::new(
+                        ErrorKind::IncompatibleVersion,
+                        format!("module {SERVICE}: client={version}, server={VERSION}"),
+                    )),
+                    None => ::ipis::core::anyhow::bail!(ServerError::new(
+                        ErrorKind::UnknownOpcode,
+                        format!("{code} (version: client={version}, server={VERSION})"),
+                    )),
+                };
 
                 // select command
                 match opcode {
                     $(
                         OpCode::$opcode => {
+                            // make a context
+                            let mut context = Context {
+                                service: SERVICE,
+                                opcode: opcode.name(),
+                                code,
+                                addr: Some(addr),
+                                peer: None,
+                                session: false,
+                            };
+
                             // recv request
-                            let mut req = request::$opcode::recv(client.as_ref(), recv).await?;
+                            let mut req = isolate(&context, request::$opcode::recv(client.as_ref(), recv)).await?;
+                            context.peer = Some(req.__sign.to_owned().await?.guarantee.account);
 
-                            // handle request
-                            let mut res = Self::$handler(client, req).await?;
+                            let handler = async move {
+                                // handle request
+                                let mut res = Self::$handler(client, req).await?;
 
-                            // send response
-                            res.send(client.as_ref(), &mut *send).await
+                                // send response
+                                responded.store(true, ::std::sync::atomic::Ordering::SeqCst);
+                                res.send(client.as_ref(), &mut *send).await
+                            };
+                            dispatch(interceptors, &context, Box::pin(handler)).await
                         }
                     )*
                     $($(
                         OpCode::$opcode_raw => {
-                            // handle raw request
-                            let mut res = Self::$handler_raw(client, recv).await?;
+                            // make a context
+                            let context = Context {
+                                service: SERVICE,
+                                opcode: opcode.name(),
+                                code,
+                                addr: Some(addr),
+                                peer: None,
+                                session: false,
+                            };
+
+                            let handler = async move {
+                                // handle raw request
+                                let mut res = Self::$handler_raw(client, recv).await?;
 
-                            // send response
-                            res.send(client.as_ref(), &mut *send).await
+                                // send response
+                                responded.store(true, ::std::sync::atomic::Ordering::SeqCst);
+                                res.send(client.as_ref(), &mut *send).await
+                            };
+                            dispatch(interceptors, &context, Box::pin(handler)).await
+                        },
+                    )*)?
+                    $($(
+                        OpCode::$opcode_duplex => {
+                            // make a context
+                            let mut context = Context {
+                                service: SERVICE,
+                                opcode: opcode.name(),
+                                code,
+                                addr: Some(addr),
+                                peer: None,
+                                session: true,
+                            };
+
+                            // recv request
+                            let mut req = isolate(&context, request::$opcode_duplex::recv(client.as_ref(), &mut recv)).await?;
+
+                            // select the peer
+                            let peer = req.__sign.to_owned().await?.guarantee.account;
+                            context.peer = Some(peer);
+
+                            let handler = async move {
+                                // handle duplex session
+                                let pending = $crate::duplex::Pending::<__IpiisClient>::new(send, recv, peer, addr, responded);
+                                Self::$handler_duplex(client, req, pending).await
+                            };
+                            dispatch(interceptors, &context, Box::pin(handler)).await
                         },
                     )*)?
                 }
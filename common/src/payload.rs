@@ -0,0 +1,91 @@
+use ipis::{
+    core::anyhow::{bail, Result},
+    rkyv::Archive,
+    stream::DynStream,
+    tokio::io::{AsyncRead, AsyncReadExt},
+};
+
+use crate::error::{ErrorKind, ServerError};
+
+/// The size limits of the incoming messages, which are checked before allocating them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PayloadLimits {
+    /// The maximum size of a field.
+    pub max_field_size: u64,
+    /// The maximum size of a field which is declared as `#[bulk]` in `define_io!`.
+    pub max_bulk_field_size: u64,
+    /// The maximum total size of the fields in a request or a response.
+    pub max_request_size: u64,
+}
+
+impl Default for PayloadLimits {
+    fn default() -> Self {
+        Self {
+            max_field_size: 16 << 20,     // 16 MiB
+            max_bulk_field_size: 2 << 30, // 2 GiB
+            max_request_size: 4 << 30,    // 4 GiB
+        }
+    }
+}
+
+/// The remaining size of a request (or a response), which is consumed field by field.
+#[derive(Clone, Debug)]
+pub struct PayloadBudget {
+    limits: PayloadLimits,
+    remaining: u64,
+}
+
+impl PayloadBudget {
+    pub fn new(limits: PayloadLimits) -> Self {
+        Self {
+            limits,
+            remaining: limits.max_request_size,
+        }
+    }
+
+    /// Receive a field after checking its announced size.
+    ///
+    /// The size is the length prefix of the `DynStream`, which is peeked here
+    /// and then passed through to `DynStream::recv` along with the rest of the field.
+    pub async fn recv<T, R>(&mut self, mut recv: R, bulk: bool) -> Result<DynStream<'static, T>>
+    where
+        T: Archive,
+        R: AsyncRead + Unpin,
+    {
+        // recv length
+        let len = recv.read_u64().await?;
+
+        // check length
+        let max_field_size = if bulk {
+            self.limits.max_bulk_field_size
+        } else {
+            self.limits.max_field_size
+        };
+        if len > max_field_size {
+            bail!(ServerError::new(
+                ErrorKind::PayloadTooLarge,
+                format!("field: {len} bytes > {max_field_size} bytes"),
+            ));
+        }
+        if len > self.remaining {
+            bail!(ServerError::new(
+                ErrorKind::PayloadTooLarge,
+                format!("request: more than {} bytes", self.limits.max_request_size),
+            ));
+        }
+        self.remaining -= len;
+
+        // recv data
+        let prefix = len.to_be_bytes();
+        DynStream::recv((&prefix[..]).chain(recv)).await
+    }
+}
+
+/// Receive a single message after checking its announced size.
+pub async fn recv_limited<T, R>(recv: R, limits: PayloadLimits) -> Result<DynStream<'static, T>>
+where
+    T: Archive,
+    R: AsyncRead + Unpin,
+{
+    PayloadBudget::new(limits).recv(recv, false).await
+}
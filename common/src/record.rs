@@ -0,0 +1,92 @@
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ipis::core::{
+    account::{AccountRef, GuaranteeSigned, Verifier},
+    anyhow::{bail, Result},
+    signature::SignatureSerializer,
+    value::hash::Hash,
+};
+use rkyv::{Archive, Serialize};
+
+use crate::{
+    error::{ErrorKind, ServerError},
+    Ipiis,
+};
+
+/// The default lifetime of an address record, which should be refreshed before it expires.
+pub const DEFAULT_RECORD_TTL: Duration = Duration::from_secs(300);
+
+/// The kind, the address, the timestamp and the expiry (in milliseconds) of an account.
+pub type AddressRecordData<Address> = (Option<Hash>, Address, u64, u64);
+
+/// An address record, which is signed by the account it describes.
+///
+/// It is self-certifying, so that any node may cache and serve it
+/// without being able to forge the address of the other accounts.
+pub type AddressRecord<Address> = GuaranteeSigned<AddressRecordData<Address>>;
+
+/// The current timestamp in milliseconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Sign the address record of the client, which is sent to the target.
+pub fn sign_record<IpiisClient>(
+    client: &IpiisClient,
+    target: AccountRef,
+    kind: Option<&Hash>,
+    address: <IpiisClient as Ipiis>::Address,
+) -> Result<AddressRecord<<IpiisClient as Ipiis>::Address>>
+where
+    IpiisClient: Ipiis,
+    AddressRecordData<<IpiisClient as Ipiis>::Address>:
+        Archive + Serialize<SignatureSerializer> + Send,
+    <AddressRecordData<<IpiisClient as Ipiis>::Address> as Archive>::Archived:
+        ::core::fmt::Debug + PartialEq,
+{
+    let timestamp = now();
+    let expiry = timestamp + DEFAULT_RECORD_TTL.as_millis() as u64;
+
+    client.sign(target, (kind.copied(), address, timestamp, expiry))
+}
+
+/// Ensure that the record is a valid and live one, which is signed by the target itself.
+pub fn verify_record<Address>(
+    record: &AddressRecord<Address>,
+    kind: Option<&Hash>,
+    target: &AccountRef,
+) -> Result<()>
+where
+    AddressRecord<Address>: Verifier,
+{
+    // verify the signature
+    record.verify(None)?;
+
+    // verify the owner
+    if &record.guarantee.account != target {
+        bail!(ServerError::new(
+            ErrorKind::Unauthorized,
+            format!("address record is not signed by {target}"),
+        ));
+    }
+
+    // verify the data
+    let (record_kind, _, _, expiry) = &record.data.data;
+    if record_kind.as_ref() != kind {
+        bail!(ServerError::new(
+            ErrorKind::Unauthorized,
+            format!("address record of {target} has another kind"),
+        ));
+    }
+    if *expiry <= now() {
+        bail!(ServerError::new(
+            ErrorKind::Unauthorized,
+            format!("address record of {target} is expired"),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use ipis::core::{
+    account::AccountRef,
+    anyhow::{bail, Result},
+};
+
+use crate::error::{ErrorKind, ServerError};
+
+/// The default number of the primaries which may be asked to resolve an address.
+pub const DEFAULT_MAX_HOPS: u32 = 8;
+
+/// A resolved address, along with the accounts which took part in resolving it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolution<Address> {
+    pub address: Address,
+    /// The visited accounts, from the requester to the one which knows the address.
+    pub path: Vec<AccountRef>,
+}
+
+/// A resolved primary of a kind, along with the accounts which took part in resolving it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrimaryResolution<Address> {
+    pub account: AccountRef,
+    pub address: Option<Address>,
+    /// The visited accounts, from the requester to the one which knows the primary.
+    pub path: Vec<AccountRef>,
+}
+
+/// Append the current account to the visited path, rejecting a cycle.
+pub fn visit(path: &[AccountRef], me: AccountRef) -> Result<Vec<AccountRef>> {
+    if path.contains(&me) {
+        bail!(ServerError::new(
+            ErrorKind::Unresolvable,
+            format!("cycle detected: {} -> {me}", display_path(path)),
+        ));
+    }
+
+    let mut path = path.to_vec();
+    path.push(me);
+    Ok(path)
+}
+
+/// Ensure that the next primary may be asked.
+pub fn ensure_hops(path: &[AccountRef], hops: u32) -> Result<()> {
+    if hops == 0 {
+        bail!(ServerError::new(
+            ErrorKind::Unresolvable,
+            format!("hop limit exceeded: {}", display_path(path)),
+        ));
+    }
+    Ok(())
+}
+
+pub fn display_path(path: &[AccountRef]) -> String {
+    path.iter()
+        .map(|account| account.to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
@@ -0,0 +1,165 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use ipis::{
+    core::{
+        anyhow::{bail, Result},
+        value::hash::Hash,
+    },
+    futures::{future::BoxFuture, Future, FutureExt},
+    tokio::io::AsyncReadExt,
+};
+
+use crate::{
+    error::{ErrorKind, ServerError},
+    interceptor::Interceptor,
+    payload::{recv_limited, PayloadLimits},
+    Ipiis,
+};
+
+/// The server-side interceptors, which are shared by all services of a router.
+pub type Interceptors = Arc<Vec<Arc<dyn Interceptor>>>;
+
+type Handler<IpiisClient> = Arc<
+    dyn Fn(
+            Interceptors,
+            SocketAddr,
+            <IpiisClient as Ipiis>::Writer,
+            <IpiisClient as Ipiis>::Reader,
+        ) -> BoxFuture<'static, Result<()>>
+        + Send
+        + Sync,
+>;
+
+/// Dispatches each incoming stream to the service which is requested in its header.
+///
+/// Each `define_io!` module is a service, so that a single server can host
+/// the built-in directory service along with any number of modules.
+pub struct Router<IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    services: Vec<(Hash, &'static str, Handler<IpiisClient>)>,
+    interceptors: Interceptors,
+    payload_limits: PayloadLimits,
+}
+
+impl<IpiisClient> Default for Router<IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    fn default() -> Self {
+        Self {
+            services: Default::default(),
+            interceptors: Default::default(),
+            payload_limits: Default::default(),
+        }
+    }
+}
+
+impl<IpiisClient> Router<IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    pub fn with_interceptors(interceptors: Vec<Arc<dyn Interceptor>>) -> Self {
+        Self {
+            services: Default::default(),
+            interceptors: Arc::new(interceptors),
+            payload_limits: Default::default(),
+        }
+    }
+
+    /// Limit the size of the request headers, which are received before selecting a service.
+    pub fn set_payload_limits(&mut self, limits: PayloadLimits) {
+        self.payload_limits = limits;
+    }
+
+    /// Apply the interceptor to every request of all services, after the ones added before.
+    pub fn add_interceptor<I>(&mut self, interceptor: I)
+    where
+        I: Interceptor + 'static,
+    {
+        Arc::make_mut(&mut self.interceptors).push(Arc::new(interceptor));
+    }
+
+    pub fn register<F, Fut>(&mut self, service: &'static str, handler: F) -> Result<()>
+    where
+        F: Fn(
+                Interceptors,
+                SocketAddr,
+                <IpiisClient as Ipiis>::Writer,
+                <IpiisClient as Ipiis>::Reader,
+            ) -> Fut
+            + Send
+            + Sync
+            + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let id = Hash::with_str(service);
+        if self.services.iter().any(|(key, _, _)| key == &id) {
+            bail!("duplicated service: {service}");
+        }
+
+        self.services.push((
+            id,
+            service,
+            Arc::new(move |interceptors, addr, send, recv| {
+                handler(interceptors, addr, send, recv).boxed()
+            }),
+        ));
+        Ok(())
+    }
+
+    pub fn services(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.services.iter().map(|(_, name, _)| *name)
+    }
+
+    pub async fn handle(
+        &self,
+        addr: SocketAddr,
+        mut send: <IpiisClient as Ipiis>::Writer,
+        recv: <IpiisClient as Ipiis>::Reader,
+    ) -> Result<()> {
+        match self.try_handle(recv).await {
+            Ok((handler, recv)) => handler(self.interceptors.clone(), addr, send, recv).await,
+            Err(e) => {
+                // collect data
+                let error = ServerError::from_anyhow(e);
+
+                // send data
+                error.send(&mut send).await
+            }
+        }
+    }
+
+    async fn try_handle(
+        &self,
+        mut recv: <IpiisClient as Ipiis>::Reader,
+    ) -> Result<(Handler<IpiisClient>, <IpiisClient as Ipiis>::Reader)> {
+        // recv protocol version
+        let protocol_version = recv.read_u16().await?;
+        if protocol_version != crate::PROTOCOL_VERSION {
+            bail!(ServerError::new(
+                ErrorKind::IncompatibleVersion,
+                format!(
+                    "protocol: client={protocol_version}, server={}",
+                    crate::PROTOCOL_VERSION,
+                ),
+            ));
+        }
+
+        // recv service
+        let service: Hash = recv_limited(&mut recv, self.payload_limits)
+            .await?
+            .to_owned()
+            .await?;
+
+        // select service
+        match self.services.iter().find(|(key, _, _)| key == &service) {
+            Some((_, _, handler)) => Ok((handler.clone(), recv)),
+            None => bail!(ServerError::new(
+                ErrorKind::UnknownService,
+                "the requested service is not hosted on this server",
+            )),
+        }
+    }
+}
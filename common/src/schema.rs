@@ -0,0 +1,192 @@
+use core::str::FromStr;
+
+use ipis::core::anyhow::{anyhow, bail, Error, Result};
+
+/// A recordable description of the opcodes generated by `define_io!`.
+///
+/// The textual form (`Display`/`FromStr`) is meant to be committed along with the module,
+/// so that a later `define_io!` can be checked against it with [`Schema::ensure_compatible`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Schema {
+    /// The name of the service, which is empty if recorded before the services are routed.
+    pub service: String,
+    pub version: u32,
+    pub opcodes: Vec<OpCodeSchema>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpCodeSchema {
+    pub code: u32,
+    pub name: String,
+    pub inputs: String,
+    pub input_sign: String,
+    pub outputs: String,
+    pub output_sign: String,
+    pub duplex: String,
+}
+
+impl OpCodeSchema {
+    const SECTIONS: [&'static str; 5] =
+        ["inputs", "input_sign", "outputs", "output_sign", "duplex"];
+
+    fn sections(&self) -> [&str; 5] {
+        [
+            &self.inputs,
+            &self.input_sign,
+            &self.outputs,
+            &self.output_sign,
+            &self.duplex,
+        ]
+    }
+}
+
+impl ::core::fmt::Display for Schema {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+        writeln!(f, "service: {}", self.service)?;
+        writeln!(f, "version: {}", self.version)?;
+        for opcode in &self.opcodes {
+            write!(f, "{} {}", opcode.code, opcode.name)?;
+            for (key, value) in OpCodeSchema::SECTIONS.iter().zip(opcode.sections()) {
+                write!(f, " | {key}: {value}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Schema {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .peekable();
+
+        // the older schemas have no service
+        let service = match lines.peek().and_then(|line| line.strip_prefix("service:")) {
+            Some(service) => {
+                let service = service.trim().to_string();
+                lines.next();
+                service
+            }
+            None => Default::default(),
+        };
+
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("version:"))
+            .ok_or_else(|| anyhow!("schema version is missing"))?
+            .trim()
+            .parse()?;
+
+        let opcodes = lines
+            .map(|line| {
+                let mut sections = line.split(" | ");
+
+                let (code, name) = sections
+                    .next()
+                    .and_then(|head| head.split_once(' '))
+                    .ok_or_else(|| anyhow!("malformed opcode: {line}"))?;
+
+                let mut next = |key: &str| {
+                    sections
+                        .next()
+                        .and_then(|section| section.strip_prefix(key))
+                        .and_then(|section| section.strip_prefix(':'))
+                        .map(|section| section.trim().to_string())
+                        .ok_or_else(|| anyhow!("malformed opcode section {key:?}: {line}"))
+                };
+
+                Ok(OpCodeSchema {
+                    code: code.trim().parse()?,
+                    name: name.trim().to_string(),
+                    inputs: next("inputs")?,
+                    input_sign: next("input_sign")?,
+                    outputs: next("outputs")?,
+                    output_sign: next("output_sign")?,
+                    duplex: next("duplex")?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            service,
+            version,
+            opcodes,
+        })
+    }
+}
+
+impl Schema {
+    /// Check that peers built with the `previous` schema can still talk to this one.
+    ///
+    /// Every recorded opcode should keep its number, name and signature,
+    /// and the version should be bumped whenever a new opcode is added.
+    pub fn ensure_compatible(&self, previous: &Self) -> Result<()> {
+        if !previous.service.is_empty() && self.service != previous.service {
+            bail!(
+                "service is renamed: {} -> {}",
+                previous.service,
+                self.service,
+            );
+        }
+        if self.version < previous.version {
+            bail!(
+                "version is downgraded: {} -> {}",
+                previous.version,
+                self.version,
+            );
+        }
+
+        for old in &previous.opcodes {
+            let new = match self.opcodes.iter().find(|new| new.code == old.code) {
+                Some(new) => new,
+                None => bail!("opcode is removed: {} {}", old.code, old.name),
+            };
+
+            if new.name != old.name {
+                bail!(
+                    "opcode is renamed: {} {} -> {}",
+                    old.code,
+                    old.name,
+                    new.name,
+                );
+            }
+
+            for ((key, old_section), new_section) in OpCodeSchema::SECTIONS
+                .iter()
+                .zip(old.sections())
+                .zip(new.sections())
+            {
+                if normalize(old_section) != normalize(new_section) {
+                    bail!(
+                        "opcode {key} is changed: {} {}: {old_section:?} -> {new_section:?}",
+                        old.code,
+                        old.name,
+                    );
+                }
+            }
+        }
+
+        let is_extended = self
+            .opcodes
+            .iter()
+            .any(|new| !previous.opcodes.iter().any(|old| old.code == new.code));
+        if is_extended && self.version == previous.version {
+            bail!("version should be bumped when adding opcodes");
+        }
+        Ok(())
+    }
+}
+
+/// Check the current schema of `define_io!` against a recorded one.
+pub fn ensure_compatible(current: &Schema, recorded: &str) -> Result<()> {
+    current.ensure_compatible(&recorded.parse()?)
+}
+
+fn normalize(section: &str) -> String {
+    section.split_whitespace().collect()
+}
@@ -0,0 +1,42 @@
+use ipiis_common::balance::{Balancer, Strategy};
+use ipis::core::account::Account;
+
+#[test]
+fn test_balance() {
+    let a = Account::generate().account_ref();
+    let b = Account::generate().account_ref();
+    let c = Account::generate().account_ref();
+    let replicas = [(a, 2), (b, 1), (c, 0)];
+
+    let balancer = Balancer::default();
+    let pick = |strategy, key: &[u8]| balancer.pick(strategy, None, &replicas, key).unwrap();
+
+    // visit the accounts as many times as their weights
+    let picked: Vec<_> = (0..6).map(|_| *pick(Strategy::RoundRobin, &[])).collect();
+    assert_eq!(picked, [a, a, b, a, a, b]);
+
+    // skip the accounts without weights
+    for _ in 0..32 {
+        assert_ne!(*pick(Strategy::Random, &[]), c);
+    }
+
+    // prefer the account with the fewest in-flight requests per weight
+    let first = pick(Strategy::LeastOutstanding, &[]);
+    let second = pick(Strategy::LeastOutstanding, &[]);
+    assert_ne!(*first, *second);
+    assert_eq!(balancer.outstanding(&first), 1);
+    drop(first);
+    drop(second);
+    assert_eq!(balancer.outstanding(&a), 0);
+
+    // keep the same account for the same key
+    let expected = *pick(Strategy::ConsistentHash, b"key");
+    for _ in 0..8 {
+        assert_eq!(*pick(Strategy::ConsistentHash, b"key"), expected);
+    }
+
+    // fail without any weights
+    assert!(balancer
+        .pick(Strategy::RoundRobin, None, &[(c, 0)], &[])
+        .is_none());
+}
@@ -0,0 +1,72 @@
+use std::io::Cursor;
+
+use ipiis_common::duplex::Duplex;
+use ipis::{
+    core::{
+        account::{Account, AccountRef},
+        value::hash::Hash,
+    },
+    tokio,
+};
+
+type Session<'a> = Duplex<'a, Vec<u8>, Cursor<Vec<u8>>, String, String>;
+
+fn new_session(account_me: &Account, peer: AccountRef, input: Vec<u8>) -> Session<'_> {
+    let session = Hash::with_str("session");
+    Duplex::new(account_me, peer, session, Vec::new(), Cursor::new(input))
+}
+
+/// Encode the messages as they are sent on a session.
+async fn encode(account_me: &Account, peer: AccountRef, signed: bool, msgs: &[&str]) -> Vec<u8> {
+    let mut session = new_session(account_me, peer, vec![]).signed(signed);
+    for msg in msgs {
+        session.send(msg.to_string()).await.unwrap();
+    }
+    session.into_inner().0
+}
+
+#[tokio::test]
+async fn test_duplex() {
+    let alice = Account::generate();
+    let bob = Account::generate();
+    let eve = Account::generate();
+
+    // receive the signed messages in order
+    let input = encode(&alice, bob.account_ref(), true, &["hello", "world"]).await;
+    let mut session = new_session(&bob, alice.account_ref(), input).require_signed(true);
+    assert_eq!(session.recv().await.unwrap(), Some("hello".to_string()));
+    assert_eq!(session.recv().await.unwrap(), Some("world".to_string()));
+    assert_eq!(session.recv().await.unwrap(), None);
+
+    // reject the unsigned messages only if required
+    let input = encode(&alice, bob.account_ref(), false, &["hello"]).await;
+    let mut session = new_session(&bob, alice.account_ref(), input.clone());
+    assert_eq!(session.recv().await.unwrap(), Some("hello".to_string()));
+    let mut session = new_session(&bob, alice.account_ref(), input).require_signed(true);
+    assert!(session.recv().await.is_err());
+
+    // reject the messages signed by others
+    let input = encode(&eve, bob.account_ref(), true, &["hello"]).await;
+    let mut session = new_session(&bob, alice.account_ref(), input);
+    assert!(session.recv().await.is_err());
+
+    // reject the replayed messages
+    let input = encode(&alice, bob.account_ref(), true, &["hello"]).await;
+    let mut session = new_session(&bob, alice.account_ref(), input.repeat(2));
+    assert_eq!(session.recv().await.unwrap(), Some("hello".to_string()));
+    assert!(session.recv().await.is_err());
+
+    // reject the messages replayed from another session
+    let mut session: Session = Duplex::new(
+        &alice,
+        bob.account_ref(),
+        Hash::with_str("another session"),
+        Vec::new(),
+        Cursor::new(vec![]),
+    )
+    .signed(true);
+    session.send("hello".to_string()).await.unwrap();
+    let input = session.into_inner().0;
+    let mut session = new_session(&bob, alice.account_ref(), input).require_signed(true);
+    assert!(session.recv().await.is_err());
+}
@@ -0,0 +1,122 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    interceptor::{dispatch, Context, Interceptor, Next},
+};
+use ipis::{
+    async_trait::async_trait,
+    core::anyhow::{bail, Result},
+    futures::FutureExt,
+    tokio,
+};
+
+/// Record the requests which pass through it.
+struct Recorder {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Interceptor for Recorder {
+    async fn intercept(&self, ctx: &Context, next: Next<'_>) -> Result<()> {
+        self.log.lock().unwrap().push(format!(
+            "{}: {}::{} ({}) from {:?}",
+            self.name, ctx.service, ctx.opcode, ctx.code, ctx.addr,
+        ));
+        next.run(ctx).await
+    }
+}
+
+/// Reject the requests of the given opcode.
+struct Rejector {
+    opcode: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Interceptor for Rejector {
+    async fn intercept(&self, ctx: &Context, next: Next<'_>) -> Result<()> {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("rejector: {}::{}", ctx.service, ctx.opcode));
+        if ctx.opcode == self.opcode {
+            bail!(ServerError::new(
+                ErrorKind::Unauthorized,
+                format!("rejected: {}", ctx.opcode),
+            ))
+        }
+        next.run(ctx).await
+    }
+}
+
+fn context(opcode: &'static str, code: u32) -> Context {
+    Context {
+        service: "ipiis",
+        opcode,
+        code,
+        addr: Some("127.0.0.1:5001".parse().unwrap()),
+        peer: None,
+        session: false,
+    }
+}
+
+#[tokio::test]
+async fn test_interceptor_chain() {
+    let log = Arc::new(Mutex::new(vec![]));
+    let interceptors: Vec<Arc<dyn Interceptor>> = vec![
+        Arc::new(Recorder {
+            name: "recorder",
+            log: log.clone(),
+        }),
+        Arc::new(Rejector {
+            opcode: "SetAccountPrimary",
+            log: log.clone(),
+        }),
+    ];
+
+    // pass the accepted requests through all interceptors in order
+    let handled = AtomicBool::new(false);
+    let ctx = context("GetAccountPrimary", 1);
+    let handler = async {
+        handled.store(true, Ordering::SeqCst);
+        Ok(())
+    };
+    dispatch(&interceptors, &ctx, handler.boxed())
+        .await
+        .unwrap();
+    assert!(handled.load(Ordering::SeqCst));
+    assert_eq!(
+        log.lock().unwrap().drain(..).collect::<Vec<_>>(),
+        [
+            "recorder: ipiis::GetAccountPrimary (1) from Some(127.0.0.1:5001)",
+            "rejector: ipiis::GetAccountPrimary",
+        ],
+    );
+
+    // short-circuit the rejected requests before the handler
+    let handled = AtomicBool::new(false);
+    let ctx = context("SetAccountPrimary", 2);
+    let handler = async {
+        handled.store(true, Ordering::SeqCst);
+        Ok(())
+    };
+    let error = dispatch(&interceptors, &ctx, handler.boxed())
+        .await
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::Unauthorized);
+    assert!(!handled.load(Ordering::SeqCst));
+    assert_eq!(
+        log.lock().unwrap().drain(..).collect::<Vec<_>>(),
+        [
+            "recorder: ipiis::SetAccountPrimary (2) from Some(127.0.0.1:5001)",
+            "rejector: ipiis::SetAccountPrimary",
+        ],
+    );
+}
@@ -0,0 +1,53 @@
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    payload::{PayloadBudget, PayloadLimits},
+};
+use ipis::{stream::DynStream, tokio};
+
+async fn encode(data: Vec<u8>) -> Vec<u8> {
+    let mut buf = Vec::new();
+    DynStream::Owned(data).copy_to(&mut buf).await.unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn test_payload_limits() {
+    let limits = PayloadLimits {
+        max_field_size: 64,
+        max_bulk_field_size: 1024,
+        max_request_size: 1536,
+    };
+    let small = encode(vec![42; 16]).await;
+    let large = encode(vec![42; 512]).await;
+
+    // accept the fields within the limits
+    let mut budget = PayloadBudget::new(limits);
+    let data: Vec<u8> = budget
+        .recv(&small[..], false)
+        .await
+        .unwrap()
+        .to_owned()
+        .await
+        .unwrap();
+    assert_eq!(data, vec![42; 16]);
+    budget.recv::<Vec<u8>, _>(&large[..], true).await.unwrap();
+
+    // reject the large field before receiving it
+    let error = budget
+        .recv::<Vec<u8>, _>(&large[..], false)
+        .await
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::PayloadTooLarge);
+
+    // reject the request exceeding its total size
+    budget.recv::<Vec<u8>, _>(&large[..], true).await.unwrap();
+    let error = budget
+        .recv::<Vec<u8>, _>(&large[..], true)
+        .await
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::PayloadTooLarge);
+}
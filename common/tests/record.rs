@@ -0,0 +1,49 @@
+use std::net::SocketAddr;
+
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    record::{self, AddressRecord},
+};
+use ipis::core::{
+    account::{Account, AccountRef},
+    metadata::Metadata,
+    value::hash::Hash,
+};
+
+fn sign(account: &Account, kind: Option<Hash>, expiry: u64) -> AddressRecord<SocketAddr> {
+    let address: SocketAddr = "127.0.0.1:9801".parse().unwrap();
+    let data = (kind, address, record::now(), expiry);
+
+    Metadata::builder()
+        .build(account, account.account_ref(), data)
+        .unwrap()
+}
+
+fn kind_of(error: ipis::core::anyhow::Error) -> ErrorKind {
+    error.downcast::<ServerError>().unwrap().kind
+}
+
+#[test]
+fn test_address_record() {
+    let alice = Account::generate();
+    let bob: AccountRef = Account::generate().account_ref();
+    let kind = Hash::with_str("my_kind");
+    let expiry = record::now() + 60_000;
+
+    // accept the record signed by the target itself
+    let record = sign(&alice, Some(kind), expiry);
+    record::verify_record(&record, Some(&kind), &alice.account_ref()).unwrap();
+
+    // reject the record of another account
+    let error = record::verify_record(&record, Some(&kind), &bob).unwrap_err();
+    assert_eq!(kind_of(error), ErrorKind::Unauthorized);
+
+    // reject the record of another kind
+    let error = record::verify_record(&record, None, &alice.account_ref()).unwrap_err();
+    assert_eq!(kind_of(error), ErrorKind::Unauthorized);
+
+    // reject the expired record
+    let record = sign(&alice, Some(kind), record::now() - 1);
+    let error = record::verify_record(&record, Some(&kind), &alice.account_ref()).unwrap_err();
+    assert_eq!(kind_of(error), ErrorKind::Unauthorized);
+}
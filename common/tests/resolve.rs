@@ -0,0 +1,28 @@
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    resolve,
+};
+use ipis::core::account::Account;
+
+#[test]
+fn test_resolve_path() {
+    let a = Account::generate().account_ref();
+    let b = Account::generate().account_ref();
+
+    // visit the accounts in order
+    let path = resolve::visit(&[], a).unwrap();
+    let path = resolve::visit(&path, b).unwrap();
+    assert_eq!(path, vec![a, b]);
+    assert!(resolve::ensure_hops(&path, 1).is_ok());
+
+    // detect a cycle
+    let error = resolve::visit(&path, a).unwrap_err();
+    let error: ServerError = error.downcast().unwrap();
+    assert_eq!(error.kind, ErrorKind::Unresolvable);
+    assert!(error.message.contains(&a.to_string()));
+
+    // exceed the hop limit
+    let error = resolve::ensure_hops(&path, 0).unwrap_err();
+    let error: ServerError = error.downcast().unwrap();
+    assert_eq!(error.kind, ErrorKind::Unresolvable);
+}
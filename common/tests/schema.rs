@@ -0,0 +1,41 @@
+use ipiis_common::{io, schema};
+
+/// The recorded schemas of `ipiis_common::io`, from the oldest to the latest.
+const SCHEMAS: &[&str] = &[
+    include_str!("schema/io.v1.txt"),
+    include_str!("schema/io.v2.txt"),
+];
+
+const SCHEMA_LATEST: &str = SCHEMAS[SCHEMAS.len() - 1];
+
+#[test]
+fn test_io_compatibility() {
+    for recorded in SCHEMAS {
+        schema::ensure_compatible(&io::schema(), recorded).unwrap()
+    }
+
+    // the latest one should be up-to-date
+    let latest: schema::Schema = SCHEMA_LATEST.parse().unwrap();
+    assert_eq!(io::schema().version, latest.version);
+    assert_eq!(io::schema().opcodes.len(), latest.opcodes.len());
+}
+
+#[test]
+fn test_io_incompatibility() {
+    // rename the service
+    let mut current = io::schema();
+    current.service = "other".to_string();
+    assert!(schema::ensure_compatible(&current, SCHEMA_LATEST).is_err());
+
+    // renumber an opcode
+    let mut current = io::schema();
+    current.opcodes[0].code = 42;
+    assert!(schema::ensure_compatible(&current, SCHEMA_LATEST).is_err());
+
+    // add an opcode without bumping the version
+    let mut current = io::schema();
+    let mut opcode = current.opcodes[0].clone();
+    opcode.code = 42;
+    current.opcodes.push(opcode);
+    assert!(schema::ensure_compatible(&current, SCHEMA_LATEST).is_err());
+}
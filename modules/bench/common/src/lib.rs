@@ -41,9 +41,11 @@ where
 }
 
 define_io! {
-    Ping {
+    service: "ipiis_bench",
+    version: 1,
+    Ping = 1 {
         inputs: {
-            data: Vec<u8>,
+            #[bulk] data: Vec<u8>,
         },
         input_sign: GuaranteeSigned<u8>,
         outputs: { },
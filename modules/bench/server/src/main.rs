@@ -4,7 +4,7 @@ use byte_unit::Byte;
 use clap::{Parser, Subcommand};
 use ipiis_api::{
     client::IpiisClient,
-    common::{handle_external_call, Ipiis, ServerResult},
+    common::{handle_external_call, payload::PayloadBudget, Ipiis, ServerResult},
     server::IpiisServer,
 };
 use ipiis_modules_bench_common::{IpiisBench, KIND};
@@ -71,12 +71,14 @@ impl IpiisBenchServer {
     where
         R: AsyncRead + Send + Unpin + 'static,
     {
+        let mut budget = PayloadBudget::new(client.payload_limits());
+
         // recv sign
         let sign_as_guarantee: GuaranteeSigned<u8> =
-            DynStream::recv(&mut recv).await?.into_owned().await?;
+            budget.recv(&mut recv, false).await?.into_owned().await?;
 
         // recv data
-        let _ = DynStream::<Vec<u8>>::recv(recv).await?;
+        let _ = budget.recv::<Vec<u8>, _>(recv, true).await?;
 
         // sign data
         let sign = client.sign_as_guarantor(sign_as_guarantee)?;
@@ -207,8 +209,7 @@ async fn main() -> Result<()> {
             info!("- Address: {address}:{port}");
 
             // deploy the server
-            server.run().await;
-            Ok(())
+            server.run().await
         }
     }
 }
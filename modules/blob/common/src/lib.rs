@@ -0,0 +1,210 @@
+pub mod store;
+
+use std::collections::HashSet;
+
+use ipiis_common::{define_io, external_call, Ipiis, ServerResult};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{GuaranteeSigned, GuarantorSigned},
+        anyhow::{bail, Result},
+        value::hash::Hash,
+    },
+};
+
+use crate::store::ChunkStore;
+
+#[async_trait]
+pub trait IpiisBlob {
+    /// Filter the chunks which are not stored in the remote yet, keeping their order.
+    async fn has(&self, hashes: &[Hash]) -> Result<Vec<Hash>>;
+
+    async fn get_chunk(&self, hash: &Hash) -> Result<Vec<u8>>;
+
+    async fn put_chunk(&self, chunk: Vec<u8>) -> Result<Hash>;
+
+    /// The largest chunk which is accepted by the remote.
+    async fn max_chunk_size(&self) -> Result<usize>;
+
+    /// Download the chunks into the local store, and then assemble them.
+    ///
+    /// Only the missing chunks are received, so an interrupted download can be resumed
+    /// by calling it again with the same store.
+    async fn get_blob<S>(&self, hashes: &[Hash], store: &S) -> Result<Vec<u8>>
+    where
+        S: ChunkStore + ?Sized;
+
+    /// Upload the data chunk by chunk, returning the hashes of the chunks.
+    ///
+    /// The chunks are no larger than the remote accepts, even if `chunk_size` is.
+    /// Only the missing chunks are sent, so an interrupted upload can be resumed
+    /// by calling it again.
+    async fn put_blob(&self, data: &[u8], chunk_size: usize) -> Result<Vec<Hash>>;
+}
+
+#[async_trait]
+impl<IpiisClient> IpiisBlob for IpiisClient
+where
+    IpiisClient: Ipiis + Send + Sync,
+{
+    async fn has(&self, hashes: &[Hash]) -> Result<Vec<Hash>> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (missing,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Has,
+            sign: self.sign(target, hashes.to_vec())?,
+            inputs: { },
+            outputs: { missing, },
+        );
+
+        // unpack data
+        Ok(missing)
+    }
+
+    async fn get_chunk(&self, hash: &Hash) -> Result<Vec<u8>> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (chunk,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Get,
+            sign: self.sign(target, *hash)?,
+            inputs: { },
+            outputs: { chunk, },
+        );
+
+        // verify data
+        store::verify(hash, &chunk)?;
+
+        // unpack data
+        Ok(chunk)
+    }
+
+    async fn put_chunk(&self, chunk: Vec<u8>) -> Result<Hash> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let hash = Hash::with_bytes(&chunk);
+        external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Put,
+            sign: self.sign(target, hash)?,
+            inputs: {
+                chunk: chunk,
+            },
+            outputs: { },
+        );
+
+        // unpack data
+        Ok(hash)
+    }
+
+    async fn max_chunk_size(&self) -> Result<usize> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (max_chunk_size,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => GetMaxChunkSize,
+            sign: self.sign(target, 0)?,
+            inputs: { },
+            outputs: { max_chunk_size, },
+        );
+
+        // unpack data
+        Ok(max_chunk_size.try_into()?)
+    }
+
+    async fn get_blob<S>(&self, hashes: &[Hash], store: &S) -> Result<Vec<u8>>
+    where
+        S: ChunkStore + ?Sized,
+    {
+        // receive the missing chunks
+        for hash in store.missing(hashes).await? {
+            let chunk = self.get_chunk(&hash).await?;
+            store.put(hash, chunk).await?;
+        }
+
+        // assemble the chunks
+        let mut data = vec![];
+        for hash in hashes {
+            match store.get(hash).await? {
+                Some(chunk) => data.extend_from_slice(&chunk),
+                None => bail!("the chunk is dropped from the store: {hash:?}"),
+            }
+        }
+        Ok(data)
+    }
+
+    async fn put_blob(&self, data: &[u8], chunk_size: usize) -> Result<Vec<Hash>> {
+        let chunk_size = chunk_size.min(self.max_chunk_size().await?);
+        let chunks: Vec<_> = store::split(data, chunk_size).collect();
+        let hashes: Vec<_> = chunks.iter().map(|(hash, _)| *hash).collect();
+
+        // send the missing chunks
+        let missing: HashSet<_> = self.has(&hashes).await?.into_iter().collect();
+        for (hash, chunk) in chunks {
+            if missing.contains(&hash) {
+                self.put_chunk(chunk.to_vec()).await?;
+            }
+        }
+        Ok(hashes)
+    }
+}
+
+define_io! {
+    service: "ipiis_blob",
+    version: 1,
+    Put = 1 {
+        inputs: {
+            #[bulk] chunk: Vec<u8>,
+        },
+        input_sign: GuaranteeSigned<Hash>,
+        outputs: { },
+        output_sign: GuarantorSigned<Hash>,
+        generics: { },
+    },
+    Get = 2 {
+        inputs: { },
+        input_sign: GuaranteeSigned<Hash>,
+        outputs: {
+            #[bulk] chunk: Vec<u8>,
+        },
+        output_sign: GuarantorSigned<Hash>,
+        generics: { },
+    },
+    Has = 3 {
+        inputs: { },
+        input_sign: GuaranteeSigned<Vec<Hash>>,
+        outputs: {
+            missing: Vec<Hash>,
+        },
+        output_sign: GuarantorSigned<Vec<Hash>>,
+        generics: { },
+    },
+    GetMaxChunkSize = 4 {
+        inputs: { },
+        input_sign: GuaranteeSigned<u8>,
+        outputs: {
+            max_chunk_size: u64,
+        },
+        output_sign: GuarantorSigned<u8>,
+        generics: { },
+    },
+}
+
+::ipis::lazy_static::lazy_static! {
+    pub static ref KIND: Option<::ipis::core::value::hash::Hash> = Some(
+        ::ipis::core::value::hash::Hash::with_str("__ipis__ipiis__blob__"),
+    );
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        anyhow::{bail, Result},
+        value::hash::Hash,
+    },
+};
+
+/// The default size of each chunk of a blob.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20; // 1 MiB
+
+/// The default size of the largest chunk which is accepted by the server.
+pub const DEFAULT_MAX_CHUNK_SIZE: usize = 4 * DEFAULT_CHUNK_SIZE;
+
+/// The default total size of the chunks which are stored by the server.
+pub const DEFAULT_QUOTA: usize = 1 << 30; // 1 GiB
+
+/// The local storage of the chunks, which are addressed by their hashes.
+///
+/// The chunks are verified before they are stored, so the store may trust them.
+#[async_trait]
+pub trait ChunkStore: Send + Sync {
+    async fn contains(&self, hash: &Hash) -> Result<bool>;
+
+    async fn get(&self, hash: &Hash) -> Result<Option<Vec<u8>>>;
+
+    async fn put(&self, hash: Hash, chunk: Vec<u8>) -> Result<()>;
+
+    /// Filter the chunks which are not stored yet, keeping their order.
+    async fn missing(&self, hashes: &[Hash]) -> Result<Vec<Hash>> {
+        let mut missing = vec![];
+        for hash in hashes {
+            if !self.contains(hash).await? {
+                missing.push(*hash);
+            }
+        }
+        Ok(missing)
+    }
+}
+
+/// A chunk store in memory, which is dropped when the process exits.
+///
+/// The store is unlimited by default, unless it is created with a quota.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    chunks: Mutex<HashMap<Hash, Vec<u8>>>,
+    size: AtomicUsize,
+    quota: Option<usize>,
+}
+
+impl MemoryStore {
+    /// Create a store which rejects the new chunks beyond the total size.
+    pub fn with_quota(quota: usize) -> Self {
+        Self {
+            quota: Some(quota),
+            ..Default::default()
+        }
+    }
+
+    /// The total size of the stored chunks.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl ChunkStore for MemoryStore {
+    async fn contains(&self, hash: &Hash) -> Result<bool> {
+        Ok(self.chunks.lock().unwrap().contains_key(hash))
+    }
+
+    async fn get(&self, hash: &Hash) -> Result<Option<Vec<u8>>> {
+        Ok(self.chunks.lock().unwrap().get(hash).cloned())
+    }
+
+    async fn put(&self, hash: Hash, chunk: Vec<u8>) -> Result<()> {
+        let mut chunks = self.chunks.lock().unwrap();
+        if chunks.contains_key(&hash) {
+            return Ok(());
+        }
+
+        // the size is updated only while the chunks are locked
+        let size = self.size.load(Ordering::SeqCst) + chunk.len();
+        if let Some(quota) = self.quota {
+            if size > quota {
+                bail!(ServerError::new(
+                    ErrorKind::Overloaded,
+                    format!("the store is full: {size} bytes > {quota} bytes"),
+                ));
+            }
+        }
+
+        self.size.store(size, Ordering::SeqCst);
+        chunks.insert(hash, chunk);
+        Ok(())
+    }
+}
+
+/// Split the data into the chunks, along with their hashes.
+pub fn split(data: &[u8], chunk_size: usize) -> impl Iterator<Item = (Hash, &[u8])> {
+    data.chunks(chunk_size.max(1))
+        .map(|chunk| (Hash::with_bytes(chunk), chunk))
+}
+
+/// Check that the chunk is not corrupted nor tampered.
+pub fn verify(hash: &Hash, chunk: &[u8]) -> Result<()> {
+    if &Hash::with_bytes(chunk) == hash {
+        Ok(())
+    } else {
+        bail!("the chunk does not match its hash: {hash:?}")
+    }
+}
@@ -0,0 +1,60 @@
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipiis_modules_blob_common::store::{self, ChunkStore, MemoryStore};
+use ipis::tokio;
+
+#[tokio::test]
+async fn test_store() {
+    let data: Vec<u8> = (0..10u8).collect();
+    let chunks: Vec<_> = store::split(&data, 4).collect();
+    let hashes: Vec<_> = chunks.iter().map(|(hash, _)| *hash).collect();
+    assert_eq!(chunks.len(), 3);
+
+    // reject the corrupted chunks
+    assert!(store::verify(&hashes[0], chunks[0].1).is_ok());
+    assert!(store::verify(&hashes[0], chunks[1].1).is_err());
+
+    // resume from the missing chunks
+    let store = MemoryStore::default();
+    store.put(hashes[1], chunks[1].1.to_vec()).await.unwrap();
+    assert_eq!(
+        store.missing(&hashes).await.unwrap(),
+        vec![hashes[0], hashes[2]],
+    );
+
+    for (hash, chunk) in &chunks {
+        store.put(*hash, chunk.to_vec()).await.unwrap();
+    }
+    assert!(store.missing(&hashes).await.unwrap().is_empty());
+
+    // assemble the chunks
+    let mut assembled = vec![];
+    for hash in &hashes {
+        assembled.extend(store.get(hash).await.unwrap().unwrap());
+    }
+    assert_eq!(assembled, data);
+}
+
+#[tokio::test]
+async fn test_store_quota() {
+    let data: Vec<u8> = (0..10u8).collect();
+    let chunks: Vec<_> = store::split(&data, 4).collect();
+
+    // accept the chunks within the quota, counting each one only once
+    let store = MemoryStore::with_quota(8);
+    for (hash, chunk) in &chunks[..2] {
+        store.put(*hash, chunk.to_vec()).await.unwrap();
+        store.put(*hash, chunk.to_vec()).await.unwrap();
+    }
+    assert_eq!(store.size(), 8);
+
+    // reject the chunks beyond the quota
+    let (hash, chunk) = chunks[2];
+    let error = store
+        .put(hash, chunk.to_vec())
+        .await
+        .unwrap_err()
+        .downcast::<ServerError>()
+        .unwrap();
+    assert_eq!(error.kind, ErrorKind::Overloaded);
+    assert!(!store.contains(&hash).await.unwrap());
+}
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        handle_external_call,
+        payload::{PayloadBudget, PayloadLimits},
+        Ipiis, ServerResult,
+    },
+    server::IpiisServer,
+};
+use ipiis_modules_blob_common::store::{
+    self, ChunkStore, MemoryStore, DEFAULT_MAX_CHUNK_SIZE, DEFAULT_QUOTA,
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{GuaranteeSigned, Verifier},
+        anyhow::{bail, Result},
+        value::hash::Hash,
+    },
+    env::Infer,
+    tokio::io::AsyncRead,
+};
+
+/// The size of an archived chunk besides its bytes, such as its length and padding.
+const CHUNK_ARCHIVE_OVERHEAD: u64 = 64;
+
+pub struct IpiisBlobServer {
+    client: Arc<IpiisServer>,
+    store: Box<dyn ChunkStore>,
+    max_chunk_size: usize,
+}
+
+impl ::core::ops::Deref for IpiisBlobServer {
+    type Target = IpiisServer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl AsRef<IpiisClient> for IpiisBlobServer {
+    fn as_ref(&self) -> &IpiisClient {
+        (*self.client).as_ref()
+    }
+}
+
+impl AsRef<IpiisServer> for IpiisBlobServer {
+    fn as_ref(&self) -> &IpiisServer {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<'a> Infer<'a> for IpiisBlobServer {
+    type GenesisArgs = <IpiisServer as Infer<'a>>::GenesisArgs;
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self> {
+        Ok(Self::new(IpiisServer::try_infer().await?.into()))
+    }
+
+    async fn genesis(
+        args: <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        Ok(Self::new(IpiisServer::genesis(args).await?.into()))
+    }
+}
+
+handle_external_call!(
+    server: IpiisBlobServer => IpiisBlobServer,
+    request: ::ipiis_modules_blob_common::io => {
+        Get => handle_get,
+        Has => handle_has,
+        GetMaxChunkSize => handle_get_max_chunk_size,
+    },
+    request_raw: ::ipiis_modules_blob_common::io => {
+        Put => handle_put,
+    },
+);
+
+impl IpiisBlobServer {
+    pub fn new(client: Arc<IpiisServer>) -> Self {
+        Self {
+            client,
+            store: Box::new(MemoryStore::with_quota(DEFAULT_QUOTA)),
+            max_chunk_size: DEFAULT_MAX_CHUNK_SIZE,
+        }
+    }
+
+    /// Replace the local storage of the chunks, which is in memory by default.
+    ///
+    /// The default store is limited by `DEFAULT_QUOTA`.
+    pub fn set_store<S>(&mut self, store: S)
+    where
+        S: ChunkStore + 'static,
+    {
+        self.store = Box::new(store);
+    }
+
+    /// Set the largest chunk which is accepted to be stored.
+    pub fn set_max_chunk_size(&mut self, max_chunk_size: usize) {
+        self.max_chunk_size = max_chunk_size;
+    }
+
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let runtime: &IpiisServer = &self.client;
+
+        // register services
+        let mut router = runtime.router();
+        IpiisServer::register_service::<IpiisClient>(self.client.clone(), &mut router)?;
+        Self::register_service::<IpiisClient>(self.clone(), &mut router)?;
+
+        runtime.run(Arc::new(router)).await
+    }
+
+    async fn handle_put<R>(
+        client: &Self,
+        mut recv: R,
+    ) -> Result<::ipiis_modules_blob_common::io::response::Put<'static>>
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        // reject the large chunks before receiving them
+        let limits = client.payload_limits();
+        let mut budget = PayloadBudget::new(PayloadLimits {
+            max_bulk_field_size: limits
+                .max_bulk_field_size
+                .min(client.max_chunk_size as u64 + CHUNK_ARCHIVE_OVERHEAD),
+            ..limits
+        });
+
+        // recv sign
+        let sign_as_guarantee: GuaranteeSigned<Hash> =
+            budget.recv(&mut recv, false).await?.into_owned().await?;
+        sign_as_guarantee.verify(Some(client.account_me().account_ref()))?;
+
+        // unpack data
+        let hash = sign_as_guarantee.data.data;
+        let chunk: Vec<u8> = budget.recv(recv, true).await?.into_owned().await?;
+
+        // verify data
+        if chunk.len() > client.max_chunk_size {
+            bail!(ServerError::new(
+                ErrorKind::PayloadTooLarge,
+                format!(
+                    "the chunk is too large: {} > {}",
+                    chunk.len(),
+                    client.max_chunk_size,
+                ),
+            ))
+        }
+        store::verify(&hash, &chunk)?;
+
+        // handle data
+        client.store.put(hash, chunk).await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_blob_common::io::response::Put {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+
+    async fn handle_get(
+        client: &Self,
+        req: ::ipiis_modules_blob_common::io::request::Get<'static>,
+    ) -> Result<::ipiis_modules_blob_common::io::response::Get<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let hash = sign_as_guarantee.data.data;
+
+        // handle data
+        let chunk = match client.store.get(&hash).await? {
+            Some(chunk) => chunk,
+            None => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no such chunk: {hash:?}"),
+            )),
+        };
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_blob_common::io::response::Get {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            chunk: ::ipis::stream::DynStream::Owned(chunk),
+        })
+    }
+
+    async fn handle_get_max_chunk_size(
+        client: &Self,
+        req: ::ipiis_modules_blob_common::io::request::GetMaxChunkSize<'static>,
+    ) -> Result<::ipiis_modules_blob_common::io::response::GetMaxChunkSize<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_blob_common::io::response::GetMaxChunkSize {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            max_chunk_size: ::ipis::stream::DynStream::Owned(client.max_chunk_size as u64),
+        })
+    }
+
+    async fn handle_has(
+        client: &Self,
+        req: ::ipiis_modules_blob_common::io::request::Has<'static>,
+    ) -> Result<::ipiis_modules_blob_common::io::response::Has<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let hashes = &sign_as_guarantee.data.data;
+
+        // handle data
+        let missing = client.store.missing(hashes).await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_blob_common::io::response::Has {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            missing: ::ipis::stream::DynStream::Owned(missing),
+        })
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::Arc;
+
+use ipiis_modules_blob_server::IpiisBlobServer;
+use ipis::{core::anyhow::Result, env::Infer, tokio};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // init logger
+    ::ipis::logger::init_once();
+
+    Arc::new(IpiisBlobServer::infer().await).run().await
+}
@@ -0,0 +1,93 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{
+        error::{ErrorKind, ServerError},
+        external_call, Ipiis,
+    },
+    server::IpiisServer,
+};
+use ipiis_modules_blob_common::{
+    store::{self, ChunkStore, MemoryStore, DEFAULT_CHUNK_SIZE},
+    IpiisBlob, KIND,
+};
+use ipiis_modules_blob_server::IpiisBlobServer;
+use ipis::{
+    core::{account::AccountRef, anyhow::Result, value::hash::Hash},
+    env::Infer,
+    tokio,
+};
+
+/// Send the chunk under the given hash, without computing it from the chunk.
+async fn put_tampered(
+    client: &IpiisClient,
+    target: &AccountRef,
+    hash: Hash,
+    chunk: Vec<u8>,
+) -> Result<()> {
+    external_call!(
+        client: client,
+        target: KIND.as_ref() => target,
+        request: ::ipiis_modules_blob_common::io => Put,
+        sign: client.sign(*target, hash)?,
+        inputs: {
+            chunk: chunk,
+        },
+        outputs: { },
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resume_blob() -> Result<()> {
+    // init a server, accepting the small chunks only
+    let mut server = IpiisBlobServer::new(Arc::new(IpiisServer::genesis(5113).await?));
+    server.set_max_chunk_size(4);
+    let server = Arc::new(server);
+    let target = server.account_me().account_ref();
+
+    tokio::spawn(server.clone().run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    client.set_account_primary(KIND.as_ref(), &target).await?;
+    client
+        .set_address(KIND.as_ref(), &target, &"127.0.0.1:5113".parse()?)
+        .await?;
+
+    let data: Vec<u8> = (0..10u8).collect();
+    let chunks: Vec<_> = store::split(&data, 4).collect();
+    let hashes: Vec<_> = chunks.iter().map(|(hash, _)| *hash).collect();
+
+    // reject the tampered chunk
+    assert!(
+        put_tampered(&client, &target, hashes[0], chunks[1].1.to_vec())
+            .await
+            .is_err()
+    );
+    assert_eq!(client.has(&hashes).await?, hashes);
+
+    // reject the chunk which is too large
+    let error = client.put_chunk(data.clone()).await.unwrap_err();
+    assert_eq!(
+        error.downcast::<ServerError>()?.kind,
+        ErrorKind::PayloadTooLarge,
+    );
+
+    // resume the interrupted upload, split by the server
+    client.put_chunk(chunks[1].1.to_vec()).await?;
+    assert_eq!(client.has(&hashes).await?, vec![hashes[0], hashes[2]]);
+    assert_eq!(client.max_chunk_size().await?, 4);
+    assert_eq!(client.put_blob(&data, DEFAULT_CHUNK_SIZE).await?, hashes);
+    assert!(client.has(&hashes).await?.is_empty());
+
+    // resume the interrupted download
+    let local = MemoryStore::default();
+    local.put(hashes[0], chunks[0].1.to_vec()).await?;
+    assert_eq!(client.get_blob(&hashes, &local).await?, data);
+    assert!(local.missing(&hashes).await?.is_empty());
+    Ok(())
+}
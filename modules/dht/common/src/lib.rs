@@ -0,0 +1,159 @@
+pub mod node;
+pub mod routing;
+
+use core::str::FromStr;
+
+use ipiis_common::{define_io, dial::Dialer, external_call, record, Ipiis, ServerResult};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{AccountRef, GuaranteeSigned, GuarantorSigned},
+        anyhow::Result,
+        value::hash::Hash,
+    },
+};
+
+use crate::{
+    node::{DhtNode, DhtRecord, DhtRpc},
+    routing::Contact,
+};
+
+/// The DHT requests over the `Ipiis` network.
+pub struct IpiisDhtRpc<'a, IpiisClient> {
+    pub client: &'a IpiisClient,
+    /// The address of this node, which is announced to the peers.
+    pub address: Option<String>,
+}
+
+impl<'a, IpiisClient> IpiisDhtRpc<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + Send + Sync,
+    <IpiisClient as Ipiis>::Address: FromStr,
+    <<IpiisClient as Ipiis>::Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+{
+    /// Find the address record of the target in the DHT, and store its address.
+    ///
+    /// Only the address which is signed by the target itself is stored into the address book.
+    pub async fn resolve(
+        &self,
+        node: &DhtNode,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<<IpiisClient as Ipiis>::Address> {
+        let record = node.find_record(self, kind, target).await?;
+        let address = record.data.data.1.parse()?;
+
+        self.client.set_address(kind, target, &address).await?;
+        Ok(address)
+    }
+
+    /// Dial the peer at the address it reported, which is kept only in the routing table.
+    fn dial<'b>(&'b self, peer: &'b Contact) -> Dialer<'b, IpiisClient> {
+        Dialer::new(self.client, peer)
+    }
+}
+
+#[async_trait]
+impl<'a, IpiisClient> DhtRpc for IpiisDhtRpc<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + Send + Sync,
+    <IpiisClient as Ipiis>::Address: FromStr,
+    <<IpiisClient as Ipiis>::Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+{
+    async fn find_node(&self, peer: &Contact, target: &AccountRef) -> Result<Vec<Contact>> {
+        let client = &self.dial(peer);
+
+        // external call
+        let (nodes,) = external_call!(
+            client: client,
+            target: KIND.as_ref() => &peer.0,
+            request: crate::io => FindNode,
+            sign: self.client.sign(peer.0, (*target, self.address.clone()))?,
+            inputs: { },
+            outputs: { nodes, },
+        );
+
+        // unpack data
+        Ok(nodes)
+    }
+
+    async fn find_value(
+        &self,
+        peer: &Contact,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<(Option<DhtRecord>, Vec<Contact>)> {
+        let client = &self.dial(peer);
+
+        // external call
+        let (record, nodes) = external_call!(
+            client: client,
+            target: KIND.as_ref() => &peer.0,
+            request: crate::io => FindValue,
+            sign: self.client.sign(peer.0, (kind.copied(), *target, self.address.clone()))?,
+            inputs: { },
+            outputs: { record, nodes, },
+        );
+
+        // unpack data
+        Ok((record, nodes))
+    }
+
+    async fn store(&self, peer: &Contact, kind: Option<&Hash>, address: &str) -> Result<()> {
+        let client = &self.dial(peer);
+
+        // sign the record
+        let timestamp = record::now();
+        let expiry = timestamp + record::DEFAULT_RECORD_TTL.as_millis() as u64;
+        let record = (kind.copied(), address.to_string(), timestamp, expiry);
+
+        // external call
+        external_call!(
+            client: client,
+            target: KIND.as_ref() => &peer.0,
+            request: crate::io => Store,
+            sign: self.client.sign(peer.0, record)?,
+            inputs: { },
+        );
+
+        // unpack data
+        Ok(())
+    }
+}
+
+define_io! {
+    service: "ipiis_dht",
+    version: 1,
+    FindNode = 1 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(AccountRef, Option<String>)>,
+        outputs: {
+            nodes: Vec<(AccountRef, String)>,
+        },
+        output_sign: GuarantorSigned<(AccountRef, Option<String>)>,
+        generics: { },
+    },
+    FindValue = 2 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, AccountRef, Option<String>)>,
+        outputs: {
+            record: Option<GuaranteeSigned<(Option<Hash>, String, u64, u64)>>,
+            nodes: Vec<(AccountRef, String)>,
+        },
+        output_sign: GuarantorSigned<(Option<Hash>, AccountRef, Option<String>)>,
+        generics: { },
+    },
+    Store = 3 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(Option<Hash>, String, u64, u64)>,
+        outputs: { },
+        output_sign: GuarantorSigned<(Option<Hash>, String, u64, u64)>,
+        generics: { },
+    },
+}
+
+::ipis::lazy_static::lazy_static! {
+    pub static ref KIND: Option<::ipis::core::value::hash::Hash> = Some(
+        ::ipis::core::value::hash::Hash::with_str("__ipis__ipiis__dht__"),
+    );
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ipiis_common::{
+    error::{ErrorKind, ServerError},
+    record::{self, AddressRecord},
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+        value::hash::Hash,
+    },
+    futures::future,
+};
+
+use crate::routing::{self, Contact, RoutingTable, ALPHA, K};
+
+/// An address record in the DHT, whose address is kept as a string.
+pub type DhtRecord = AddressRecord<String>;
+
+/// The requests to the other nodes, which drive the lookups of a node.
+#[async_trait]
+pub trait DhtRpc {
+    /// Ask the peer for the nodes which are the closest to the target.
+    async fn find_node(&self, peer: &Contact, target: &AccountRef) -> Result<Vec<Contact>>;
+
+    /// Ask the peer for the record of the target, or the nodes which are the closest to it.
+    async fn find_value(
+        &self,
+        peer: &Contact,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<(Option<DhtRecord>, Vec<Contact>)>;
+
+    /// Store the address record of this node to the peer.
+    async fn store(&self, peer: &Contact, kind: Option<&Hash>, address: &str) -> Result<()>;
+}
+
+/// The state of a DHT node: the routing table and the stored records.
+#[derive(Debug)]
+pub struct DhtNode {
+    table: Mutex<RoutingTable>,
+    records: Mutex<HashMap<Vec<u8>, DhtRecord>>,
+}
+
+impl DhtNode {
+    pub fn new(me: AccountRef) -> Self {
+        Self {
+            table: Mutex::new(RoutingTable::new(me)),
+            records: Default::default(),
+        }
+    }
+
+    pub fn me(&self) -> AccountRef {
+        *self.table.lock().unwrap().me()
+    }
+
+    /// Add or refresh a node, returning whether it is in the routing table.
+    pub fn add_node(&self, contact: Contact) -> bool {
+        self.table.lock().unwrap().insert(contact)
+    }
+
+    pub fn remove_node(&self, account: &AccountRef) {
+        self.table.lock().unwrap().remove(account)
+    }
+
+    /// Get the known nodes which are the closest to the target.
+    pub fn closest(&self, target: &AccountRef, count: usize) -> Vec<Contact> {
+        self.table.lock().unwrap().closest(target, count)
+    }
+
+    /// Get the live record of the target which is stored in this node.
+    pub fn get_record(&self, kind: Option<&Hash>, target: &AccountRef) -> Option<DhtRecord> {
+        let key = to_key(kind, target);
+
+        let mut records = self.records.lock().unwrap();
+        match records.get(&key) {
+            Some(record) if record::verify_record(record, kind, target).is_ok() => {
+                Some(record.clone())
+            }
+            Some(_) => {
+                // forget the expired record
+                records.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Store the record, which should be verified before, unless a newer one is stored.
+    pub fn put_record(&self, record: DhtRecord) {
+        let key = to_key(record.data.data.0.as_ref(), &record.guarantee.account);
+
+        let mut records = self.records.lock().unwrap();
+        match records.get(&key) {
+            Some(current) if current.data.data.2 >= record.data.data.2 => {}
+            _ => {
+                records.insert(key, record);
+            }
+        }
+    }
+
+    /// Handle a `FindNode` request.
+    pub fn on_find_node(&self, from: Option<Contact>, target: &AccountRef) -> Vec<Contact> {
+        if let Some(from) = from {
+            self.add_node(from);
+        }
+
+        self.closest(target, K)
+    }
+
+    /// Handle a `FindValue` request.
+    pub fn on_find_value(
+        &self,
+        from: Option<Contact>,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> (Option<DhtRecord>, Vec<Contact>) {
+        if let Some(from) = from {
+            self.add_node(from);
+        }
+
+        match self.get_record(kind, target) {
+            Some(record) => (Some(record), vec![]),
+            None => (None, self.closest(target, K)),
+        }
+    }
+
+    /// Handle a `Store` request, whose record is signed by the sender itself.
+    pub fn on_store(&self, record: DhtRecord) -> Result<()> {
+        let kind = record.data.data.0;
+        let account = record.guarantee.account;
+
+        // verify the record
+        record::verify_record(&record, kind.as_ref(), &account)?;
+
+        // the sender is reachable with the record
+        self.add_node((account, record.data.data.1.clone()));
+        self.put_record(record);
+        Ok(())
+    }
+
+    /// Find the nodes which are the closest to the target, asking the known nodes iteratively.
+    pub async fn lookup<R>(&self, rpc: &R, target: &AccountRef) -> Vec<Contact>
+    where
+        R: DhtRpc + Sync,
+    {
+        self.iterate(rpc, target, None).await.1
+    }
+
+    /// Find the record of the target, asking the nodes which are closer to it iteratively.
+    pub async fn find_record<R>(
+        &self,
+        rpc: &R,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<DhtRecord>
+    where
+        R: DhtRpc + Sync,
+    {
+        // find locally
+        if let Some(record) = self.get_record(kind, target) {
+            return Ok(record);
+        }
+
+        match self.iterate(rpc, target, Some(kind)).await.0 {
+            Some(record) => {
+                // store response
+                self.put_record(record.clone());
+                Ok(record)
+            }
+            None => bail!(ServerError::new(
+                ErrorKind::Unresolvable,
+                format!("no address record of {target} in the DHT"),
+            )),
+        }
+    }
+
+    /// Store the record of this node to the closest nodes, returning the number of them.
+    pub async fn publish<R>(&self, rpc: &R, kind: Option<&Hash>, address: &str) -> Result<usize>
+    where
+        R: DhtRpc + Sync,
+    {
+        let mut stored = 0;
+        for node in self.lookup(rpc, &self.me()).await {
+            match rpc.store(&node, kind, address).await {
+                Ok(()) => stored += 1,
+                Err(_) => self.remove_node(&node.0),
+            }
+        }
+        Ok(stored)
+    }
+
+    async fn iterate<R>(
+        &self,
+        rpc: &R,
+        target: &AccountRef,
+        kind: Option<Option<&Hash>>,
+    ) -> (Option<DhtRecord>, Vec<Contact>)
+    where
+        R: DhtRpc + Sync,
+    {
+        let me = self.me();
+        let mut shortlist = self.closest(target, K);
+        let mut queried: Vec<AccountRef> = vec![];
+
+        loop {
+            // select the closest nodes which are not queried yet
+            let peers: Vec<_> = shortlist
+                .iter()
+                .filter(|(account, _)| !queried.contains(account))
+                .take(ALPHA)
+                .cloned()
+                .collect();
+            if peers.is_empty() {
+                break (None, shortlist);
+            }
+            queried.extend(peers.iter().map(|(account, _)| *account));
+
+            // ask them concurrently
+            let responses = future::join_all(peers.iter().map(|peer| async move {
+                match kind {
+                    Some(kind) => rpc.find_value(peer, kind, target).await,
+                    None => rpc.find_node(peer, target).await.map(|nodes| (None, nodes)),
+                }
+            }))
+            .await;
+
+            for (peer, response) in peers.into_iter().zip(responses) {
+                match response {
+                    Ok((record, nodes)) => {
+                        self.add_node(peer);
+
+                        // stop if the record is found
+                        if let Some(record) = record {
+                            if record::verify_record(&record, kind.flatten(), target).is_ok() {
+                                return (Some(record), shortlist);
+                            }
+                        }
+
+                        // merge the closer nodes
+                        for node in nodes {
+                            if node.0 != me && !shortlist.iter().any(|(known, _)| known == &node.0)
+                            {
+                                shortlist.push(node);
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        self.remove_node(&peer.0);
+                        shortlist.retain(|(known, _)| known != &peer.0);
+                    }
+                }
+            }
+
+            // keep the closest ones
+            shortlist.sort_by(|(a, _), (b, _)| routing::cmp_distance(target, a, b));
+            shortlist.truncate(K);
+        }
+    }
+}
+
+fn to_key(kind: Option<&Hash>, account: &AccountRef) -> Vec<u8> {
+    let kind = kind.map(|e| &***e).unwrap_or_else(|| &[]);
+
+    [kind, account.as_bytes().as_ref()].concat()
+}
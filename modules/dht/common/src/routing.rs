@@ -0,0 +1,104 @@
+use core::cmp::Ordering;
+
+use ipis::core::account::AccountRef;
+
+/// The maximum number of the nodes in a bucket, and the number of the closest nodes to find.
+pub const K: usize = 20;
+
+/// The number of the concurrent requests in a lookup.
+pub const ALPHA: usize = 3;
+
+/// An account and its address, which can be contacted directly.
+pub type Contact = (AccountRef, String);
+
+/// The XOR distance between two accounts, which is compared lexicographically.
+pub fn distance(a: &AccountRef, b: &AccountRef) -> Vec<u8> {
+    a.as_bytes()
+        .as_ref()
+        .iter()
+        .zip(b.as_bytes().as_ref())
+        .map(|(a, b)| a ^ b)
+        .collect()
+}
+
+/// Compare the distances of two accounts to the target.
+pub fn cmp_distance(target: &AccountRef, a: &AccountRef, b: &AccountRef) -> Ordering {
+    distance(target, a).cmp(&distance(target, b))
+}
+
+/// The known nodes, which are grouped by the common prefix length with this node.
+#[derive(Clone, Debug)]
+pub struct RoutingTable {
+    me: AccountRef,
+    buckets: Vec<Vec<Contact>>,
+}
+
+impl RoutingTable {
+    pub fn new(me: AccountRef) -> Self {
+        let bits = me.as_bytes().as_ref().len() * 8;
+
+        Self {
+            me,
+            buckets: vec![vec![]; bits],
+        }
+    }
+
+    pub fn me(&self) -> &AccountRef {
+        &self.me
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add or refresh a node, returning whether it is in the table.
+    ///
+    /// A full bucket keeps its nodes, as the long-lived nodes are likely to stay online.
+    pub fn insert(&mut self, contact: Contact) -> bool {
+        let bucket = match self.bucket_of(&contact.0) {
+            Some(bucket) => &mut self.buckets[bucket],
+            None => return false,
+        };
+
+        // move the node to the tail, as the most recently seen one
+        if let Some(index) = bucket.iter().position(|(account, _)| account == &contact.0) {
+            bucket.remove(index);
+            bucket.push(contact);
+            true
+        } else if bucket.len() < K {
+            bucket.push(contact);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn remove(&mut self, account: &AccountRef) {
+        if let Some(bucket) = self.bucket_of(account) {
+            self.buckets[bucket].retain(|(known, _)| known != account);
+        }
+    }
+
+    /// Get the known nodes which are the closest to the target.
+    pub fn closest(&self, target: &AccountRef, count: usize) -> Vec<Contact> {
+        let mut contacts: Vec<_> = self.buckets.iter().flatten().cloned().collect();
+        contacts.sort_by(|(a, _), (b, _)| cmp_distance(target, a, b));
+        contacts.truncate(count);
+        contacts
+    }
+
+    fn bucket_of(&self, account: &AccountRef) -> Option<usize> {
+        let distance = distance(&self.me, account);
+
+        // the index of the first different bit
+        distance
+            .iter()
+            .enumerate()
+            .find(|(_, byte)| **byte != 0)
+            .map(|(index, byte)| index * 8 + byte.leading_zeros() as usize)
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use ipiis_common::record;
+use ipiis_modules_dht_common::{
+    node::{DhtNode, DhtRecord, DhtRpc},
+    routing::{self, Contact},
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{Account, AccountRef},
+        anyhow::{anyhow, Result},
+        metadata::Metadata,
+        value::hash::Hash,
+    },
+    tokio,
+};
+
+/// Many nodes in a process, which call each other directly.
+struct Network {
+    nodes: Vec<(Account, Arc<DhtNode>, String)>,
+}
+
+impl Network {
+    fn new(size: usize) -> Self {
+        Self {
+            nodes: (0..size)
+                .map(|index| {
+                    let account = Account::generate();
+                    let node = DhtNode::new(account.account_ref());
+                    (
+                        account,
+                        Arc::new(node),
+                        format!("127.0.0.1:{}", 10000 + index),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn rpc(&self, index: usize) -> LocalRpc<'_> {
+        LocalRpc {
+            network: self,
+            index,
+        }
+    }
+
+    fn contact(&self, index: usize) -> Contact {
+        let (account, _, address) = &self.nodes[index];
+        (account.account_ref(), address.clone())
+    }
+
+    fn node_of(&self, account: &AccountRef) -> Result<&DhtNode> {
+        self.nodes
+            .iter()
+            .find(|(known, _, _)| &known.account_ref() == account)
+            .map(|(_, node, _)| &**node)
+            .ok_or_else(|| anyhow!("unreachable: {account}"))
+    }
+}
+
+struct LocalRpc<'a> {
+    network: &'a Network,
+    index: usize,
+}
+
+#[async_trait]
+impl<'a> DhtRpc for LocalRpc<'a> {
+    async fn find_node(&self, peer: &Contact, target: &AccountRef) -> Result<Vec<Contact>> {
+        let from = self.network.contact(self.index);
+        Ok(self
+            .network
+            .node_of(&peer.0)?
+            .on_find_node(Some(from), target))
+    }
+
+    async fn find_value(
+        &self,
+        peer: &Contact,
+        kind: Option<&Hash>,
+        target: &AccountRef,
+    ) -> Result<(Option<DhtRecord>, Vec<Contact>)> {
+        let from = self.network.contact(self.index);
+        Ok(self
+            .network
+            .node_of(&peer.0)?
+            .on_find_value(Some(from), kind, target))
+    }
+
+    async fn store(&self, peer: &Contact, kind: Option<&Hash>, address: &str) -> Result<()> {
+        let (account, _, _) = &self.network.nodes[self.index];
+        let timestamp = record::now();
+        let data = (
+            kind.copied(),
+            address.to_string(),
+            timestamp,
+            timestamp + 60_000,
+        );
+        let record = Metadata::builder().build(account, peer.0, data)?;
+
+        self.network.node_of(&peer.0)?.on_store(record)
+    }
+}
+
+#[tokio::test]
+async fn test_dht() {
+    let network = Network::new(32);
+
+    // join the network through the first node
+    for index in 1..network.nodes.len() {
+        let (account, node, _) = &network.nodes[index];
+        node.add_node(network.contact(0));
+        node.lookup(&network.rpc(index), &account.account_ref())
+            .await;
+    }
+
+    // publish the records
+    for (index, (_, node, address)) in network.nodes.iter().enumerate() {
+        let stored = node
+            .publish(&network.rpc(index), None, address)
+            .await
+            .unwrap();
+        assert!(stored > 0);
+    }
+
+    // find the closest node
+    let target = network.contact(5).0;
+    let closest = network.nodes[31].1.lookup(&network.rpc(31), &target).await;
+    let mut expected: Vec<_> = (0..31).map(|index| network.contact(index).0).collect();
+    expected.sort_by(|a, b| routing::cmp_distance(&target, a, b));
+    assert_eq!(closest[0].0, expected[0]);
+
+    // find the record, which is signed by the target itself
+    let record = network.nodes[31]
+        .1
+        .find_record(&network.rpc(31), None, &target)
+        .await
+        .unwrap();
+    assert_eq!(record.guarantee.account, target);
+    assert_eq!(record.data.data.1, network.contact(5).1);
+
+    // reject the record of an unknown account
+    let unknown = Account::generate().account_ref();
+    assert!(network.nodes[31]
+        .1
+        .find_record(&network.rpc(31), None, &unknown)
+        .await
+        .is_err());
+}
@@ -0,0 +1,221 @@
+use std::{sync::Arc, time::Duration};
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{handle_external_call, Ipiis, ServerResult},
+    server::IpiisServer,
+};
+use ipiis_modules_dht_common::{node::DhtNode, routing::Contact, IpiisDhtRpc};
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+    env::{infer, Infer},
+    log::{info, warn},
+    tokio,
+};
+
+/// The interval to refresh the routing table and the record of this node.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct IpiisDhtServer {
+    client: Arc<IpiisServer>,
+    node: DhtNode,
+    /// The address of this node, which is announced to the peers.
+    address: Option<String>,
+}
+
+impl ::core::ops::Deref for IpiisDhtServer {
+    type Target = IpiisServer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl AsRef<IpiisClient> for IpiisDhtServer {
+    fn as_ref(&self) -> &IpiisClient {
+        (*self.client).as_ref()
+    }
+}
+
+impl AsRef<IpiisServer> for IpiisDhtServer {
+    fn as_ref(&self) -> &IpiisServer {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<'a> Infer<'a> for IpiisDhtServer {
+    type GenesisArgs = <IpiisServer as Infer<'a>>::GenesisArgs;
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self> {
+        Ok(Self::new(IpiisServer::try_infer().await?.into()))
+    }
+
+    async fn genesis(
+        args: <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        Ok(Self::new(IpiisServer::genesis(args).await?.into()))
+    }
+}
+
+handle_external_call!(
+    server: IpiisDhtServer => IpiisDhtServer,
+    request: ::ipiis_modules_dht_common::io => {
+        FindNode => handle_find_node,
+        FindValue => handle_find_value,
+        Store => handle_store,
+    },
+);
+
+impl IpiisDhtServer {
+    pub fn new(client: Arc<IpiisServer>) -> Self {
+        Self {
+            node: DhtNode::new(client.account_me().account_ref()),
+            address: infer("ipiis_server_public_address").ok(),
+            client,
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let runtime: &IpiisServer = &self.client;
+
+        // register services
+        let mut router = runtime.router();
+        IpiisServer::register_service::<IpiisClient>(self.client.clone(), &mut router)?;
+        Self::register_service::<IpiisClient>(self.clone(), &mut router)?;
+
+        runtime.run(Arc::new(router)).await
+    }
+
+    /// Join the network through the bootstrap node,
+    /// and then refresh the routing table and the record of this node periodically.
+    pub async fn maintain(&self, bootstrap: Option<Contact>) -> Result<()> {
+        if let Some(bootstrap) = bootstrap {
+            self.node.add_node(bootstrap);
+        }
+
+        let shutdown = self.client.shutdown_handle();
+        loop {
+            let rpc = self.rpc();
+
+            // refresh the routing table
+            let nodes = self.node.lookup(&rpc, &self.node.me()).await;
+            info!("found {} nodes close to me", nodes.len());
+
+            // publish the record
+            if let Some(address) = &self.address {
+                match self.node.publish(&rpc, None, address).await {
+                    Ok(stored) => info!("published the record to {stored} nodes"),
+                    Err(e) => warn!("failed to publish the record: {e}"),
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(REFRESH_INTERVAL) => continue,
+                _ = shutdown.wait() => break Ok(()),
+            }
+        }
+    }
+
+    fn rpc(&self) -> IpiisDhtRpc<'_, IpiisClient> {
+        IpiisDhtRpc {
+            client: self.as_ref(),
+            address: self.address.clone(),
+        }
+    }
+
+    async fn handle_find_node(
+        client: &Self,
+        req: ::ipiis_modules_dht_common::io::request::FindNode<'static>,
+    ) -> Result<::ipiis_modules_dht_common::io::response::FindNode<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let target = sign_as_guarantee.data.data.0;
+        let from = sign_as_guarantee.data.data.1.clone();
+        let from = from.map(|address| (sign_as_guarantee.guarantee.account, address));
+
+        // handle data
+        let nodes = client.node.on_find_node(from, &target);
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_dht_common::io::response::FindNode {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            nodes: ::ipis::stream::DynStream::Owned(nodes),
+        })
+    }
+
+    async fn handle_find_value(
+        client: &Self,
+        req: ::ipiis_modules_dht_common::io::request::FindValue<'static>,
+    ) -> Result<::ipiis_modules_dht_common::io::response::FindValue<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let kind = sign_as_guarantee.data.data.0;
+        let target = sign_as_guarantee.data.data.1;
+        let from = sign_as_guarantee.data.data.2.clone();
+        let from = from.map(|address| (sign_as_guarantee.guarantee.account, address));
+
+        // handle data
+        let (record, nodes) = client.node.on_find_value(from, kind.as_ref(), &target);
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_dht_common::io::response::FindValue {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            record: ::ipis::stream::DynStream::Owned(record),
+            nodes: ::ipis::stream::DynStream::Owned(nodes),
+        })
+    }
+
+    async fn handle_store(
+        client: &Self,
+        req: ::ipiis_modules_dht_common::io::request::Store<'static>,
+    ) -> Result<::ipiis_modules_dht_common::io::response::Store<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // handle data
+        client.node.on_store(sign_as_guarantee.clone())?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_dht_common::io::response::Store {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // init logger
+    ::ipis::logger::init_once();
+
+    let server = Arc::new(IpiisDhtServer::infer().await);
+
+    // join the network
+    let account: Result<AccountRef> = infer("ipiis_dht_bootstrap_account");
+    let address: Result<String> = infer("ipiis_dht_bootstrap_address");
+    let bootstrap = account.and_then(|account| Ok((account, address?))).ok();
+    {
+        let server = server.clone();
+        tokio::spawn(async move { server.maintain(bootstrap).await });
+    }
+
+    server.run().await
+}
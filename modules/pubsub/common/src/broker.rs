@@ -0,0 +1,212 @@
+use core::time::Duration;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipis::{
+    core::{
+        account::AccountRef,
+        anyhow::{bail, Result},
+    },
+    tokio::sync::Notify,
+};
+
+/// The default time to wait for an acknowledgement, before the message is redelivered.
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default number of the unacknowledged messages which are kept for each subscription.
+pub const DEFAULT_MAX_PENDING: usize = 1024;
+
+/// A message along with its sequence number in the broker.
+pub type Message<T> = (u64, T);
+
+/// Keeps the messages of each subscription until they are acknowledged,
+/// so that every message is delivered at least once.
+///
+/// A subscription outlives its sessions, so that the subscriber can resume it
+/// after reconnecting, until it unsubscribes.
+///
+/// Each subscription keeps at most `max_pending` unacknowledged messages,
+/// so that a subscriber which never comes back cannot exhaust the memory of the broker.
+/// The publishers are rejected while any subscription of the topic is full.
+pub struct Broker<T> {
+    ack_timeout: Duration,
+    max_pending: usize,
+    next_id: AtomicU64,
+    topics: Mutex<HashMap<String, Topic<T>>>,
+}
+
+struct Topic<T> {
+    subscriptions: HashMap<AccountRef, Subscription<T>>,
+}
+
+impl<T> Default for Topic<T> {
+    fn default() -> Self {
+        Self {
+            subscriptions: Default::default(),
+        }
+    }
+}
+
+struct Subscription<T> {
+    /// The unacknowledged messages, with the time when each one was sent last.
+    pending: BTreeMap<u64, (T, Option<Instant>)>,
+    notify: Arc<Notify>,
+}
+
+impl<T> Default for Subscription<T> {
+    fn default() -> Self {
+        Self {
+            pending: Default::default(),
+            notify: Default::default(),
+        }
+    }
+}
+
+impl<T> Default for Broker<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_ACK_TIMEOUT, DEFAULT_MAX_PENDING)
+    }
+}
+
+impl<T> Broker<T> {
+    pub fn new(ack_timeout: Duration, max_pending: usize) -> Self {
+        Self {
+            ack_timeout,
+            max_pending,
+            next_id: Default::default(),
+            topics: Default::default(),
+        }
+    }
+
+    pub fn ack_timeout(&self) -> Duration {
+        self.ack_timeout
+    }
+
+    /// Begin or resume the subscription, redelivering the unacknowledged messages.
+    ///
+    /// The returned handle is notified whenever a new message is published.
+    pub fn subscribe(&self, topic: &str, subscriber: AccountRef) -> Arc<Notify> {
+        let mut topics = self.topics.lock().unwrap();
+        let subscription = topics
+            .entry(topic.to_string())
+            .or_default()
+            .subscriptions
+            .entry(subscriber)
+            .or_default();
+
+        // the former session may have lost them
+        for (_, sent) in subscription.pending.values_mut() {
+            *sent = None;
+        }
+        subscription.notify.clone()
+    }
+
+    /// End the subscription, dropping its unacknowledged messages.
+    ///
+    /// The topic is forgotten along with its last subscription.
+    pub fn unsubscribe(&self, topic: &str, subscriber: &AccountRef) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        let (removed, is_empty) = match topics.get_mut(topic) {
+            Some(entry) => (
+                entry.subscriptions.remove(subscriber).is_some(),
+                entry.subscriptions.is_empty(),
+            ),
+            None => return false,
+        };
+        if is_empty {
+            topics.remove(topic);
+        }
+        removed
+    }
+
+    /// Acknowledge the message, which will not be redelivered.
+    pub fn ack(&self, topic: &str, subscriber: &AccountRef, id: u64) -> bool {
+        let mut topics = self.topics.lock().unwrap();
+        topics
+            .get_mut(topic)
+            .and_then(|topic| topic.subscriptions.get_mut(subscriber))
+            .and_then(|subscription| subscription.pending.remove(&id))
+            .is_some()
+    }
+
+    /// The number of the unacknowledged messages of the subscription.
+    pub fn pending(&self, topic: &str, subscriber: &AccountRef) -> usize {
+        let topics = self.topics.lock().unwrap();
+        topics
+            .get(topic)
+            .and_then(|topic| topic.subscriptions.get(subscriber))
+            .map(|subscription| subscription.pending.len())
+            .unwrap_or_default()
+    }
+}
+
+impl<T> Broker<T>
+where
+    T: Clone,
+{
+    /// Queue the message to all subscriptions of the topic, returning its sequence number.
+    ///
+    /// The message is rejected as a whole if any subscription is full,
+    /// so that the publisher can retry it later without losing any message.
+    pub fn publish(&self, topic: &str, message: T) -> Result<u64> {
+        let mut topics = self.topics.lock().unwrap();
+
+        // the topics without subscriptions are not kept
+        let entry = match topics.get_mut(topic) {
+            Some(entry) => entry,
+            None => return Ok(self.next_id.fetch_add(1, Ordering::SeqCst)),
+        };
+
+        if entry
+            .subscriptions
+            .values()
+            .any(|subscription| subscription.pending.len() >= self.max_pending)
+        {
+            bail!(ServerError::new(
+                ErrorKind::Overloaded,
+                format!("too many unacknowledged messages in the topic: {topic}"),
+            ));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        for subscription in entry.subscriptions.values_mut() {
+            subscription.pending.insert(id, (message.clone(), None));
+            subscription.notify.notify_one();
+        }
+        Ok(id)
+    }
+
+    /// Take the messages to be delivered now, in order: the ones which have never been sent,
+    /// and the ones which are not acknowledged in time.
+    pub fn due(&self, topic: &str, subscriber: &AccountRef) -> Vec<Message<T>> {
+        let mut topics = self.topics.lock().unwrap();
+        let subscription = match topics
+            .get_mut(topic)
+            .and_then(|topic| topic.subscriptions.get_mut(subscriber))
+        {
+            Some(subscription) => subscription,
+            None => return Default::default(),
+        };
+
+        let now = Instant::now();
+        subscription
+            .pending
+            .iter_mut()
+            .filter(|(_, (_, sent))| match sent {
+                Some(sent) => now.duration_since(*sent) >= self.ack_timeout,
+                None => true,
+            })
+            .map(|(id, (message, sent))| {
+                *sent = Some(now);
+                (*id, message.clone())
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,172 @@
+pub mod broker;
+
+use ipiis_common::{define_io, duplex::Duplex, external_call, Ipiis, ServerResult};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{AccountRef, GuaranteeSigned, GuarantorSigned, Verifier},
+        anyhow::Result,
+    },
+};
+
+/// A message which is signed by its publisher.
+pub type Published = GuaranteeSigned<(String, Vec<u8>)>;
+
+/// A session of the subscription to a topic.
+///
+/// Each message should be acknowledged after it is handled, or it will be redelivered.
+pub struct Subscription<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    broker: AccountRef,
+    session: Duplex<
+        'a,
+        <IpiisClient as Ipiis>::Writer,
+        <IpiisClient as Ipiis>::Reader,
+        u64,
+        (u64, Published),
+    >,
+}
+
+impl<'a, IpiisClient> Subscription<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + ?Sized,
+{
+    pub fn broker(&self) -> &AccountRef {
+        &self.broker
+    }
+
+    /// Receive the next message with its sequence number,
+    /// or `None` if the broker has closed the session.
+    pub async fn recv(&mut self) -> Result<Option<(u64, Published)>> {
+        match self.session.recv().await? {
+            Some((id, msg)) => {
+                // verify data
+                msg.verify(Some(self.broker))?;
+                Ok(Some((id, msg)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Acknowledge the message, so that it will not be redelivered.
+    pub async fn ack(&mut self, id: u64) -> Result<()> {
+        self.session.send(id).await
+    }
+}
+
+#[async_trait]
+pub trait IpiisPubSub: Ipiis {
+    /// Publish the message to the topic, returning its sequence number.
+    async fn publish(&self, topic: &str, data: Vec<u8>) -> Result<u64>;
+
+    /// Begin or resume the subscription to the topic.
+    ///
+    /// The subscription is kept by the broker until unsubscribed,
+    /// so the unacknowledged messages are redelivered on the next session.
+    async fn subscribe(&self, topic: &str) -> Result<Subscription<'_, Self>>;
+
+    async fn unsubscribe(&self, topic: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl<IpiisClient> IpiisPubSub for IpiisClient
+where
+    IpiisClient: Ipiis + Send + Sync,
+{
+    async fn publish(&self, topic: &str, data: Vec<u8>) -> Result<u64> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (id,) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Publish,
+            sign: self.sign(target, (topic.to_string(), data))?,
+            inputs: { },
+            outputs: { id, },
+        );
+
+        // unpack data
+        Ok(id)
+    }
+
+    async fn subscribe(&self, topic: &str) -> Result<Subscription<'_, Self>> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        let (_, session) = external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Subscribe,
+            sign: self.sign(target, topic.to_string())?,
+            inputs: { },
+            outputs: open,
+        );
+
+        // unpack data
+        Ok(Subscription {
+            broker: target,
+            session: session.signed(true).require_signed(true),
+        })
+    }
+
+    async fn unsubscribe(&self, topic: &str) -> Result<()> {
+        // next target
+        let target = self.get_account_primary(KIND.as_ref()).await?;
+
+        // external call
+        external_call!(
+            client: self,
+            target: KIND.as_ref() => &target,
+            request: crate::io => Unsubscribe,
+            sign: self.sign(target, topic.to_string())?,
+            inputs: { },
+            outputs: { },
+        );
+
+        // unpack data
+        Ok(())
+    }
+}
+
+define_io! {
+    service: "ipiis_pubsub",
+    version: 1,
+    Subscribe = 1 {
+        inputs: { },
+        input_sign: GuaranteeSigned<String>,
+        outputs: { },
+        output_sign: GuarantorSigned<String>,
+        generics: { },
+        duplex: {
+            inputs: u64,
+            outputs: (u64, GuaranteeSigned<(String, Vec<u8>)>),
+        },
+    },
+    Unsubscribe = 2 {
+        inputs: { },
+        input_sign: GuaranteeSigned<String>,
+        outputs: { },
+        output_sign: GuarantorSigned<String>,
+        generics: { },
+    },
+    Publish = 3 {
+        inputs: { },
+        input_sign: GuaranteeSigned<(String, Vec<u8>)>,
+        outputs: {
+            id: u64,
+        },
+        output_sign: GuarantorSigned<(String, Vec<u8>)>,
+        generics: { },
+    },
+}
+
+::ipis::lazy_static::lazy_static! {
+    pub static ref KIND: Option<::ipis::core::value::hash::Hash> = Some(
+        ::ipis::core::value::hash::Hash::with_str("__ipis__ipiis__pubsub__"),
+    );
+}
@@ -0,0 +1,60 @@
+use core::time::Duration;
+
+use ipiis_common::error::{ErrorKind, ServerError};
+use ipiis_modules_pubsub_common::broker::Broker;
+use ipis::{
+    core::{account::Account, anyhow::Result},
+    tokio,
+};
+
+#[tokio::test]
+async fn test_broker() -> Result<()> {
+    let subscriber = Account::generate().account_ref();
+    let broker = Broker::new(Duration::from_millis(100), 2);
+
+    // drop the messages without subscriptions
+    broker.publish("topic", "before")?;
+    assert!(broker.due("topic", &subscriber).is_empty());
+
+    // deliver each message only once until the timeout
+    let notify = broker.subscribe("topic", subscriber);
+    let id = broker.publish("topic", "hello")?;
+    notify.notified().await;
+    assert_eq!(broker.due("topic", &subscriber), vec![(id, "hello")]);
+    assert!(broker.due("topic", &subscriber).is_empty());
+
+    // redeliver the message which is not acknowledged in time
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(broker.due("topic", &subscriber), vec![(id, "hello")]);
+
+    // redeliver the unacknowledged messages on the next session
+    let next = broker.publish("topic", "world")?;
+    broker.subscribe("topic", subscriber);
+    assert_eq!(
+        broker.due("topic", &subscriber),
+        vec![(id, "hello"), (next, "world")],
+    );
+
+    // forget the acknowledged messages
+    assert!(broker.ack("topic", &subscriber, id));
+    assert!(!broker.ack("topic", &subscriber, id));
+    assert_eq!(broker.pending("topic", &subscriber), 1);
+
+    // reject the publishers while the subscription is full
+    let first = broker.publish("topic", "first")?;
+    let error = broker.publish("topic", "second").unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<ServerError>().map(|error| error.kind),
+        Some(ErrorKind::Overloaded),
+    );
+    assert_eq!(broker.pending("topic", &subscriber), 2);
+    assert!(broker.ack("topic", &subscriber, next));
+    assert!(broker.ack("topic", &subscriber, first));
+    broker.publish("topic", "second")?;
+
+    // drop the messages of the closed subscription
+    assert!(broker.unsubscribe("topic", &subscriber));
+    broker.publish("topic", "bye")?;
+    assert_eq!(broker.pending("topic", &subscriber), 0);
+    Ok(())
+}
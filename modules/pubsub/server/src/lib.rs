@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{duplex::Pending, handle_external_call, Ipiis, ServerResult},
+    server::IpiisServer,
+};
+use ipiis_modules_pubsub_common::{broker::Broker, Published};
+use ipis::{async_trait::async_trait, core::anyhow::Result, env::Infer, futures::StreamExt, tokio};
+
+pub struct IpiisPubSubServer {
+    client: Arc<IpiisServer>,
+    broker: Broker<Published>,
+}
+
+impl ::core::ops::Deref for IpiisPubSubServer {
+    type Target = IpiisServer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl AsRef<IpiisClient> for IpiisPubSubServer {
+    fn as_ref(&self) -> &IpiisClient {
+        (*self.client).as_ref()
+    }
+}
+
+impl AsRef<IpiisServer> for IpiisPubSubServer {
+    fn as_ref(&self) -> &IpiisServer {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<'a> Infer<'a> for IpiisPubSubServer {
+    type GenesisArgs = <IpiisServer as Infer<'a>>::GenesisArgs;
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self> {
+        Ok(Self::new(IpiisServer::try_infer().await?.into()))
+    }
+
+    async fn genesis(
+        args: <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        Ok(Self::new(IpiisServer::genesis(args).await?.into()))
+    }
+}
+
+handle_external_call!(
+    server: IpiisPubSubServer => IpiisPubSubServer,
+    request: ::ipiis_modules_pubsub_common::io => {
+        Unsubscribe => handle_unsubscribe,
+        Publish => handle_publish,
+    },
+    request_duplex: ::ipiis_modules_pubsub_common::io => {
+        Subscribe => handle_subscribe,
+    },
+);
+
+impl IpiisPubSubServer {
+    pub fn new(client: Arc<IpiisServer>) -> Self {
+        Self {
+            client,
+            broker: Default::default(),
+        }
+    }
+
+    pub fn broker(&self) -> &Broker<Published> {
+        &self.broker
+    }
+
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let runtime: &IpiisServer = &self.client;
+
+        // register services
+        let mut router = runtime.router();
+        IpiisServer::register_service::<IpiisClient>(self.client.clone(), &mut router)?;
+        Self::register_service::<IpiisClient>(self.clone(), &mut router)?;
+
+        runtime.run(Arc::new(router)).await
+    }
+
+    async fn handle_subscribe<__IpiisClient>(
+        client: &Self,
+        req: ::ipiis_modules_pubsub_common::io::request::Subscribe<'static>,
+        pending: Pending<'_, __IpiisClient>,
+    ) -> Result<()>
+    where
+        __IpiisClient: Ipiis,
+    {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let topic = sign_as_guarantee.data.data.clone();
+        let subscriber = sign_as_guarantee.guarantee.account;
+
+        // resume the subscription, so that the messages published from now on are kept
+        let notify = client.broker.subscribe(&topic, subscriber);
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        let mut res = ::ipiis_modules_pubsub_common::io::response::Subscribe {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        };
+
+        // begin a session
+        let (mut sender, receiver) = res
+            .accept(client, pending)
+            .await?
+            .signed(true)
+            .require_signed(true)
+            .split();
+        let mut acks = Box::pin(receiver.into_stream());
+
+        // deliver the messages until the subscriber leaves
+        let shutdown = client.client.shutdown_handle();
+        loop {
+            for msg in client.broker.due(&topic, &subscriber) {
+                sender.send(msg).await?;
+            }
+
+            tokio::select! {
+                ack = acks.next() => match ack {
+                    Some(id) => {
+                        client.broker.ack(&topic, &subscriber, id?);
+                    }
+                    None => break Ok(()),
+                },
+                _ = notify.notified() => continue,
+                // redeliver the messages which are not acknowledged in time
+                _ = tokio::time::sleep(client.broker.ack_timeout()) => continue,
+                _ = shutdown.wait() => break Ok(()),
+            }
+        }
+    }
+
+    async fn handle_unsubscribe(
+        client: &Self,
+        req: ::ipiis_modules_pubsub_common::io::request::Unsubscribe<'static>,
+    ) -> Result<::ipiis_modules_pubsub_common::io::response::Unsubscribe<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let topic = &sign_as_guarantee.data.data;
+        let subscriber = &sign_as_guarantee.guarantee.account;
+
+        // handle data
+        client.broker.unsubscribe(topic, subscriber);
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_pubsub_common::io::response::Unsubscribe {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+        })
+    }
+
+    async fn handle_publish(
+        client: &Self,
+        req: ::ipiis_modules_pubsub_common::io::request::Publish<'static>,
+    ) -> Result<::ipiis_modules_pubsub_common::io::response::Publish<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let topic = sign_as_guarantee.data.data.0.clone();
+
+        // handle data
+        let id = client.broker.publish(&topic, sign_as_guarantee.clone())?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_pubsub_common::io::response::Publish {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            id: ::ipis::stream::DynStream::Owned(id),
+        })
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::Arc;
+
+use ipiis_modules_pubsub_server::IpiisPubSubServer;
+use ipis::{core::anyhow::Result, env::Infer, tokio};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // init logger
+    ::ipis::logger::init_once();
+
+    Arc::new(IpiisPubSubServer::infer().await).run().await
+}
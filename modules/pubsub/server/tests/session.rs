@@ -0,0 +1,46 @@
+use core::time::Duration;
+use std::sync::Arc;
+
+use ipiis_api::{client::IpiisClient, common::Ipiis, server::IpiisServer};
+use ipiis_modules_pubsub_common::{IpiisPubSub, KIND};
+use ipiis_modules_pubsub_server::IpiisPubSubServer;
+use ipis::{core::anyhow::Result, env::Infer, tokio};
+
+#[tokio::test]
+async fn test_redeliver_on_next_session() -> Result<()> {
+    // init a broker
+    let server = Arc::new(IpiisPubSubServer::new(Arc::new(
+        IpiisServer::genesis(5112).await?,
+    )));
+    let broker = server.account_me().account_ref();
+
+    tokio::spawn(server.clone().run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // init a client
+    let client = IpiisClient::genesis(None).await?;
+    let subscriber = client.account_me().account_ref();
+    client.set_account_primary(KIND.as_ref(), &broker).await?;
+    client
+        .set_address(KIND.as_ref(), &broker, &"127.0.0.1:5112".parse()?)
+        .await?;
+
+    // receive a message, leaving the session without acknowledging it
+    let mut subscription = client.subscribe("topic").await?;
+    let id = client.publish("topic", b"hello".to_vec()).await?;
+    let (received, msg) = subscription.recv().await?.unwrap();
+    assert_eq!(received, id);
+    assert_eq!(msg.data.data.1, b"hello");
+    drop(subscription);
+
+    // redeliver it on the next session
+    let mut subscription = client.subscribe("topic").await?;
+    let (received, _) = subscription.recv().await?.unwrap();
+    assert_eq!(received, id);
+
+    // forget the acknowledged message
+    subscription.ack(id).await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.broker().pending("topic", &subscriber), 0);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+pub mod membership;
+
+use core::str::FromStr;
+
+use ipiis_common::{define_io, dial::Dialer, external_call, Ipiis, ServerResult};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{AccountRef, GuaranteeSigned, GuarantorSigned},
+        anyhow::Result,
+    },
+};
+
+use crate::membership::{Contact, SignedUpdate, SwimRpc};
+
+/// The SWIM requests over the `Ipiis` network.
+pub struct IpiisSwimRpc<'a, IpiisClient> {
+    pub client: &'a IpiisClient,
+}
+
+impl<'a, IpiisClient> IpiisSwimRpc<'a, IpiisClient> {
+    /// Dial the member at the address it announced, which is kept only in the membership list.
+    fn dial<'b>(&'b self, peer: &'b Contact) -> Dialer<'b, IpiisClient> {
+        Dialer::new(self.client, peer)
+    }
+}
+
+#[async_trait]
+impl<'a, IpiisClient> SwimRpc for IpiisSwimRpc<'a, IpiisClient>
+where
+    IpiisClient: Ipiis + Send + Sync,
+    <IpiisClient as Ipiis>::Address: FromStr,
+    <<IpiisClient as Ipiis>::Address as FromStr>::Err: ::std::error::Error + Send + Sync + 'static,
+{
+    async fn ping(&self, peer: &Contact, updates: Vec<SignedUpdate>) -> Result<Vec<SignedUpdate>> {
+        let client = &self.dial(peer);
+
+        // external call
+        let (updates,) = external_call!(
+            client: client,
+            target: KIND.as_ref() => &peer.0,
+            request: crate::io => SignedPing,
+            sign: self.client.sign(peer.0, updates.len() as u64)?,
+            inputs: {
+                updates: updates,
+            },
+            outputs: { updates, },
+        );
+
+        // unpack data
+        Ok(updates)
+    }
+
+    async fn ping_req(
+        &self,
+        peer: &Contact,
+        target: &Contact,
+        updates: Vec<SignedUpdate>,
+    ) -> Result<(bool, Vec<SignedUpdate>)> {
+        let client = &self.dial(peer);
+
+        // external call
+        let (acked, updates) = external_call!(
+            client: client,
+            target: KIND.as_ref() => &peer.0,
+            request: crate::io => SignedPingReq,
+            sign: self.client.sign(peer.0, target.clone())?,
+            inputs: {
+                updates: updates,
+            },
+            outputs: { acked, updates, },
+        );
+
+        // unpack data
+        Ok((acked, updates))
+    }
+}
+
+define_io! {
+    service: "ipiis_swim",
+    version: 1,
+    SignedPing = 1 {
+        inputs: {
+            updates: Vec<GuaranteeSigned<(AccountRef, String, u8, u64)>>,
+        },
+        input_sign: GuaranteeSigned<u64>,
+        outputs: {
+            updates: Vec<GuaranteeSigned<(AccountRef, String, u8, u64)>>,
+        },
+        output_sign: GuarantorSigned<u64>,
+        generics: { },
+    },
+    SignedPingReq = 2 {
+        inputs: {
+            updates: Vec<GuaranteeSigned<(AccountRef, String, u8, u64)>>,
+        },
+        input_sign: GuaranteeSigned<(AccountRef, String)>,
+        outputs: {
+            acked: bool,
+            updates: Vec<GuaranteeSigned<(AccountRef, String, u8, u64)>>,
+        },
+        output_sign: GuarantorSigned<(AccountRef, String)>,
+        generics: { },
+    },
+}
+
+::ipis::lazy_static::lazy_static! {
+    pub static ref KIND: Option<::ipis::core::value::hash::Hash> = Some(
+        ::ipis::core::value::hash::Hash::with_str("__ipis__ipiis__swim__"),
+    );
+}
@@ -0,0 +1,536 @@
+use core::time::Duration;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{Account, AccountRef, GuaranteeSigned, Verifier},
+        anyhow::{bail, Error, Result},
+        metadata::Metadata,
+    },
+    futures::future,
+    tokio::{self, sync::broadcast},
+};
+use rand::seq::SliceRandom;
+
+/// The interval to probe a member.
+pub const PROTOCOL_PERIOD: Duration = Duration::from_secs(1);
+
+/// The time to wait for the acknowledgement of a direct probe.
+pub const PING_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// The number of the members which probe a target on behalf of this node.
+pub const INDIRECT_PROBES: usize = 3;
+
+/// The number of the protocol periods before a suspected member is declared dead.
+pub const SUSPICION_PERIODS: u64 = 5;
+
+/// The maximum number of the updates which are piggybacked on a message.
+pub const MAX_PIGGYBACK: usize = 16;
+
+/// The multiplier of the number of the transmissions of an update,
+/// which grows logarithmically with the number of the members.
+pub const RETRANSMIT_MULT: u32 = 3;
+
+/// The number of the events which are kept for the slow subscribers.
+const EVENT_CAPACITY: usize = 1024;
+
+/// An account and its address, which can be contacted directly.
+pub type Contact = (AccountRef, String);
+
+/// The state of a member which is disseminated to the others:
+/// the account, the address, the state and the incarnation number.
+pub type Update = (AccountRef, String, u8, u64);
+
+/// An update which is signed by the member making it.
+///
+/// Any member may suspect the others, but only the member itself may claim to be alive,
+/// so that a suspicion cannot be refuted on behalf of a failed member.
+/// A suspicion is accepted only up to the next incarnation of the known one,
+/// so that the member can always refute it.
+pub type SignedUpdate = GuaranteeSigned<Update>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+}
+
+impl From<MemberState> for u8 {
+    fn from(state: MemberState) -> Self {
+        match state {
+            MemberState::Alive => 0,
+            MemberState::Suspect => 1,
+            MemberState::Dead => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for MemberState {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Alive),
+            1 => Ok(Self::Suspect),
+            2 => Ok(Self::Dead),
+            _ => bail!("unknown member state: {value}"),
+        }
+    }
+}
+
+/// A change of the membership, which is observed by this node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MembershipEvent {
+    /// A new member, or a dead one which came back.
+    Joined(Contact),
+    Suspected(AccountRef),
+    /// A suspected member which refuted the suspicion.
+    Recovered(AccountRef),
+    Died(AccountRef),
+}
+
+/// The requests to the other members, which drive the failure detection of a node.
+#[async_trait]
+pub trait SwimRpc {
+    /// Probe the peer directly, exchanging the piggybacked updates.
+    async fn ping(&self, peer: &Contact, updates: Vec<SignedUpdate>) -> Result<Vec<SignedUpdate>>;
+
+    /// Ask the peer to probe the target, returning whether the target acknowledged it.
+    async fn ping_req(
+        &self,
+        peer: &Contact,
+        target: &Contact,
+        updates: Vec<SignedUpdate>,
+    ) -> Result<(bool, Vec<SignedUpdate>)>;
+}
+
+#[derive(Clone, Debug)]
+struct Member {
+    address: String,
+    state: MemberState,
+    incarnation: u64,
+    /// The protocol period when the member is suspected.
+    suspected_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    incarnation: u64,
+    period: u64,
+    members: HashMap<AccountRef, Member>,
+    /// The latest update of each member to disseminate, with the remaining transmissions.
+    updates: HashMap<AccountRef, (SignedUpdate, u32)>,
+    /// The members to probe in this round, in random order.
+    probes: Vec<AccountRef>,
+}
+
+impl State {
+    fn apply(&mut self, me: &AccountRef, update: SignedUpdate) -> Option<MembershipEvent> {
+        let state = MemberState::try_from(update.data.data.2).ok()?;
+        let (account, address, _, incarnation) = update.data.data.clone();
+
+        // verify the update, whose alive state is claimed only by the member itself
+        update.verify(None).ok()?;
+        if state == MemberState::Alive && update.guarantee.account != account {
+            return None;
+        }
+
+        // bound the incarnation of the suspicions, so that a forged one cannot outlive
+        // all the refutations of the member
+        let known = if &account == me {
+            self.incarnation
+        } else {
+            self.members
+                .get(&account)
+                .map(|member| member.incarnation)
+                .unwrap_or_default()
+        };
+        if state != MemberState::Alive && incarnation > known.saturating_add(1) {
+            return None;
+        }
+
+        // refute the suspicion of this node, which is announced on every message
+        if &account == me {
+            if state != MemberState::Alive && incarnation >= self.incarnation {
+                self.incarnation = incarnation.saturating_add(1);
+            }
+            return None;
+        }
+
+        let period = self.period;
+        let event = match self.members.get_mut(&account) {
+            Some(member) => {
+                if !overrides((member.state, member.incarnation), (state, incarnation)) {
+                    return None;
+                }
+
+                let event = match (member.state, state) {
+                    (MemberState::Dead, MemberState::Alive) => {
+                        Some(MembershipEvent::Joined((account, address.clone())))
+                    }
+                    (MemberState::Suspect, MemberState::Alive) => {
+                        Some(MembershipEvent::Recovered(account))
+                    }
+                    (MemberState::Alive, MemberState::Suspect) => {
+                        Some(MembershipEvent::Suspected(account))
+                    }
+                    (_, MemberState::Dead) => Some(MembershipEvent::Died(account)),
+                    _ => None,
+                };
+
+                if state == MemberState::Suspect && member.state != MemberState::Suspect {
+                    member.suspected_at = period;
+                }
+                if !address.is_empty() {
+                    member.address = address;
+                }
+                member.state = state;
+                member.incarnation = incarnation;
+                event
+            }
+            None => {
+                self.members.insert(
+                    account,
+                    Member {
+                        address: address.clone(),
+                        state,
+                        incarnation,
+                        suspected_at: period,
+                    },
+                );
+
+                // the dead ones are kept only to ignore their stale updates
+                match state {
+                    MemberState::Dead => None,
+                    _ => Some(MembershipEvent::Joined((account, address))),
+                }
+            }
+        };
+
+        // disseminate the accepted update
+        let retransmits = self.retransmits();
+        self.updates.insert(account, (update, retransmits));
+        event
+    }
+
+    fn retransmits(&self) -> u32 {
+        let members = self.members.len() as u32 + 1;
+        let log2 = u32::BITS - members.leading_zeros();
+
+        RETRANSMIT_MULT * log2
+    }
+
+    fn piggyback(&mut self) -> Vec<SignedUpdate> {
+        // prefer the updates which are transmitted less
+        let mut updates: Vec<_> = self.updates.values_mut().collect();
+        updates.sort_by_key(|(_, remaining)| ::core::cmp::Reverse(*remaining));
+
+        let picked = updates
+            .into_iter()
+            .take(MAX_PIGGYBACK)
+            .map(|(update, remaining)| {
+                *remaining -= 1;
+                update.clone()
+            })
+            .collect();
+
+        self.updates.retain(|_, (_, remaining)| *remaining > 0);
+        picked
+    }
+}
+
+/// Whether the update is newer than the current state of a member.
+fn overrides(current: (MemberState, u64), update: (MemberState, u64)) -> bool {
+    match (current.0, update.0) {
+        (MemberState::Dead, MemberState::Alive) => update.1 > current.1,
+        (MemberState::Dead, _) => false,
+        (MemberState::Alive, MemberState::Suspect)
+        | (MemberState::Alive, MemberState::Dead)
+        | (MemberState::Suspect, MemberState::Dead) => update.1 >= current.1,
+        _ => update.1 > current.1,
+    }
+}
+
+/// The membership list of a node, which is maintained with the SWIM protocol.
+#[derive(Debug)]
+pub struct Membership {
+    account: Arc<Account>,
+    me: Contact,
+    state: Mutex<State>,
+    events: broadcast::Sender<MembershipEvent>,
+}
+
+impl Membership {
+    /// Create the membership list of the account, which is announced with the address.
+    pub fn new(account: Arc<Account>, address: String) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Self {
+            me: (account.account_ref(), address),
+            account,
+            state: Default::default(),
+            events,
+        }
+    }
+
+    pub fn me(&self) -> &Contact {
+        &self.me
+    }
+
+    pub fn incarnation(&self) -> u64 {
+        self.state.lock().unwrap().incarnation
+    }
+
+    /// Subscribe the membership changes which are observed after now.
+    pub fn subscribe(&self) -> broadcast::Receiver<MembershipEvent> {
+        self.events.subscribe()
+    }
+
+    /// Get the members which are not dead, including the suspected ones.
+    pub fn members(&self) -> Vec<Contact> {
+        self.state
+            .lock()
+            .unwrap()
+            .members
+            .iter()
+            .filter(|(_, member)| member.state != MemberState::Dead)
+            .map(|(account, member)| (*account, member.address.clone()))
+            .collect()
+    }
+
+    pub fn state_of(&self, account: &AccountRef) -> Option<MemberState> {
+        let state = self.state.lock().unwrap();
+        state.members.get(account).map(|member| member.state)
+    }
+
+    /// Add a member which is believed to be alive, e.g. a bootstrap node.
+    ///
+    /// It is not disseminated, as the member announces itself on the first probe.
+    pub fn join(&self, contact: Contact) {
+        let (account, address) = contact;
+        if account == self.me.0 {
+            return;
+        }
+
+        let joined = {
+            let mut state = self.state.lock().unwrap();
+            let period = state.period;
+            match state.members.entry(account) {
+                Entry::Occupied(_) => false,
+                Entry::Vacant(entry) => {
+                    entry.insert(Member {
+                        address: address.clone(),
+                        state: MemberState::Alive,
+                        incarnation: 0,
+                        suspected_at: period,
+                    });
+                    true
+                }
+            }
+        };
+
+        if joined {
+            // nobody may listen to the events
+            let _ = self
+                .events
+                .send(MembershipEvent::Joined((account, address)));
+        }
+    }
+
+    /// Apply the disseminated updates which are newer than the known states.
+    ///
+    /// The updates which are not signed properly are ignored.
+    pub fn apply(&self, updates: Vec<SignedUpdate>) {
+        let events: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            updates
+                .into_iter()
+                .filter_map(|update| state.apply(&self.me.0, update))
+                .collect()
+        };
+
+        for event in events {
+            // nobody may listen to the events
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// Take the updates to piggyback on a message, led by the state of this node.
+    pub fn piggyback(&self) -> Result<Vec<SignedUpdate>> {
+        let mut state = self.state.lock().unwrap();
+        let me = self.sign((
+            self.me.0,
+            self.me.1.clone(),
+            MemberState::Alive.into(),
+            state.incarnation,
+        ))?;
+
+        Ok(::core::iter::once(me).chain(state.piggyback()).collect())
+    }
+
+    /// Handle a `Ping` request.
+    pub fn on_ping(&self, updates: Vec<SignedUpdate>) -> Result<Vec<SignedUpdate>> {
+        self.apply(updates);
+        self.piggyback()
+    }
+
+    /// Handle a `PingReq` request, probing the target on behalf of the sender.
+    pub async fn on_ping_req<R>(
+        &self,
+        rpc: &R,
+        target: &Contact,
+        updates: Vec<SignedUpdate>,
+    ) -> Result<(bool, Vec<SignedUpdate>)>
+    where
+        R: SwimRpc + Sync,
+    {
+        self.apply(updates);
+
+        let acked = self.ping(rpc, target).await;
+        Ok((acked, self.piggyback()?))
+    }
+
+    /// Run a protocol period: declare the expired suspects dead, and then probe a member.
+    pub async fn tick<R>(&self, rpc: &R) -> Result<()>
+    where
+        R: SwimRpc + Sync,
+    {
+        // declare the expired suspects dead
+        let expired: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            state.period += 1;
+
+            let period = state.period;
+            state
+                .members
+                .iter()
+                .filter(|(_, member)| member.state == MemberState::Suspect)
+                .filter(|(_, member)| member.suspected_at + SUSPICION_PERIODS <= period)
+                .map(|(account, member)| {
+                    let state = MemberState::Dead.into();
+                    (*account, member.address.clone(), state, member.incarnation)
+                })
+                .collect()
+        };
+        let expired = expired
+            .into_iter()
+            .map(|update| self.sign(update))
+            .collect::<Result<_>>()?;
+        self.apply(expired);
+
+        // select the target
+        let target = match self.next_probe() {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+
+        // probe directly
+        if self.ping(rpc, &target).await {
+            return Ok(());
+        }
+
+        // probe indirectly
+        let target = &target;
+        let helpers = self.helpers(&target.0);
+        let acks = future::join_all(helpers.into_iter().map(|helper| async move {
+            let request = async { rpc.ping_req(&helper, target, self.piggyback()?).await };
+            match tokio::time::timeout(PING_TIMEOUT * 2, request).await {
+                Ok(Ok((acked, updates))) => {
+                    self.apply(updates);
+                    acked
+                }
+                _ => false,
+            }
+        }))
+        .await;
+        if acks.contains(&true) {
+            return Ok(());
+        }
+
+        // suspect the target
+        let incarnation = {
+            let state = self.state.lock().unwrap();
+            state
+                .members
+                .get(&target.0)
+                .map(|member| member.incarnation)
+        };
+        if let Some(incarnation) = incarnation {
+            let state = MemberState::Suspect.into();
+            let update = self.sign((target.0, target.1.clone(), state, incarnation))?;
+            self.apply(vec![update]);
+        }
+        Ok(())
+    }
+
+    fn sign(&self, update: Update) -> Result<SignedUpdate> {
+        Metadata::builder().build(&self.account, self.me.0, update)
+    }
+
+    async fn ping<R>(&self, rpc: &R, target: &Contact) -> bool
+    where
+        R: SwimRpc + Sync,
+    {
+        let request = async { rpc.ping(target, self.piggyback()?).await };
+        match tokio::time::timeout(PING_TIMEOUT, request).await {
+            Ok(Ok(updates)) => {
+                self.apply(updates);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Select the next member to probe, visiting all of them in random order.
+    fn next_probe(&self) -> Option<Contact> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match state.probes.pop() {
+                Some(account) => match state.members.get(&account) {
+                    Some(member) if member.state != MemberState::Dead => {
+                        break Some((account, member.address.clone()));
+                    }
+                    _ => continue,
+                },
+                None => {
+                    // start a new round
+                    let mut probes: Vec<_> = state
+                        .members
+                        .iter()
+                        .filter(|(_, member)| member.state != MemberState::Dead)
+                        .map(|(account, _)| *account)
+                        .collect();
+                    if probes.is_empty() {
+                        break None;
+                    }
+
+                    probes.shuffle(&mut ::rand::thread_rng());
+                    state.probes = probes;
+                }
+            }
+        }
+    }
+
+    /// Select the alive members which probe the target on behalf of this node.
+    fn helpers(&self, target: &AccountRef) -> Vec<Contact> {
+        let mut helpers: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .members
+            .iter()
+            .filter(|(account, _)| *account != target)
+            .filter(|(_, member)| member.state == MemberState::Alive)
+            .map(|(account, member)| (*account, member.address.clone()))
+            .collect();
+
+        helpers.shuffle(&mut ::rand::thread_rng());
+        helpers.truncate(INDIRECT_PROBES);
+        helpers
+    }
+}
@@ -0,0 +1,227 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use ipiis_modules_swim_common::membership::{
+    Contact, MemberState, Membership, MembershipEvent, SignedUpdate, SwimRpc,
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{
+        account::{Account, AccountRef},
+        anyhow::{anyhow, Result},
+        metadata::Metadata,
+    },
+    futures::future,
+    tokio,
+};
+
+/// Many members in a process, which call each other directly.
+struct Network {
+    nodes: Vec<(Arc<Membership>, AtomicBool)>,
+}
+
+impl Network {
+    fn new(size: usize) -> Self {
+        Self {
+            nodes: (0..size)
+                .map(|index| {
+                    let account = Arc::new(Account::generate());
+                    let address = format!("127.0.0.1:{}", 10000 + index);
+                    (Arc::new(Membership::new(account, address)), true.into())
+                })
+                .collect(),
+        }
+    }
+
+    fn node_of(&self, account: &AccountRef) -> Result<&Membership> {
+        self.nodes
+            .iter()
+            .filter(|(_, alive)| alive.load(Ordering::SeqCst))
+            .find(|(node, _)| &node.me().0 == account)
+            .map(|(node, _)| &**node)
+            .ok_or_else(|| anyhow!("unreachable: {account}"))
+    }
+
+    /// Run a protocol period on all of the alive members.
+    async fn tick(&self) {
+        future::join_all(
+            self.nodes
+                .iter()
+                .filter(|(_, alive)| alive.load(Ordering::SeqCst))
+                .map(|(node, _)| async move { node.tick(self).await.unwrap() }),
+        )
+        .await;
+    }
+}
+
+#[async_trait]
+impl SwimRpc for Network {
+    async fn ping(&self, peer: &Contact, updates: Vec<SignedUpdate>) -> Result<Vec<SignedUpdate>> {
+        self.node_of(&peer.0)?.on_ping(updates)
+    }
+
+    async fn ping_req(
+        &self,
+        peer: &Contact,
+        target: &Contact,
+        updates: Vec<SignedUpdate>,
+    ) -> Result<(bool, Vec<SignedUpdate>)> {
+        let node = self.node_of(&peer.0)?;
+        node.on_ping_req(self, target, updates).await
+    }
+}
+
+#[tokio::test]
+async fn test_swim() {
+    let network = Network::new(8);
+    let size = network.nodes.len();
+
+    // join the members through the first node
+    for (node, _) in &network.nodes[1..] {
+        node.join(network.nodes[0].0.me().clone());
+    }
+
+    // disseminate the members
+    for _ in 0..50 {
+        network.tick().await;
+        if network
+            .nodes
+            .iter()
+            .all(|(node, _)| node.members().len() == size - 1)
+        {
+            break;
+        }
+    }
+    for (node, _) in &network.nodes {
+        assert_eq!(node.members().len(), size - 1);
+    }
+
+    // detect a failed member
+    let mut events = network.nodes[1].0.subscribe();
+    let failed = network.nodes[7].0.me().0;
+    network.nodes[7].1.store(false, Ordering::SeqCst);
+    for _ in 0..50 {
+        network.tick().await;
+        if network.nodes[1].0.state_of(&failed) == Some(MemberState::Dead) {
+            break;
+        }
+    }
+    assert_eq!(
+        network.nodes[1].0.state_of(&failed),
+        Some(MemberState::Dead)
+    );
+
+    let mut died = false;
+    while let Ok(event) = events.try_recv() {
+        died |= event == MembershipEvent::Died(failed);
+    }
+    assert!(died);
+}
+
+#[tokio::test]
+async fn test_refute_suspicion() {
+    let node = Membership::new(Arc::new(Account::generate()), "127.0.0.1:10000".into());
+    assert_eq!(node.incarnation(), 0);
+
+    // refute the suspicion by incrementing the incarnation
+    let other = Account::generate();
+    let (me, address) = node.me().clone();
+    node.apply(vec![sign(
+        &other,
+        (me, address.clone(), MemberState::Suspect.into(), 0),
+    )]);
+    assert_eq!(node.incarnation(), 1);
+
+    // announce the new incarnation
+    let updates = node.piggyback().unwrap();
+    assert_eq!(updates[0].data.data.0, me);
+    assert_eq!(updates[0].data.data.2, u8::from(MemberState::Alive));
+    assert_eq!(updates[0].data.data.3, 1);
+    assert_eq!(updates[0].guarantee.account, me);
+}
+
+#[tokio::test]
+async fn test_reject_forged_refutation() {
+    let node = Membership::new(Arc::new(Account::generate()), "127.0.0.1:10000".into());
+    let member = Account::generate();
+    let forger = Account::generate();
+    let contact = (member.account_ref(), "127.0.0.1:10001".to_string());
+
+    // suspect a member
+    node.apply(vec![sign(
+        &member,
+        (contact.0, contact.1.clone(), MemberState::Alive.into(), 0),
+    )]);
+    node.apply(vec![sign(
+        &forger,
+        (contact.0, contact.1.clone(), MemberState::Suspect.into(), 0),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Suspect));
+
+    // ignore the refutation which is not signed by the member itself
+    node.apply(vec![sign(
+        &forger,
+        (contact.0, contact.1.clone(), MemberState::Alive.into(), 1),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Suspect));
+
+    // accept the refutation of the member itself
+    node.apply(vec![sign(
+        &member,
+        (contact.0, contact.1, MemberState::Alive.into(), 1),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Alive));
+}
+
+#[tokio::test]
+async fn test_reject_forged_incarnation() {
+    let node = Membership::new(Arc::new(Account::generate()), "127.0.0.1:10000".into());
+    let member = Account::generate();
+    let forger = Account::generate();
+    let contact = (member.account_ref(), "127.0.0.1:10001".to_string());
+
+    node.apply(vec![sign(
+        &member,
+        (contact.0, contact.1.clone(), MemberState::Alive.into(), 0),
+    )]);
+
+    // ignore the suspicion which is far beyond the known incarnation
+    node.apply(vec![sign(
+        &forger,
+        (
+            contact.0,
+            contact.1.clone(),
+            MemberState::Dead.into(),
+            u64::MAX,
+        ),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Alive));
+
+    // keep the incarnation of this node
+    let (me, address) = node.me().clone();
+    node.apply(vec![sign(
+        &forger,
+        (me, address, MemberState::Suspect.into(), u64::MAX),
+    )]);
+    assert_eq!(node.incarnation(), 0);
+
+    // let the member refute the suspicion of the next incarnation
+    node.apply(vec![sign(
+        &forger,
+        (contact.0, contact.1.clone(), MemberState::Dead.into(), 1),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Dead));
+    node.apply(vec![sign(
+        &member,
+        (contact.0, contact.1, MemberState::Alive.into(), 2),
+    )]);
+    assert_eq!(node.state_of(&contact.0), Some(MemberState::Alive));
+}
+
+fn sign(account: &Account, update: (AccountRef, String, u8, u64)) -> SignedUpdate {
+    Metadata::builder()
+        .build(account, account.account_ref(), update)
+        .unwrap()
+}
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use ipiis_api::{
+    client::IpiisClient,
+    common::{handle_external_call, Ipiis, ServerResult},
+    server::IpiisServer,
+};
+use ipiis_modules_swim_common::{
+    membership::{Contact, Membership, MembershipEvent, PROTOCOL_PERIOD},
+    IpiisSwimRpc,
+};
+use ipis::{
+    async_trait::async_trait,
+    core::{account::AccountRef, anyhow::Result},
+    env::{infer, Infer},
+    log::{info, warn},
+    tokio::{self, sync::broadcast::error::TryRecvError},
+};
+
+pub struct IpiisSwimServer {
+    client: Arc<IpiisServer>,
+    membership: Membership,
+}
+
+impl ::core::ops::Deref for IpiisSwimServer {
+    type Target = IpiisServer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl AsRef<IpiisClient> for IpiisSwimServer {
+    fn as_ref(&self) -> &IpiisClient {
+        (*self.client).as_ref()
+    }
+}
+
+impl AsRef<IpiisServer> for IpiisSwimServer {
+    fn as_ref(&self) -> &IpiisServer {
+        &self.client
+    }
+}
+
+#[async_trait]
+impl<'a> Infer<'a> for IpiisSwimServer {
+    type GenesisArgs = <IpiisServer as Infer<'a>>::GenesisArgs;
+    type GenesisResult = Self;
+
+    async fn try_infer() -> Result<Self> {
+        Ok(Self::new(IpiisServer::try_infer().await?.into()))
+    }
+
+    async fn genesis(
+        args: <Self as Infer<'a>>::GenesisArgs,
+    ) -> Result<<Self as Infer<'a>>::GenesisResult> {
+        Ok(Self::new(IpiisServer::genesis(args).await?.into()))
+    }
+}
+
+handle_external_call!(
+    server: IpiisSwimServer => IpiisSwimServer,
+    request: ::ipiis_modules_swim_common::io => {
+        SignedPing => handle_signed_ping,
+        SignedPingReq => handle_signed_ping_req,
+    },
+);
+
+impl IpiisSwimServer {
+    pub fn new(client: Arc<IpiisServer>) -> Self {
+        // the address of this node, which is announced to the members
+        let address = infer("ipiis_server_public_address").unwrap_or_default();
+
+        Self {
+            membership: Membership::new(client.book().account_me.clone(), address),
+            client,
+        }
+    }
+
+    pub fn membership(&self) -> &Membership {
+        &self.membership
+    }
+
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let runtime: &IpiisServer = &self.client;
+
+        // register services
+        let mut router = runtime.router();
+        IpiisServer::register_service::<IpiisClient>(self.client.clone(), &mut router)?;
+        Self::register_service::<IpiisClient>(self.clone(), &mut router)?;
+
+        runtime.run(Arc::new(router)).await
+    }
+
+    /// Join the members through the bootstrap node, and then probe them periodically,
+    /// marking the dead ones in the address book, except for the primaries.
+    pub async fn maintain(&self, bootstrap: Option<Contact>) -> Result<()> {
+        let mut events = self.membership.subscribe();
+        if let Some(bootstrap) = bootstrap {
+            self.membership.join(bootstrap);
+        }
+
+        let shutdown = self.client.shutdown_handle();
+        loop {
+            if let Err(e) = self.membership.tick(&self.rpc()).await {
+                warn!("failed to probe the members: {e}");
+            }
+
+            // reflect the changes
+            loop {
+                match events.try_recv() {
+                    Ok(event) => self.on_event(event),
+                    Err(TryRecvError::Lagged(skipped)) => {
+                        warn!("skipped {skipped} membership events");
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(PROTOCOL_PERIOD) => continue,
+                _ = shutdown.wait() => break Ok(()),
+            }
+        }
+    }
+
+    fn on_event(&self, event: MembershipEvent) {
+        let book = self.client.book();
+
+        match event {
+            MembershipEvent::Joined((account, address)) => {
+                info!("joined: {account} ({address})");
+                book.mark_alive(&account);
+            }
+            MembershipEvent::Suspected(account) => info!("suspected: {account}"),
+            MembershipEvent::Recovered(account) => {
+                info!("recovered: {account}");
+                book.mark_alive(&account);
+            }
+            MembershipEvent::Died(account) => {
+                warn!("dead: {account}");
+
+                // never skip the configured primaries, which should be retried anyway
+                match book.get_primaries(None) {
+                    Ok(primaries) if !primaries.contains(&account) => book.mark_dead(&account),
+                    Ok(_) => warn!("kept the primary reachable: {account}"),
+                    Err(e) => warn!("failed to get the primaries: {e}"),
+                }
+            }
+        }
+    }
+
+    fn rpc(&self) -> IpiisSwimRpc<'_, IpiisClient> {
+        IpiisSwimRpc {
+            client: self.as_ref(),
+        }
+    }
+
+    async fn handle_signed_ping(
+        client: &Self,
+        req: ::ipiis_modules_swim_common::io::request::SignedPing<'static>,
+    ) -> Result<::ipiis_modules_swim_common::io::response::SignedPing<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let updates = req.updates.into_owned().await?;
+
+        // handle data
+        let updates = client.membership.on_ping(updates)?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_swim_common::io::response::SignedPing {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            updates: ::ipis::stream::DynStream::Owned(updates),
+        })
+    }
+
+    async fn handle_signed_ping_req(
+        client: &Self,
+        req: ::ipiis_modules_swim_common::io::request::SignedPingReq<'static>,
+    ) -> Result<::ipiis_modules_swim_common::io::response::SignedPingReq<'static>> {
+        // unpack sign
+        let sign_as_guarantee = req.__sign.into_owned().await?;
+
+        // unpack data
+        let target = sign_as_guarantee.data.data.clone();
+        let updates = req.updates.into_owned().await?;
+
+        // handle data
+        let (acked, updates) = client
+            .membership
+            .on_ping_req(&client.rpc(), &target, updates)
+            .await?;
+
+        // sign data
+        let sign = client.sign_as_guarantor(sign_as_guarantee)?;
+
+        // pack data
+        Ok(::ipiis_modules_swim_common::io::response::SignedPingReq {
+            __lifetime: Default::default(),
+            __sign: ::ipis::stream::DynStream::Owned(sign),
+            acked: ::ipis::stream::DynStream::Owned(acked),
+            updates: ::ipis::stream::DynStream::Owned(updates),
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // init logger
+    ::ipis::logger::init_once();
+
+    let server = Arc::new(IpiisSwimServer::infer().await);
+
+    // join the members
+    let account: Result<AccountRef> = infer("ipiis_swim_bootstrap_account");
+    let address: Result<String> = infer("ipiis_swim_bootstrap_address");
+    let bootstrap = account.and_then(|account| Ok((account, address?))).ok();
+    {
+        let server = server.clone();
+        tokio::spawn(async move { server.maintain(bootstrap).await });
+    }
+
+    server.run().await
+}
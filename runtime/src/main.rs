@@ -1,9 +1,79 @@
 use std::sync::Arc;
 
-use ipiis_api::server::IpiisServer;
-use ipis::{env::Infer, tokio};
+use ipiis_api::{client::IpiisClient, common::Ipiis, server::IpiisServer};
+use ipis::{
+    core::{account::AccountRef, anyhow::Result},
+    env::{infer, Infer},
+    log::{info, warn},
+    tokio,
+};
 
 #[tokio::main]
-async fn main() {
-    Arc::new(IpiisServer::infer().await).run_ipiis().await
+async fn main() -> Result<()> {
+    let mut server = IpiisServer::infer().await;
+
+    // relay the incoming streams to the peers which cannot be dialed directly
+    let relay: Result<bool> = infer("ipiis_relay");
+    server.set_relay(relay.unwrap_or_default());
+
+    let server = Arc::new(server);
+
+    // stop the server gracefully on SIGTERM/SIGINT
+    let shutdown = server.shutdown_handle();
+    tokio::spawn(async move {
+        match wait_for_signal().await {
+            Ok(signal) => {
+                info!("received {signal}: shutting down");
+                shutdown.shutdown();
+            }
+            Err(e) => warn!("failed to listen for the shutdown signals: {e}"),
+        }
+    });
+
+    // keep the primary informed of the public address
+    let address: Result<<IpiisClient as Ipiis>::Address> = infer("ipiis_server_public_address");
+    if let Ok(address) = address.as_ref() {
+        tokio::spawn(server.clone().run_heartbeat(None, address.clone()));
+    }
+
+    // announce this node to the local network
+    let discovery: Result<bool> = infer("ipiis_discovery");
+    if discovery.unwrap_or_default() {
+        tokio::spawn(server.clone().run_discovery(address.ok(), vec![]));
+    }
+
+    // keep the directory consistent with the other primaries
+    tokio::spawn(server.clone().run_replication());
+
+    // register services
+    let mut router = server.router();
+    IpiisServer::register_service::<IpiisClient>(server.clone(), &mut router)?;
+    let router = Arc::new(router);
+
+    // be dialed through the relay, e.g. behind NAT
+    let relay: Result<AccountRef> = infer("ipiis_relay_account");
+    if let Ok(relay) = relay {
+        tokio::spawn(server.clone().run_relayed(router.clone(), None, relay));
+    }
+
+    server.run(router).await
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = sigterm.recv() => Ok("SIGTERM"),
+        _ = sigint.recv() => Ok("SIGINT"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> Result<&'static str> {
+    tokio::signal::ctrl_c().await?;
+    Ok("Ctrl-C")
 }